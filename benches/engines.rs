@@ -0,0 +1,58 @@
+// Compares the tree walking engine with the bytecode VM, and the JIT with the
+// `jit` feature.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use bibim::{
+    env::{Engine, Env},
+    parse, run_bowl,
+};
+
+// sums the multiples of 3 or 5 below 2000, with cells at fractional numbers
+const MULTIPLES: &str = r"{
+    [1; @:2 = 10]
+    [2; @:3/11 = 0]
+    [3; @:4/11 = 0]
+    [@:2 + 0; {
+        [1; @:4/11 = @:4/11 + @:3/11]
+    }:((^((@:3/11)/3) ?= 1) | (^((@:3/11)/5) ?= 1))]
+    [@:2 + 1; @:3/11 = @:3/11 + 1]
+    [@:2 + 2; {
+        [0; @:2 = 0 - 10]
+        [1; @:2 = @:0 + 1]
+    }:(@:3/11 < 2000)]
+}";
+
+// fills a bowl with the squares below 1000, then reads them back
+const SQUARES: &str = r"{
+    [1; @:2 = 10]
+    [2; @:3 = 0]
+    [3; @:4 = {}]
+    [4; @:5 = 0]
+    [@:2 + 0; @:4:(@:3) = @:3 * @:3]
+    [@:2 + 1; @:5 = @:5 + @:4:(@:3)]
+    [@:2 + 2; @:3 = @:3 + 1]
+    [@:2 + 3; {
+        [0; @:2 = 0 - 10]
+        [1; @:2 = @:0 + 1]
+    }:(@:3 < 1000)]
+}";
+
+fn run_with(code: &str, engine: Engine) {
+    let mut env = Env::new(Vec::new, |_| {}).with_engine(engine);
+    run_bowl(parse(code).unwrap(), &mut env).unwrap();
+}
+
+fn engines(c: &mut Criterion) {
+    for (name, code) in [("multiples", MULTIPLES), ("squares", SQUARES)] {
+        let mut group = c.benchmark_group(name);
+        group.bench_function("tree", |b| b.iter(|| run_with(code, Engine::Tree)));
+        group.bench_function("vm", |b| b.iter(|| run_with(code, Engine::Vm)));
+        #[cfg(feature = "jit")]
+        group.bench_function("jit", |b| b.iter(|| run_with(code, Engine::Jit)));
+        group.finish();
+    }
+}
+
+criterion_group!(benches, engines);
+criterion_main!(benches);
@@ -0,0 +1,93 @@
+// Measures number arithmetic, alone and in the fizzbuzz and Euler programs of
+// the tests, whose counters and cells stay in machine integers.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use bibim::{datatype::Number, env::Env, parse, run_bowl};
+
+const FIZZBUZZ: &str = r"{
+    [1/2; @:1/3 = {[0; 102][1; 105][2; 122][3; 122]}]
+    [2/3; @:2/3 = {[0; 98][1; 117][2; 122][3; 122]}]
+    [3/4; @:2 = 1]
+    [4/5; @:1/2 = 1]
+    [@:2; {
+        [1; @:1 = @:1/3]
+    }:(^((@:1/2)/3) ?= 1)]
+    [@:2 + 1; {
+        [1; @:1 = @:2/3]
+    }:(^((@:1/2)/5) ?= 1)]
+    [@:2 + 2; @:1/2 = @:1/2 + 1]
+    [@:2 + 3; @:1 = {[0; 10]}]
+    [@:2 + 4; {
+        [1; @:2 = @:0 + 1]
+    }:(@:1/2 < 100 + 1)]
+}";
+
+// the loop of Euler problem 1, summing the multiples of 3 or 5 below 1000
+const EULER_1: &str = r"{
+    [1/2; @:2 = 1]
+    [2/3; @:3/11 = 1]
+    [3/4; @:4/11 = 0]
+    [@:2 + 0; {
+        [1; @:4/11 = @:4/11 + @:3/11]
+    }:((^((@:3/11)/3) ?= 1) | (^((@:3/11)/5) ?= 1))]
+    [@:2 + 1; @:3/11 = @:3/11 + 1]
+    [@:2 + 2; {
+        [0; @:3 = @:0 + 1]
+        [1; @:2 = @:0 + 1]
+    }:(@:3/11 < 1000)]
+}";
+
+// the loop of Euler problem 3, factoring 600851475143
+const EULER_3: &str = r"{
+    [1/2; @:2 = 1]
+    [2/3; @:2/11 = 600851475143]
+    [3/4; @:3/11 = 2]
+    [@:2 + 0; {
+        [0; {
+            [0; @:3/11 = @:3/11 + 2]
+            [1; @:3/11 = @:3/11 + 1]
+        }:(@:3/11 ?= 2)]
+        [1; @:2/11 = (@:2/11)/(@:3/11)]
+    }:(^((@:2/11)/(@:3/11)) ?= 1)]
+    [@:2 + 1; {
+        [0; @:3 = @:0 + 1]
+        [1; @:2 = @:0 + 1]
+    }:(@:2/11 > @:3/11)]
+}";
+
+fn run_program(code: &str) {
+    let mut env = Env::new(Vec::new, |_| {});
+    run_bowl(parse(code).unwrap(), &mut env).unwrap();
+}
+
+fn arithmetic(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arithmetic");
+    let (a, b): (Number, Number) = ("3/7".parse().unwrap(), "-5/11".parse().unwrap());
+    group.bench_function("small", |bench| {
+        bench.iter(|| (&(black_box(&a) + black_box(&b)) * &b) / &a)
+    });
+    let (a, b): (Number, Number) = (
+        "123456789012345678901234567890/7".parse().unwrap(),
+        "-5/98765432109876543210987654321".parse().unwrap(),
+    );
+    group.bench_function("big", |bench| {
+        bench.iter(|| (&(black_box(&a) + black_box(&b)) * &b) / &a)
+    });
+    group.finish();
+}
+
+fn programs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("programs");
+    for (name, code) in [
+        ("fizzbuzz", FIZZBUZZ),
+        ("euler_1", EULER_1),
+        ("euler_3", EULER_3),
+    ] {
+        group.bench_function(name, |b| b.iter(|| run_program(code)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, arithmetic, programs);
+criterion_main!(benches);
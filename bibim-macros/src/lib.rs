@@ -0,0 +1,206 @@
+// `bibim!` parses Bibim code at compile time and expands to an expression
+// building its `bibim::datatype::Bowl`, ready for `bibim::run_bowl`. The code
+// is given either as a string literal or directly as tokens:
+//
+//     let bowl = bibim!("{[0; @:1 = {[0; 72][1; 105]}]}");
+//     let bowl = bibim! { {[0; @:1 = {[0; 72][1; 105]}]} };
+//
+// Syntax errors are reported as compile errors. Comments (`~# ... #~`) are only
+// available with a string literal, since they are not valid Rust tokens.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::LitStr;
+
+use bibim::datatype::{Bowl, Expr, Number, Value};
+use num_bigint::Sign;
+
+#[proc_macro]
+pub fn bibim(input: TokenStream) -> TokenStream {
+    let (code, span) = match syn::parse::<LitStr>(input.clone()) {
+        Ok(literal) => (literal.value(), literal.span()),
+        Err(_) => (input.to_string(), Span::call_site()),
+    };
+    expand(&code, span).into()
+}
+
+fn expand(code: &str, span: Span) -> TokenStream2 {
+    match bibim::parse(code) {
+        Ok(bowl) => bowl_tokens(&bowl),
+        Err(errs) if errs.is_empty() => {
+            syn::Error::new(span, "invalid Bibim code").to_compile_error()
+        }
+        Err(errs) => {
+            let errors = errs
+                .iter()
+                .map(|e| syn::Error::new(span, &e.message).to_compile_error());
+            quote! { { #(#errors)* } }
+        }
+    }
+}
+
+fn bowl_tokens(bowl: &Bowl) -> TokenStream2 {
+    let noodles = bowl.noodles.iter().map(|noodle| {
+        let nn_expr = expr_tokens(&noodle.nn_expr);
+        let expr = expr_tokens(&noodle.expr);
+        let line = match noodle.line {
+            Some(line) => quote! { ::std::option::Option::Some(#line) },
+            None => quote! { ::std::option::Option::None },
+        };
+        quote! {
+            ::bibim::datatype::Noodle {
+                nn_expr: #nn_expr,
+                expr: #expr,
+                line: #line,
+            }
+        }
+    });
+    quote! {
+        ::bibim::datatype::Bowl {
+            noodles: ::std::vec![#(#noodles),*],
+        }
+    }
+}
+
+fn value_tokens(value: &Value) -> TokenStream2 {
+    match value {
+        Value::Number(number) => {
+            let number = number_tokens(number);
+            quote! {
+                ::bibim::datatype::Value::Number(::std::boxed::Box::new(#number))
+            }
+        }
+        Value::Bowl(bowl) => {
+            let bowl = bowl_tokens(&bowl.borrow());
+            quote! { ::bibim::datatype::Value::from_bowl(#bowl) }
+        }
+        Value::Null => quote! { ::bibim::datatype::Value::Null },
+    }
+}
+
+// Numbers are built from their terms as constants, with no parsing left for
+// run time.
+fn number_tokens(number: &Number) -> TokenStream2 {
+    let (numerator, denominator) = (number.numerator(), number.denominator());
+    if let (Ok(numerator), Ok(denominator)) =
+        (i64::try_from(&numerator), u64::try_from(&denominator))
+    {
+        return quote! { ::bibim::datatype::Number::ratio(#numerator, #denominator) };
+    }
+    let is_negative = numerator.sign() == Sign::Minus;
+    let numerator = numerator.magnitude().to_u32_digits();
+    let denominator = denominator.to_u32_digits();
+    quote! {
+        ::bibim::datatype::Number::from_digits(
+            #is_negative,
+            &[#(#numerator),*],
+            &[#(#denominator),*],
+        )
+        .unwrap()
+    }
+}
+
+fn expr_tokens(expr: &Expr) -> TokenStream2 {
+    let boxed = |expr: &Expr| {
+        let expr = expr_tokens(expr);
+        quote! { ::std::boxed::Box::new(#expr) }
+    };
+    match expr {
+        Expr::ValueExpr(value) => {
+            let value = value_tokens(value);
+            quote! { ::bibim::datatype::Expr::ValueExpr(#value) }
+        }
+        Expr::BowlReadExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::BowlReadExpr(#a, #b) }
+        }
+        Expr::MemReadExpr(expr) => {
+            let a = boxed(expr);
+            quote! { ::bibim::datatype::Expr::MemReadExpr(#a) }
+        }
+        Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+            let (a, b, c) = (boxed(expr1), boxed(expr2), boxed(expr3));
+            quote! { ::bibim::datatype::Expr::BowlWriteExpr(#a, #b, #c) }
+        }
+        Expr::MemWriteExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::MemWriteExpr(#a, #b) }
+        }
+        Expr::DenoFuncExpr(expr) => {
+            let a = boxed(expr);
+            quote! { ::bibim::datatype::Expr::DenoFuncExpr(#a) }
+        }
+        Expr::NotFuncExpr(expr) => {
+            let a = boxed(expr);
+            quote! { ::bibim::datatype::Expr::NotFuncExpr(#a) }
+        }
+        Expr::PlusFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::PlusFuncExpr(#a, #b) }
+        }
+        Expr::MinusFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::MinusFuncExpr(#a, #b) }
+        }
+        Expr::MulFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::MulFuncExpr(#a, #b) }
+        }
+        Expr::NumberSepFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::NumberSepFuncExpr(#a, #b) }
+        }
+        Expr::AndFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::AndFuncExpr(#a, #b) }
+        }
+        Expr::OrFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::OrFuncExpr(#a, #b) }
+        }
+        Expr::EqFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::EqFuncExpr(#a, #b) }
+        }
+        Expr::GtFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::GtFuncExpr(#a, #b) }
+        }
+        Expr::LtFuncExpr(expr1, expr2) => {
+            let (a, b) = (boxed(expr1), boxed(expr2));
+            quote! { ::bibim::datatype::Expr::LtFuncExpr(#a, #b) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_bowl() {
+        let tokens = expand("{[0; @:1 = {[0; 72]}]}", Span::call_site()).to_string();
+
+        assert!(tokens.contains("MemWriteExpr"));
+        assert!(tokens.contains("from_bowl"));
+        assert!(tokens.contains("ratio (72i64 , 1u64)"));
+        assert!(!tokens.contains("compile_error"));
+    }
+
+    #[test]
+    fn expand_tokens_as_code() {
+        // token streams print with spaces between tokens, which Bibim ignores
+        let code = "{ [0 ; @ : 1 ? = 2] }";
+        let tokens = expand(code, Span::call_site()).to_string();
+
+        assert!(tokens.contains("EqFuncExpr"));
+    }
+
+    #[test]
+    fn expand_syntax_error() {
+        let tokens = expand("{[0; @:1 = ]}", Span::call_site()).to_string();
+
+        assert!(tokens.contains("compile_error"));
+    }
+}
@@ -1,6 +1,6 @@
 %expect-unused "ASSIGN_OP"
 %start BowlP
-%avoid_insert "NUMBER"
+%avoid_insert "NUMBER" "HEX_NUMBER" "BIN_NUMBER" "DEC_NUMBER"
 %token ASSIGN_OP
 %left ASSIGN_OP 
 %left AND OR
@@ -17,7 +17,7 @@
 %left NOODLE_OPEN NOODLE_CLOSE
 %left EXPR_OPEN EXPR_CLOSE
 %left MEM
-%left NUMBER
+%left NUMBER HEX_NUMBER BIN_NUMBER DEC_NUMBER
 %%
 
 BowlP -> Result<Bowl, ()>:
@@ -29,10 +29,25 @@ BowlP -> Result<Bowl, ()>:
     }) }
     ;
 
-Number -> Result<BigInt, ()>:
+Number -> Result<Number, ()>:
     'NUMBER' {
         let v = $1.map_err(|_| ())?;
-        parse_bigint(remove_whitespace($lexer.span_str(v.span())).as_str())
+        parse_bigint(remove_whitespace($lexer.span_str(v.span())).as_str(), 10)
+            .map(|n| Number::new(n, BigUint::from(1u32)).unwrap())
+    }
+    | 'HEX_NUMBER' {
+        let v = $1.map_err(|_| ())?;
+        parse_bigint(&$lexer.span_str(v.span())[2..], 16)
+            .map(|n| Number::new(n, BigUint::from(1u32)).unwrap())
+    }
+    | 'BIN_NUMBER' {
+        let v = $1.map_err(|_| ())?;
+        parse_bigint(&$lexer.span_str(v.span())[2..], 2)
+            .map(|n| Number::new(n, BigUint::from(1u32)).unwrap())
+    }
+    | 'DEC_NUMBER' {
+        let v = $1.map_err(|_| ())?;
+        parse_decimal($lexer.span_str(v.span()))
     }
     ;
 
@@ -40,6 +55,7 @@ NoodleP -> Result<Noodle, ()>:
     'NOODLE_OPEN' ExprP 'NOODLE_SEP' ExprP 'NOODLE_CLOSE' { Ok(Noodle {
         nn_expr: $2?,
         expr: $4?,
+        line: Some($lexer.line_col($span).0.0),
     }) }
     ;
 
@@ -53,7 +69,8 @@ BowlContents -> Result<Vec<Noodle>, ()>:
     ;
 
 ExprP -> Result<Expr, ()>:
-    Number { Ok(Expr::ValueExpr(Value::from_number(&Number::new($1?, BigUint::from(1u32)).unwrap()))) }
+    Number { Ok(Expr::ValueExpr(Value::from_number(&$1?))) }
+    | 'MINUS' Number { Ok(Expr::ValueExpr(Value::from_number(&-$2?))) }
     | BowlP { Ok(Expr::ValueExpr(Value::from_bowl($1?))) }
     | ExprP 'BOWL' ExprP { Ok(Expr::BowlReadExpr(Box::new($1?), Box::new($3?))) }
     | 'MEM' 'BOWL' ExprP { Ok(Expr::MemReadExpr(Box::new($3?))) }
@@ -77,8 +94,8 @@ ExprP -> Result<Expr, ()>:
 use num_bigint::{BigInt, BigUint};
 use crate::datatype::{Bowl, Expr, Noodle, Value, Number};
 
-fn parse_bigint(s: &str) -> Result<BigInt, ()> {
-    match BigInt::parse_bytes(s.as_bytes(), 10) {
+fn parse_bigint(s: &str, radix: u32) -> Result<BigInt, ()> {
+    match BigInt::parse_bytes(s.as_bytes(), radix) {
         Some(val) => Ok(val),
         None => {
             eprintln!("{} cannot be represented as a number", s);
@@ -87,6 +104,14 @@ fn parse_bigint(s: &str) -> Result<BigInt, ()> {
     }
 }
 
+// `12.25` as 1225/100
+fn parse_decimal(s: &str) -> Result<Number, ()> {
+    let (int, frac) = s.split_once('.').ok_or(())?;
+    let numerator = parse_bigint(&format!("{}{}", int, frac), 10)?;
+    let denominator = BigUint::from(10u32).pow(frac.len() as u32);
+    Number::new(numerator, denominator).map_err(|_| ())
+}
+
 fn remove_whitespace(s: &str) -> String {
     s.chars().filter(|c| !c.is_whitespace()).collect()
 }
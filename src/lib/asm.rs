@@ -0,0 +1,492 @@
+// Assembler for `.bibima` sources. Each line is one of
+//
+//     cell name               allocate a memory cell
+//     cell name at 2/5        pin a memory cell to a given address
+//     name:                   label the next instruction
+//     goto name | +2 | -1     jump to a label or relative to this instruction
+//     if <expr> goto name     jump when the expression is 1
+//     jump <expr>             jump to a computed instruction index
+//     halt                    stop the program
+//     <expr>                  any Bibim expression, with cell and label names
+//
+// Text after `//` is a comment. An expression continues on the next lines while
+// its brackets are open. Cell names are replaced by memory reads, label names
+// by their instruction index.
+//
+// Instructions are numbered from 0 and run in order. Instruction `i` becomes
+// the noodle `[@:pc + i; ...]`, where `pc` is a cell set to 1 by a first
+// `[0; ...]` noodle. Jumping to `j` moves `pc` to `@:0 + (1 - j)`, so that `j`
+// is the smallest noodle number above the cursor.
+
+use std::{collections::HashMap, fmt::Write};
+
+use num_bigint::{BigInt, BigUint};
+
+use crate::{
+    datatype::Number, env::Extensions, error::AsmError, lint::const_expr, parse_with, syntax,
+};
+
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    pub code: String,
+    // source line of each line of `code`, both 1-based
+    pub source_map: Vec<Option<usize>>,
+}
+
+impl Assembly {
+    pub fn source_line(&self, line: usize) -> Option<usize> {
+        self.source_map.get(line.checked_sub(1)?).copied().flatten()
+    }
+
+    pub fn source_map_json(&self, source_file: &str) -> String {
+        serde_json::json!({
+            "source": source_file,
+            "lines": self.source_map,
+        })
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Label(String),
+    Relative(i64),
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Expr(String),
+    Goto(Target),
+    If(String, Target),
+    Jump(String),
+    Halt,
+}
+
+#[derive(Debug, Clone)]
+struct Instruction {
+    op: Op,
+    line: usize,
+    // source line of every line of the instruction text
+    lines: Vec<usize>,
+}
+
+struct Assembler {
+    cells: HashMap<String, String>,
+    labels: HashMap<String, usize>,
+    instructions: Vec<Instruction>,
+    // labels placed before each instruction, used as comments in the output
+    label_names: Vec<Vec<String>>,
+    pending_labels: Vec<String>,
+    label_lines: Vec<(String, usize)>,
+    extensions: Extensions,
+}
+
+pub fn assemble(source: &str, extensions: &Extensions) -> Result<Assembly, AsmError> {
+    let mut assembler = Assembler {
+        cells: HashMap::new(),
+        labels: HashMap::new(),
+        instructions: vec![],
+        label_names: vec![],
+        pending_labels: vec![],
+        label_lines: vec![],
+        extensions: *extensions,
+    };
+    let mut pinned: Vec<(String, String, usize)> = vec![];
+    let mut allocated: Vec<String> = vec![];
+
+    let lines: Vec<&str> = source.lines().collect();
+    let mut index = 0;
+    while index < lines.len() {
+        let line_no = index + 1;
+        let text = strip_comment(lines[index]).trim();
+        index += 1;
+        if text.is_empty() {
+            continue;
+        }
+        let mut words = text.split_whitespace();
+        let first = words.next().unwrap_or("");
+        if first == "cell" {
+            let name = words
+                .next()
+                .ok_or_else(|| error(line_no, "expected a cell name"))?;
+            check_name(name, line_no)?;
+            if allocated.iter().any(|other| other == name)
+                || pinned.iter().any(|(other, _, _)| other == name)
+            {
+                return Err(error(
+                    line_no,
+                    &format!("cell `{}` is declared twice", name),
+                ));
+            }
+            match words.next() {
+                None => allocated.push(name.to_string()),
+                Some("at") => {
+                    let address = text
+                        .split_once(" at ")
+                        .map(|(_, address)| address.trim())
+                        .unwrap_or("");
+                    if const_expr(address, extensions).is_none() {
+                        return Err(error(
+                            line_no,
+                            &format!("`{}` is not a constant address", address),
+                        ));
+                    }
+                    pinned.push((name.to_string(), address.to_string(), line_no));
+                }
+                Some(word) => return Err(error(line_no, &format!("unexpected `{}`", word))),
+            }
+            continue;
+        }
+        if let Some(name) = text.strip_suffix(':') {
+            if is_name(name) {
+                if assembler.labels.contains_key(name) {
+                    return Err(error(
+                        line_no,
+                        &format!("label `{}` is defined twice", name),
+                    ));
+                }
+                assembler
+                    .labels
+                    .insert(name.to_string(), assembler.instructions.len());
+                assembler.pending_labels.push(name.to_string());
+                assembler.label_lines.push((name.to_string(), line_no));
+                continue;
+            }
+        }
+        let op = match first {
+            "halt" => {
+                if words.next().is_some() {
+                    return Err(error(line_no, "`halt` takes no operand"));
+                }
+                Op::Halt
+            }
+            "goto" => Op::Goto(parse_target(text["goto".len()..].trim(), line_no)?),
+            "jump" => Op::Jump(text["jump".len()..].trim().to_string()),
+            "if" => {
+                let rest = &text["if".len()..];
+                match rest.rfind(" goto ") {
+                    Some(at) => Op::If(
+                        rest[..at].trim().to_string(),
+                        parse_target(rest[at + " goto ".len()..].trim(), line_no)?,
+                    ),
+                    None => return Err(error(line_no, "expected `if <expr> goto <label>`")),
+                }
+            }
+            _ => {
+                // an expression runs until its brackets are closed
+                let mut expr = text.to_string();
+                let mut expr_lines = vec![line_no];
+                while depth(&expr) > 0 && index < lines.len() {
+                    expr.push('\n');
+                    expr.push_str(strip_comment(lines[index]).trim());
+                    expr_lines.push(index + 1);
+                    index += 1;
+                }
+                assembler.push(Op::Expr(expr), line_no, expr_lines);
+                continue;
+            }
+        };
+        assembler.push(op, line_no, vec![line_no]);
+    }
+
+    // address 2 holds the instruction counter, named cells follow
+    let pc = 2;
+    let mut taken = vec![];
+    for (name, address, line_no) in pinned {
+        let number = const_expr(&address, extensions).unwrap();
+        if number == integer(pc) {
+            return Err(error(
+                line_no,
+                &format!("address {} is reserved for the instruction counter", pc),
+            ));
+        }
+        taken.push(number);
+        assembler.cells.insert(name, format!("({})", address));
+    }
+    let mut next = pc + 1;
+    for name in allocated {
+        while taken.iter().any(|number| *number == integer(next)) {
+            next += 1;
+        }
+        assembler.cells.insert(name, next.to_string());
+        next += 1;
+    }
+    for (name, line_no) in &assembler.label_lines {
+        if assembler.cells.contains_key(name) {
+            return Err(error(
+                *line_no,
+                &format!("`{}` is both a cell and a label", name),
+            ));
+        }
+    }
+    assembler.emit(&pc.to_string())
+}
+
+impl Assembler {
+    fn push(&mut self, op: Op, line: usize, lines: Vec<usize>) {
+        self.instructions.push(Instruction { op, line, lines });
+        self.label_names
+            .push(std::mem::take(&mut self.pending_labels));
+    }
+
+    fn target(&self, target: &Target, from: usize, line: usize) -> Result<i64, AsmError> {
+        match target {
+            Target::Label(name) => match self.labels.get(name) {
+                Some(index) => Ok(*index as i64),
+                None => Err(error(line, &format!("unknown label `{}`", name))),
+            },
+            Target::Relative(offset) => Ok(from as i64 + offset),
+        }
+    }
+
+    // Replaces cell names by memory reads and label names by their index.
+    fn expr(&self, expr: &str, line: usize) -> Result<String, AsmError> {
+        let mut result = String::new();
+        let mut chars = expr.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if c == '~' {
+                if let Some(end) = syntax::comment_end(expr, start) {
+                    result.push_str(&expr[start..end]);
+                    while chars.peek().is_some_and(|(i, _)| *i < end) {
+                        chars.next();
+                    }
+                    continue;
+                }
+            }
+            if c.is_ascii_alphabetic() || c == '_' {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || *c == '_' {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let name = &expr[start..end];
+                if let Some(address) = self.cells.get(name) {
+                    write!(result, "@:{}", address).unwrap();
+                } else if let Some(index) = self.labels.get(name) {
+                    write!(result, "{}", index).unwrap();
+                } else {
+                    return Err(error(line, &format!("unknown name `{}`", name)));
+                }
+                continue;
+            }
+            result.push(c);
+        }
+        Ok(result)
+    }
+
+    fn emit(&self, pc: &str) -> Result<Assembly, AsmError> {
+        let mut code = String::new();
+        let mut source_map = vec![];
+        let mut emit_line = |code: &mut String, text: &str, line: Option<usize>| {
+            code.push_str(text);
+            code.push('\n');
+            source_map.push(line);
+        };
+        emit_line(&mut code, "{", None);
+        emit_line(&mut code, &format!("    [0; @:{} = 1]", pc), None);
+        let count = self.instructions.len() as i64;
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            for name in &self.label_names[i] {
+                emit_line(&mut code, &format!("    ~# {} #~", name), None);
+            }
+            let line = instruction.line;
+            let body = match &instruction.op {
+                Op::Expr(expr) => self.expr(expr, line)?,
+                Op::Goto(target) => {
+                    let j = self.target(target, i, line)?;
+                    format!("@:{} = {}", pc, jump_to(j))
+                }
+                Op::If(cond, target) => {
+                    let j = self.target(target, i, line)?;
+                    format!(
+                        "@:{} = {{[0; @:{}][1; {}]}}:!!({})",
+                        pc,
+                        pc,
+                        jump_to(j),
+                        self.expr(cond, line)?
+                    )
+                }
+                Op::Jump(expr) => format!("@:{} = @:0 + (1 - ({}))", pc, self.expr(expr, line)?),
+                Op::Halt => format!("@:{} = @:0 - {}", pc, count),
+            };
+            let noodle = format!("[@:{} + {}; {}]", pc, i, body);
+            for (k, text) in noodle.split('\n').enumerate() {
+                let source_line = instruction.lines.get(k).copied().unwrap_or(line);
+                emit_line(&mut code, &format!("    {}", text), Some(source_line));
+            }
+        }
+        emit_line(&mut code, "}", None);
+
+        let assembly = Assembly { code, source_map };
+        if let Err(errs) = parse_with(&assembly.code, &self.extensions) {
+            let (line, message) = match errs.first() {
+                Some(e) => (syntax::line_of(&assembly.code, e.start), e.message.clone()),
+                None => (0, "invalid expression".to_string()),
+            };
+            return Err(error(assembly.source_line(line).unwrap_or(0), &message));
+        }
+        Ok(assembly)
+    }
+}
+
+fn integer(value: i64) -> Number {
+    Number::new(BigInt::from(value), BigUint::from(1u32)).unwrap()
+}
+
+fn jump_to(j: i64) -> String {
+    let offset = 1 - j;
+    if offset < 0 {
+        format!("@:0 - {}", -offset)
+    } else {
+        format!("@:0 + {}", offset)
+    }
+}
+
+fn parse_target(text: &str, line: usize) -> Result<Target, AsmError> {
+    if let Some(offset) = text.strip_prefix('+') {
+        offset
+            .trim()
+            .parse()
+            .map(Target::Relative)
+            .map_err(|_| error(line, &format!("invalid offset `{}`", text)))
+    } else if let Some(offset) = text.strip_prefix('-') {
+        offset
+            .trim()
+            .parse::<i64>()
+            .map(|offset| Target::Relative(-offset))
+            .map_err(|_| error(line, &format!("invalid offset `{}`", text)))
+    } else if is_name(text) {
+        Ok(Target::Label(text.to_string()))
+    } else {
+        Err(error(line, &format!("invalid jump target `{}`", text)))
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find("//") {
+        Some(at) => &line[..at],
+        None => line,
+    }
+}
+
+fn depth(expr: &str) -> i64 {
+    expr.chars()
+        .map(|c| match c {
+            '(' | '{' | '[' => 1,
+            ')' | '}' | ']' => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn check_name(name: &str, line: usize) -> Result<(), AsmError> {
+    if !is_name(name) || ["cell", "goto", "if", "jump", "halt", "at"].contains(&name) {
+        return Err(error(line, &format!("invalid name `{}`", name)));
+    }
+    Ok(())
+}
+
+fn error(line: usize, message: &str) -> AsmError {
+    AsmError {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{env::Env, run};
+
+    use super::*;
+
+    fn run_asm(source: &str) -> Vec<u8> {
+        let assembly = assemble(source, &Extensions::default()).unwrap();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut env = Env::new(Vec::new, |data| output.lock().unwrap().extend(data));
+        run(assembly.code, &mut env).unwrap();
+        let output = output.lock().unwrap().clone();
+        output
+    }
+
+    #[test]
+    fn assemble_loop() {
+        let source = "cell n
+cell sum
+    n = 3
+    sum = 0
+loop:
+    if n < 1 goto done  // count down
+    sum = sum + n
+    n = n - 1
+    goto loop
+done:
+    @:1 = {
+        [0; sum + 48]
+    }
+";
+        assert_eq!(run_asm(source), "6".as_bytes());
+
+        let assembly = assemble(source, &Extensions::default()).unwrap();
+        assert!(assembly
+            .code
+            .contains("[@:2 + 2; @:2 = {[0; @:2][1; @:0 - 5]}:!!(@:3 < 1)]"));
+        let line = assembly
+            .code
+            .lines()
+            .position(|l| l.contains("@:4 + 48"))
+            .unwrap()
+            + 1;
+        assert_eq!(assembly.source_line(line), Some(12));
+    }
+
+    #[test]
+    fn assemble_relative_jumps() {
+        let source = "cell out at 9/2
+    out = 65
+    goto +2
+    out = 66
+    jump next
+next:
+    @:1 = {[0; out]}
+    halt
+    @:1 = {[0; 67]}
+";
+        assert_eq!(run_asm(source), "A".as_bytes());
+    }
+
+    #[test]
+    fn assemble_errors() {
+        let message =
+            |source: &str| format!("{}", assemble(source, &Extensions::default()).unwrap_err());
+        assert_eq!(message("goto nowhere"), "line 1: unknown label `nowhere`");
+        assert_eq!(message("cell a\n\na = b"), "line 3: unknown name `b`");
+        assert_eq!(
+            message("cell pc at 4/2"),
+            "line 1: address 2 is reserved for the instruction counter"
+        );
+        assert!(message("cell a\na = (1 +").starts_with("line 2: "));
+        assert_eq!(
+            message("cell a at 4.5"),
+            "line 1: `4.5` is not a constant address"
+        );
+
+        let extensions = Extensions {
+            extended_literals: true,
+            ..Extensions::default()
+        };
+        let assembly = assemble("cell a at 4.5\n    a = -1", &extensions).unwrap();
+        assert!(assembly.code.contains("@:(4.5) = -1"));
+    }
+}
@@ -0,0 +1,445 @@
+// A compact binary encoding of a parsed bowl, loadable without the parser.
+//
+//     file   := "BIBM" version:u8 bowl
+//     bowl   := count:varint noodle*
+//     noodle := line:varint expr expr         (line + 1, or 0 without a line)
+//     expr   := tag:u8 operand*               (tags in the order of `Expr`)
+//             | 16 integer:varint             (a small non-negative integer)
+//     value  := 0                             (null)
+//             | 1 sign:u8 numerator denominator
+//             | 2 bowl
+//             | 3 id:varint                   (a shared bowl, see `Encoder`)
+//
+// Numerators and denominators are little endian magnitudes prefixed with their
+// length in bytes, and varints are LEB128.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use num_bigint::{BigInt, BigUint, Sign};
+
+use crate::{
+    datatype::{Bowl, Expr, Noodle, Number, Value},
+    error::DecodeError,
+};
+
+const MAGIC: &[u8] = b"BIBM";
+const VERSION: u8 = 1;
+const INTEGER_TAG: u8 = 16;
+// the deepest expressions are nested, counting through bowls, so that
+// malformed data cannot overflow the stack
+const MAX_DEPTH: usize = 500;
+
+pub fn encode(bowl: &Bowl) -> Vec<u8> {
+    let mut encoder = Encoder::new(false);
+    encoder.data.extend(MAGIC);
+    encoder.data.push(VERSION);
+    encoder.bowl(bowl);
+    encoder.data
+}
+
+pub fn decode(data: &[u8]) -> Result<Bowl, DecodeError> {
+    let mut decoder = Decoder::new(data, false);
+    decoder.header(MAGIC, VERSION, "not an encoded Bibim program")?;
+    let bowl = decoder.bowl()?;
+    decoder.finish()?;
+    Ok(bowl)
+}
+
+// Whether `data` starts like an encoded program.
+pub fn is_encoded(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// Without sharing, bowl values are written inline. With sharing, they are
+// written as references, and `table` appends every referenced bowl once, so
+// that bowls shared between values, and cycles, survive a round trip.
+pub(crate) struct Encoder {
+    pub data: Vec<u8>,
+    shared: Option<Vec<Rc<RefCell<Bowl>>>>,
+    ids: HashMap<*const RefCell<Bowl>, usize>,
+}
+
+impl Encoder {
+    pub fn new(is_shared: bool) -> Encoder {
+        Encoder {
+            data: vec![],
+            shared: if is_shared { Some(vec![]) } else { None },
+            ids: HashMap::new(),
+        }
+    }
+
+    pub fn varint(&mut self, mut value: usize) {
+        while value >= 0x80 {
+            self.data.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.data.push(value as u8);
+    }
+
+    fn magnitude(&mut self, bytes: Vec<u8>) {
+        // zero is encoded without bytes
+        let bytes = if bytes == [0] { vec![] } else { bytes };
+        self.varint(bytes.len());
+        self.data.extend(bytes);
+    }
+
+    pub fn number(&mut self, number: &Number) {
+        let (sign, numerator) = number.numerator().to_bytes_le();
+        self.data.push((sign == Sign::Minus) as u8);
+        self.magnitude(numerator);
+        self.magnitude(number.denominator().to_bytes_le());
+    }
+
+    pub fn bowl(&mut self, bowl: &Bowl) {
+        self.varint(bowl.noodles.len());
+        for noodle in &bowl.noodles {
+            self.varint(noodle.line.map_or(0, |line| line + 1));
+            self.expr(&noodle.nn_expr);
+            self.expr(&noodle.expr);
+        }
+    }
+
+    pub fn value(&mut self, value: &Value) {
+        match value {
+            Value::Null => self.data.push(0),
+            Value::Number(number) => {
+                self.data.push(1);
+                self.number(number);
+            }
+            Value::Bowl(bowl) => match self.shared.as_mut() {
+                Some(bowls) => {
+                    let id = *self.ids.entry(Rc::as_ptr(bowl)).or_insert_with(|| {
+                        bowls.push(bowl.clone());
+                        bowls.len() - 1
+                    });
+                    self.data.push(3);
+                    self.varint(id);
+                }
+                None => {
+                    self.data.push(2);
+                    self.bowl(&bowl.borrow());
+                }
+            },
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let operands: Vec<&Expr> = match expr {
+            Expr::ValueExpr(value) => {
+                match small_integer(value) {
+                    Some(integer) => {
+                        self.data.push(INTEGER_TAG);
+                        self.varint(integer);
+                    }
+                    None => {
+                        self.data.push(0);
+                        self.value(value);
+                    }
+                }
+                return;
+            }
+            Expr::BowlReadExpr(a, b) => vec![a, b],
+            Expr::MemReadExpr(a) => vec![a],
+            Expr::BowlWriteExpr(a, b, c) => vec![a, b, c],
+            Expr::MemWriteExpr(a, b) => vec![a, b],
+            Expr::DenoFuncExpr(a) => vec![a],
+            Expr::PlusFuncExpr(a, b) => vec![a, b],
+            Expr::MinusFuncExpr(a, b) => vec![a, b],
+            Expr::MulFuncExpr(a, b) => vec![a, b],
+            Expr::NumberSepFuncExpr(a, b) => vec![a, b],
+            Expr::AndFuncExpr(a, b) => vec![a, b],
+            Expr::OrFuncExpr(a, b) => vec![a, b],
+            Expr::NotFuncExpr(a) => vec![a],
+            Expr::EqFuncExpr(a, b) => vec![a, b],
+            Expr::GtFuncExpr(a, b) => vec![a, b],
+            Expr::LtFuncExpr(a, b) => vec![a, b],
+        };
+        self.data.push(expr_tag(expr));
+        for operand in operands {
+            self.expr(operand);
+        }
+    }
+
+    // Writes the noodles of every referenced bowl, including the ones only
+    // referenced from the table itself.
+    pub fn table(&mut self) {
+        let mut id = 0;
+        while let Some(bowl) = self.shared.as_ref().and_then(|bowls| bowls.get(id)) {
+            let bowl = bowl.clone();
+            self.bowl(&bowl.borrow());
+            id += 1;
+        }
+    }
+}
+
+fn small_integer(value: &Value) -> Option<usize> {
+    match value {
+        Value::Number(number) => u64::try_from(&**number)
+            .ok()
+            .and_then(|integer| usize::try_from(integer).ok()),
+        _ => None,
+    }
+}
+
+fn expr_tag(expr: &Expr) -> u8 {
+    match expr {
+        Expr::ValueExpr(_) => 0,
+        Expr::BowlReadExpr(..) => 1,
+        Expr::MemReadExpr(_) => 2,
+        Expr::BowlWriteExpr(..) => 3,
+        Expr::MemWriteExpr(..) => 4,
+        Expr::DenoFuncExpr(_) => 5,
+        Expr::PlusFuncExpr(..) => 6,
+        Expr::MinusFuncExpr(..) => 7,
+        Expr::MulFuncExpr(..) => 8,
+        Expr::NumberSepFuncExpr(..) => 9,
+        Expr::AndFuncExpr(..) => 10,
+        Expr::OrFuncExpr(..) => 11,
+        Expr::NotFuncExpr(_) => 12,
+        Expr::EqFuncExpr(..) => 13,
+        Expr::GtFuncExpr(..) => 14,
+        Expr::LtFuncExpr(..) => 15,
+    }
+}
+
+pub(crate) struct Decoder<'a> {
+    data: &'a [u8],
+    offset: usize,
+    // referenced bowls, empty until their noodles are read by `table`
+    shared: Option<Vec<Rc<RefCell<Bowl>>>>,
+    depth: usize,
+}
+
+impl Decoder<'_> {
+    pub fn new(data: &[u8], is_shared: bool) -> Decoder<'_> {
+        Decoder {
+            data,
+            offset: 0,
+            shared: if is_shared { Some(vec![]) } else { None },
+            depth: 0,
+        }
+    }
+
+    pub fn error(&self, message: &str) -> DecodeError {
+        DecodeError {
+            offset: self.offset,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn header(&mut self, magic: &[u8], version: u8, message: &str) -> Result<(), DecodeError> {
+        if self.bytes(magic.len())? != magic {
+            return Err(self.error(message));
+        }
+        let found = self.byte()?;
+        if found != version {
+            return Err(self.error(&format!("unsupported version {}", found)));
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> Result<(), DecodeError> {
+        if self.offset != self.data.len() {
+            return Err(self.error("trailing data"));
+        }
+        Ok(())
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&[u8], DecodeError> {
+        if self.data.len() - self.offset < len {
+            return Err(self.error("unexpected end of data"));
+        }
+        let bytes = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn varint(&mut self) -> Result<usize, DecodeError> {
+        let mut value: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= usize::BITS {
+                return Err(self.error("varint is too long"));
+            }
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn magnitude(&mut self) -> Result<BigUint, DecodeError> {
+        let len = self.varint()?;
+        Ok(BigUint::from_bytes_le(self.bytes(len)?))
+    }
+
+    pub fn number(&mut self) -> Result<Number, DecodeError> {
+        let sign = if self.byte()? == 1 {
+            Sign::Minus
+        } else {
+            Sign::Plus
+        };
+        let numerator = BigInt::from_biguint(sign, self.magnitude()?);
+        let denominator = self.magnitude()?;
+        Number::new(numerator, denominator).map_err(|e| self.error(&e.to_string()))
+    }
+
+    pub fn bowl(&mut self) -> Result<Bowl, DecodeError> {
+        let count = self.varint()?;
+        let mut noodles = vec![];
+        for _ in 0..count {
+            let line = match self.varint()? {
+                0 => None,
+                line => Some(line - 1),
+            };
+            noodles.push(Noodle {
+                nn_expr: self.expr()?,
+                expr: self.expr()?,
+                line,
+            });
+        }
+        Ok(Bowl { noodles })
+    }
+
+    pub fn value(&mut self) -> Result<Value, DecodeError> {
+        match self.byte()? {
+            0 => Ok(Value::Null),
+            1 => Ok(Value::from_number(&self.number()?)),
+            2 if self.shared.is_none() => Ok(Value::from_bowl(self.bowl()?)),
+            3 if self.shared.is_some() => {
+                let id = self.varint()?;
+                let bowls = self.shared.as_mut().unwrap();
+                // ids are given in order of first reference
+                if id == bowls.len() {
+                    bowls.push(Rc::new(RefCell::new(Bowl { noodles: vec![] })));
+                }
+                match bowls.get(id) {
+                    Some(bowl) => Ok(Value::Bowl(bowl.clone())),
+                    None => Err(self.error(&format!("unknown bowl reference {}", id))),
+                }
+            }
+            tag => Err(self.error(&format!("unknown value tag {}", tag))),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, DecodeError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("expressions are nested too deeply"));
+        }
+        self.depth += 1;
+        let expr = self.nested_expr();
+        self.depth -= 1;
+        expr
+    }
+
+    fn nested_expr(&mut self) -> Result<Expr, DecodeError> {
+        let tag = self.byte()?;
+        if tag == 0 {
+            return Ok(Expr::ValueExpr(self.value()?));
+        }
+        if tag == INTEGER_TAG {
+            let integer = BigInt::from(self.varint()?);
+            return Ok(Expr::ValueExpr(Value::from_big_int(
+                &integer,
+                &BigUint::from(1u32),
+            )));
+        }
+        // operands are read before the expression is built, keeping the frames
+        // of this recursion small
+        let count = match tag {
+            2 | 5 | 12 => 1,
+            3 => 3,
+            1..=15 => 2,
+            _ => return Err(self.error(&format!("unknown expression tag {}", tag))),
+        };
+        let mut operands = vec![];
+        for _ in 0..count {
+            operands.push(Box::new(self.expr()?));
+        }
+        let mut operands = operands.into_iter();
+        let mut operand = || operands.next().unwrap();
+        Ok(match tag {
+            1 => Expr::BowlReadExpr(operand(), operand()),
+            2 => Expr::MemReadExpr(operand()),
+            3 => Expr::BowlWriteExpr(operand(), operand(), operand()),
+            4 => Expr::MemWriteExpr(operand(), operand()),
+            5 => Expr::DenoFuncExpr(operand()),
+            6 => Expr::PlusFuncExpr(operand(), operand()),
+            7 => Expr::MinusFuncExpr(operand(), operand()),
+            8 => Expr::MulFuncExpr(operand(), operand()),
+            9 => Expr::NumberSepFuncExpr(operand(), operand()),
+            10 => Expr::AndFuncExpr(operand(), operand()),
+            11 => Expr::OrFuncExpr(operand(), operand()),
+            12 => Expr::NotFuncExpr(operand()),
+            13 => Expr::EqFuncExpr(operand(), operand()),
+            14 => Expr::GtFuncExpr(operand(), operand()),
+            _ => Expr::LtFuncExpr(operand(), operand()),
+        })
+    }
+
+    // Reads the noodles of every referenced bowl into the bowls handed out so
+    // far, in the order `Encoder::table` wrote them.
+    pub fn table(&mut self) -> Result<(), DecodeError> {
+        let mut id = 0;
+        while let Some(bowl) = self.shared.as_ref().and_then(|bowls| bowls.get(id)) {
+            let bowl = bowl.clone();
+            *bowl.borrow_mut() = self.bowl()?;
+            id += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn round_trip() {
+        let code = "{[0; @:1 = {[0; 72][1; 0 - 3/7][2; 123456789012345678901234567890]}][@:2 + 1; !(^(@:3) ?= 1) & (@:4:2 < 5)]}";
+        let bowl = parse(code).unwrap();
+        let data = encode(&bowl);
+        let decoded = decode(&data).unwrap();
+
+        assert_eq!(decoded.to_string(), bowl.to_string());
+        assert_eq!(decoded.noodles[1].line, bowl.noodles[1].line);
+        assert!(data.len() < code.len());
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(
+            decode(b"BIBN\x01\x00").unwrap_err().message,
+            "not an encoded Bibim program"
+        );
+        assert_eq!(
+            decode(b"BIBM\x01\x01\x00").unwrap_err().message,
+            "unexpected end of data"
+        );
+        // a zero denominator
+        assert_eq!(
+            decode(b"BIBM\x01\x01\x00\x00\x01\x00\x00\x00")
+                .unwrap_err()
+                .message,
+            "denominator cannot be zero"
+        );
+
+        // deeply nested negations, then bowls
+        let mut data = b"BIBM\x01\x01\x00".to_vec();
+        data.extend([12; 100_000]);
+        let e = decode(&data).unwrap_err();
+        assert_eq!(e.message, "expressions are nested too deeply");
+        assert_eq!(e.offset, 7 + MAX_DEPTH);
+        let mut data = b"BIBM\x01\x01\x00".to_vec();
+        data.extend([0, 2, 1, 0].repeat(100_000));
+        let e = decode(&data).unwrap_err();
+        assert_eq!(e.message, "expressions are nested too deeply");
+    }
+}
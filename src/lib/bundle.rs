@@ -0,0 +1,101 @@
+// Self-contained executables: the interpreter followed by the encoded program
+// and a trailer giving its length and the extensions it runs with. A bundled
+// executable runs its program when started without a program to run, and is
+// otherwise the interpreter.
+//
+//     executable := interpreter program trailer
+//     trailer    := length:u64 extensions:u8 "BIBMBNDL"
+//
+// The extensions are bits: extended literals first, then structural equality.
+
+use crate::{binary, datatype::Bowl, env::Extensions};
+
+const MAGIC: &[u8] = b"BIBMBNDL";
+pub const TRAILER_LEN: usize = 8 + 1 + MAGIC.len();
+
+const EXTENDED_LITERALS: u8 = 1;
+const STRUCTURAL_EQ: u8 = 2;
+
+pub fn bundle(executable: &[u8], bowl: &Bowl, extensions: &Extensions) -> Vec<u8> {
+    let mut data = interpreter(executable).to_vec();
+    let program = binary::encode(bowl);
+    let len = program.len() as u64;
+    data.extend(program);
+    data.extend(len.to_le_bytes());
+    let mut bits = 0;
+    if extensions.extended_literals {
+        bits |= EXTENDED_LITERALS;
+    }
+    if extensions.structural_eq {
+        bits |= STRUCTURAL_EQ;
+    }
+    data.push(bits);
+    data.extend(MAGIC);
+    data
+}
+
+// The length of the program before `trailer` and its extensions, if it is one.
+pub fn read_trailer(trailer: &[u8]) -> Option<(usize, Extensions)> {
+    if trailer.len() != TRAILER_LEN || !trailer.ends_with(MAGIC) {
+        return None;
+    }
+    let len = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    let bits = trailer[8];
+    if bits & !(EXTENDED_LITERALS | STRUCTURAL_EQ) != 0 {
+        return None;
+    }
+    let extensions = Extensions {
+        extended_literals: bits & EXTENDED_LITERALS != 0,
+        structural_eq: bits & STRUCTURAL_EQ != 0,
+    };
+    Some((usize::try_from(len).ok()?, extensions))
+}
+
+// The interpreter part of `executable`, without any bundled program.
+pub fn interpreter(executable: &[u8]) -> &[u8] {
+    if executable.len() < TRAILER_LEN {
+        return executable;
+    }
+    let trailer_start = executable.len() - TRAILER_LEN;
+    match read_trailer(&executable[trailer_start..]) {
+        Some((len, _)) if len <= trailer_start => &executable[..trailer_start - len],
+        _ => executable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn bundle_program() {
+        let bowl = parse("{[0; @:1 = {[0; 72][1; 105]}]}").unwrap();
+        let extensions = Extensions {
+            structural_eq: true,
+            ..Extensions::default()
+        };
+        let executable = bundle(b"\x7fELF interpreter", &bowl, &extensions);
+
+        let trailer = &executable[executable.len() - TRAILER_LEN..];
+        let (len, read) = read_trailer(trailer).unwrap();
+        assert_eq!(read, extensions);
+        let program = &executable[executable.len() - TRAILER_LEN - len..][..len];
+        assert_eq!(
+            binary::decode(program).unwrap().to_string(),
+            bowl.to_string()
+        );
+        assert_eq!(interpreter(&executable), b"\x7fELF interpreter");
+    }
+
+    #[test]
+    fn rebundle() {
+        let extensions = Extensions::default();
+        let first = bundle(b"interpreter", &parse("{[0; 1]}").unwrap(), &extensions);
+        let second = bundle(&first, &parse("{[0; 2]}").unwrap(), &extensions);
+
+        assert_eq!(interpreter(&second), b"interpreter");
+        assert_eq!(read_trailer(b"interpreter"), None);
+        assert_eq!(interpreter(b"interpreter"), b"interpreter");
+    }
+}
@@ -0,0 +1,696 @@
+// Lowers Bibim script to a bowl, following the conventions of hand-written
+// Bibim code:
+//
+// - Code is split in blocks. A block has its own base cell `@:b`, and its
+//   instructions are the noodles `[@:b + 0; ...]`, `[@:b + 1; ...]` and so on.
+//   Setting `@:b = @:0 + 1` runs the block next. A block is only left by its
+//   last instruction, so no other noodle can be above the cursor.
+// - A function has static cells for its parameters, variables, result and
+//   return position. The caller writes the arguments, writes the address of
+//   the base cell of the block to continue at into the return position cell,
+//   and jumps to the entry block. The function returns with
+//   `@:(@:ret) = @:0 + 1`. As frames are static, recursion is rejected.
+
+use std::collections::HashMap;
+
+use num_bigint::{BigInt, BigUint};
+
+use crate::{
+    datatype::{Bowl, Expr, Noodle, Number, Value},
+    error::CompileError,
+    script::{parse_script, BinaryOp, Function, ScriptExpr, Statement, StatementKind, UnaryOp},
+};
+
+struct Block {
+    base: Number,
+    noodles: Vec<Noodle>,
+}
+
+struct FunctionCells {
+    entry: Number,
+    ret: Number,
+    result: Number,
+    params: Vec<Number>,
+}
+
+struct Compiler {
+    next_cell: i64,
+    functions: HashMap<String, FunctionCells>,
+    blocks: Vec<Block>,
+    current: usize,
+}
+
+struct Scope<'a> {
+    function: &'a Function,
+    vars: HashMap<String, Number>,
+}
+
+pub fn compile(source: &str) -> Result<Bowl, CompileError> {
+    let functions = parse_script(source)?;
+    check_functions(&functions)?;
+    let mut compiler = Compiler {
+        // @:0 and @:1 are the cursor and IO
+        next_cell: 2,
+        functions: HashMap::new(),
+        blocks: vec![],
+        current: 0,
+    };
+    for function in &functions {
+        let cells = FunctionCells {
+            entry: compiler.cell(),
+            ret: compiler.cell(),
+            result: compiler.cell(),
+            params: function.params.iter().map(|_| compiler.cell()).collect(),
+        };
+        compiler.functions.insert(function.name.clone(), cells);
+    }
+    let main = functions
+        .iter()
+        .find(|function| function.name == "main")
+        .ok_or_else(|| error(1, "no `main` function"))?;
+    if !main.params.is_empty() {
+        return Err(error(main.line, "`main` takes no parameters"));
+    }
+
+    let mut noodles = vec![Noodle {
+        nn_expr: number(0),
+        expr: jump(&compiler.functions["main"].entry),
+        line: Some(main.line),
+    }];
+    for function in &functions {
+        compiler.function(function)?;
+    }
+    for block in compiler.blocks {
+        noodles.extend(block.noodles);
+    }
+    Ok(Bowl { noodles })
+}
+
+fn error(line: usize, message: &str) -> CompileError {
+    CompileError {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn integer(value: i64) -> Number {
+    Number::new(BigInt::from(value), BigUint::from(1u32)).unwrap()
+}
+
+fn number(value: i64) -> Expr {
+    Expr::ValueExpr(Value::from_number(&integer(value)))
+}
+
+fn cell(address: &Number) -> Expr {
+    Expr::ValueExpr(Value::from_number(address))
+}
+
+fn read(address: &Number) -> Expr {
+    Expr::MemReadExpr(Box::new(cell(address)))
+}
+
+fn write(address: &Number, value: Expr) -> Expr {
+    Expr::MemWriteExpr(Box::new(cell(address)), Box::new(value))
+}
+
+// `@:b = @:0 + 1`, which runs the block based at `b` next
+fn jump(base: &Number) -> Expr {
+    write(
+        base,
+        Expr::PlusFuncExpr(Box::new(read(&Number::zero())), Box::new(number(1))),
+    )
+}
+
+fn bowl(noodles: Vec<Noodle>) -> Expr {
+    Expr::ValueExpr(Value::from_bowl(Bowl { noodles }))
+}
+
+// Checks names and call arity, and rejects recursion.
+fn check_functions(functions: &[Function]) -> Result<(), CompileError> {
+    let mut arity = HashMap::new();
+    for function in functions {
+        if ["input", "deno"].contains(&function.name.as_str()) {
+            return Err(error(
+                function.line,
+                &format!("`{}` is a builtin function", function.name),
+            ));
+        }
+        if arity
+            .insert(function.name.as_str(), function.params.len())
+            .is_some()
+        {
+            return Err(error(
+                function.line,
+                &format!("function `{}` is defined twice", function.name),
+            ));
+        }
+    }
+    arity.insert("input", 0);
+    arity.insert("deno", 1);
+
+    let mut calls: HashMap<&str, Vec<(&str, usize)>> = HashMap::new();
+    for function in functions {
+        let mut callees = vec![];
+        for statement in &function.body {
+            statement_calls(statement, &mut callees);
+        }
+        for (name, args, line) in &callees {
+            match arity.get(name) {
+                None => return Err(error(*line, &format!("unknown function `{}`", name))),
+                Some(count) if count != args => {
+                    return Err(error(
+                        *line,
+                        &format!(
+                            "`{}` takes {} arguments but {} were given",
+                            name, count, args
+                        ),
+                    ))
+                }
+                _ => {}
+            }
+        }
+        calls.insert(
+            function.name.as_str(),
+            callees
+                .into_iter()
+                .map(|(name, _, line)| (name, line))
+                .collect(),
+        );
+    }
+
+    // depth first search for a call cycle
+    fn visit<'a>(
+        name: &'a str,
+        calls: &HashMap<&'a str, Vec<(&'a str, usize)>>,
+        path: &mut Vec<&'a str>,
+        done: &mut Vec<&'a str>,
+    ) -> Result<(), CompileError> {
+        if done.contains(&name) {
+            return Ok(());
+        }
+        path.push(name);
+        for (callee, line) in calls.get(name).into_iter().flatten() {
+            if path.contains(callee) {
+                return Err(error(
+                    *line,
+                    &format!(
+                        "recursive call to `{}` is not supported, as functions use static cells",
+                        callee
+                    ),
+                ));
+            }
+            visit(callee, calls, path, done)?;
+        }
+        path.pop();
+        done.push(name);
+        Ok(())
+    }
+    let mut done = vec![];
+    for function in functions {
+        visit(&function.name, &calls, &mut vec![], &mut done)?;
+    }
+    Ok(())
+}
+
+fn statement_calls<'a>(statement: &'a Statement, calls: &mut Vec<(&'a str, usize, usize)>) {
+    let line = statement.line;
+    match &statement.kind {
+        StatementKind::Var(_, expr)
+        | StatementKind::Assign(_, expr)
+        | StatementKind::Print(expr)
+        | StatementKind::Expr(expr)
+        | StatementKind::Return(Some(expr)) => expr_calls(expr, line, calls),
+        StatementKind::Return(None) => {}
+        StatementKind::IndexAssign(bowl, index, value) => {
+            expr_calls(bowl, line, calls);
+            expr_calls(index, line, calls);
+            expr_calls(value, line, calls);
+        }
+        StatementKind::If(cond, then_body, else_body) => {
+            expr_calls(cond, line, calls);
+            for statement in then_body.iter().chain(else_body) {
+                statement_calls(statement, calls);
+            }
+        }
+        StatementKind::While(cond, body) => {
+            expr_calls(cond, line, calls);
+            for statement in body {
+                statement_calls(statement, calls);
+            }
+        }
+    }
+}
+
+fn expr_calls<'a>(expr: &'a ScriptExpr, line: usize, calls: &mut Vec<(&'a str, usize, usize)>) {
+    match expr {
+        ScriptExpr::Number(_) | ScriptExpr::Str(_) | ScriptExpr::Var(_) => {}
+        ScriptExpr::Call(name, args) => {
+            calls.push((name, args.len(), line));
+            for arg in args {
+                expr_calls(arg, line, calls);
+            }
+        }
+        ScriptExpr::Index(expr1, expr2) | ScriptExpr::Binary(_, expr1, expr2) => {
+            expr_calls(expr1, line, calls);
+            expr_calls(expr2, line, calls);
+        }
+        ScriptExpr::Unary(_, expr) => expr_calls(expr, line, calls),
+    }
+}
+
+impl Compiler {
+    fn cell(&mut self) -> Number {
+        let cell = integer(self.next_cell);
+        self.next_cell += 1;
+        cell
+    }
+
+    fn new_block(&mut self, base: Number) -> usize {
+        self.blocks.push(Block {
+            base,
+            noodles: vec![],
+        });
+        self.blocks.len() - 1
+    }
+
+    fn emit(&mut self, expr: Expr, line: usize) {
+        let block = &mut self.blocks[self.current];
+        let nn_expr = Expr::PlusFuncExpr(
+            Box::new(read(&block.base)),
+            Box::new(number(block.noodles.len() as i64)),
+        );
+        block.noodles.push(Noodle {
+            nn_expr,
+            expr,
+            line: Some(line),
+        });
+    }
+
+    // Ends the current block with `expr` and continues in a new one.
+    fn terminate(&mut self, expr: Expr, line: usize, next: usize) {
+        self.emit(expr, line);
+        self.current = next;
+    }
+
+    fn function(&mut self, function: &Function) -> Result<(), CompileError> {
+        let cells = &self.functions[&function.name];
+        let entry = cells.entry.clone();
+        let mut scope = Scope {
+            function,
+            vars: function
+                .params
+                .iter()
+                .cloned()
+                .zip(cells.params.iter().cloned())
+                .collect(),
+        };
+        self.current = self.new_block(entry);
+        self.statements(&function.body, &mut scope)?;
+        self.ret(
+            None,
+            function.body.last().map_or(function.line, |s| s.line),
+            &mut scope,
+        )
+    }
+
+    fn statements(
+        &mut self,
+        statements: &[Statement],
+        scope: &mut Scope,
+    ) -> Result<(), CompileError> {
+        for statement in statements {
+            self.statement(statement, scope)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement, scope: &mut Scope) -> Result<(), CompileError> {
+        let line = statement.line;
+        match &statement.kind {
+            StatementKind::Var(name, expr) => {
+                let value = self.expr(expr, line, scope)?;
+                if scope.vars.contains_key(name) {
+                    return Err(error(
+                        line,
+                        &format!("variable `{}` is declared twice", name),
+                    ));
+                }
+                let address = self.cell();
+                scope.vars.insert(name.clone(), address.clone());
+                self.emit(write(&address, value), line);
+            }
+            StatementKind::Assign(name, expr) => {
+                let value = self.expr(expr, line, scope)?;
+                let address = self.var(name, line, scope)?;
+                self.emit(write(&address, value), line);
+            }
+            StatementKind::IndexAssign(bowl, index, value) => {
+                let bowl = self.expr(bowl, line, scope)?;
+                let index = self.expr(index, line, scope)?;
+                let value = self.expr(value, line, scope)?;
+                self.emit(
+                    Expr::BowlWriteExpr(Box::new(bowl), Box::new(index), Box::new(value)),
+                    line,
+                );
+            }
+            StatementKind::Print(expr) => {
+                let value = self.expr(expr, line, scope)?;
+                self.emit(write(&Number::one(), value), line);
+            }
+            StatementKind::Expr(expr) => {
+                let value = self.expr(expr, line, scope)?;
+                // a call leaves only a read of its result
+                if !matches!(value, Expr::MemReadExpr(_)) {
+                    self.emit(value, line);
+                }
+            }
+            StatementKind::If(cond, then_body, else_body) => {
+                let cond = self.expr(cond, line, scope)?;
+                let then_block = self.block();
+                let else_block = self.block();
+                let end_block = self.block();
+                self.branch(cond, then_block, else_block, line);
+                self.statements(then_body, scope)?;
+                self.goto(end_block, line);
+                self.current = else_block;
+                self.statements(else_body, scope)?;
+                self.goto(end_block, line);
+                self.current = end_block;
+            }
+            StatementKind::While(cond, body) => {
+                let cond_block = self.block();
+                let body_block = self.block();
+                let end_block = self.block();
+                self.goto(cond_block, line);
+                self.current = cond_block;
+                let cond = self.expr(cond, line, scope)?;
+                self.branch(cond, body_block, end_block, line);
+                self.statements(body, scope)?;
+                self.goto(cond_block, line);
+                self.current = end_block;
+            }
+            StatementKind::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => Some(self.expr(expr, line, scope)?),
+                    None => None,
+                };
+                self.ret(value, line, scope)?;
+                // code after `return` goes to a block that is never run
+                self.current = self.block();
+            }
+        }
+        Ok(())
+    }
+
+    fn block(&mut self) -> usize {
+        let base = self.cell();
+        self.new_block(base)
+    }
+
+    fn goto(&mut self, block: usize, line: usize) {
+        let base = self.blocks[block].base.clone();
+        self.terminate(jump(&base), line, block);
+    }
+
+    // `{[0; @:else = @:0 + 1][1; @:then = @:0 + 1]}:(cond)`
+    fn branch(&mut self, cond: Expr, then_block: usize, else_block: usize, line: usize) {
+        let then_base = self.blocks[then_block].base.clone();
+        let else_base = self.blocks[else_block].base.clone();
+        let cond = match cond {
+            Expr::EqFuncExpr(..)
+            | Expr::GtFuncExpr(..)
+            | Expr::LtFuncExpr(..)
+            | Expr::AndFuncExpr(..)
+            | Expr::OrFuncExpr(..)
+            | Expr::NotFuncExpr(..) => cond,
+            // any non zero number is true
+            cond => Expr::OrFuncExpr(Box::new(cond), Box::new(number(0))),
+        };
+        let branches = bowl(vec![
+            Noodle {
+                nn_expr: number(0),
+                expr: jump(&else_base),
+                line: Some(line),
+            },
+            Noodle {
+                nn_expr: number(1),
+                expr: jump(&then_base),
+                line: Some(line),
+            },
+        ]);
+        self.terminate(
+            Expr::BowlReadExpr(Box::new(branches), Box::new(cond)),
+            line,
+            then_block,
+        );
+    }
+
+    fn ret(
+        &mut self,
+        value: Option<Expr>,
+        line: usize,
+        scope: &mut Scope,
+    ) -> Result<(), CompileError> {
+        // returning from `main` ends the program, as no noodle is left above the cursor
+        if scope.function.name == "main" {
+            return Ok(());
+        }
+        let cells = &self.functions[&scope.function.name];
+        let (ret, result) = (cells.ret.clone(), cells.result.clone());
+        if let Some(value) = value {
+            self.emit(write(&result, value), line);
+        }
+        let expr = Expr::MemWriteExpr(
+            Box::new(read(&ret)),
+            Box::new(Expr::PlusFuncExpr(
+                Box::new(read(&Number::zero())),
+                Box::new(number(1)),
+            )),
+        );
+        self.emit(expr, line);
+        Ok(())
+    }
+
+    fn var(&self, name: &str, line: usize, scope: &Scope) -> Result<Number, CompileError> {
+        scope
+            .vars
+            .get(name)
+            .cloned()
+            .ok_or_else(|| error(line, &format!("unknown variable `{}`", name)))
+    }
+
+    // Lowers an expression. Calls are run by instructions emitted before, and
+    // their results are read from temporary cells.
+    fn expr(
+        &mut self,
+        expr: &ScriptExpr,
+        line: usize,
+        scope: &mut Scope,
+    ) -> Result<Expr, CompileError> {
+        Ok(match expr {
+            ScriptExpr::Number(value) => {
+                Expr::ValueExpr(Value::from_big_int(value, &BigUint::from(1u32)))
+            }
+            ScriptExpr::Str(bytes) => bowl(
+                bytes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, byte)| Noodle {
+                        nn_expr: number(i as i64),
+                        expr: number(*byte as i64),
+                        line: Some(line),
+                    })
+                    .collect(),
+            ),
+            ScriptExpr::Var(name) => read(&self.var(name, line, scope)?),
+            ScriptExpr::Index(bowl, index) => Expr::BowlReadExpr(
+                Box::new(self.expr(bowl, line, scope)?),
+                Box::new(self.expr(index, line, scope)?),
+            ),
+            ScriptExpr::Unary(UnaryOp::Neg, expr) => {
+                Expr::MinusFuncExpr(Box::new(number(0)), Box::new(self.expr(expr, line, scope)?))
+            }
+            ScriptExpr::Unary(UnaryOp::Not, expr) => {
+                Expr::NotFuncExpr(Box::new(self.expr(expr, line, scope)?))
+            }
+            ScriptExpr::Binary(op, expr1, expr2) => {
+                let expr1 = Box::new(self.expr(expr1, line, scope)?);
+                let expr2 = Box::new(self.expr(expr2, line, scope)?);
+                match op {
+                    BinaryOp::Add => Expr::PlusFuncExpr(expr1, expr2),
+                    BinaryOp::Sub => Expr::MinusFuncExpr(expr1, expr2),
+                    BinaryOp::Mul => Expr::MulFuncExpr(expr1, expr2),
+                    BinaryOp::Div => Expr::NumberSepFuncExpr(expr1, expr2),
+                    BinaryOp::Eq => Expr::EqFuncExpr(expr1, expr2),
+                    BinaryOp::Ne => Expr::NotFuncExpr(Box::new(Expr::EqFuncExpr(expr1, expr2))),
+                    BinaryOp::Gt => Expr::GtFuncExpr(expr1, expr2),
+                    BinaryOp::Lt => Expr::LtFuncExpr(expr1, expr2),
+                    BinaryOp::Ge => Expr::NotFuncExpr(Box::new(Expr::LtFuncExpr(expr1, expr2))),
+                    BinaryOp::Le => Expr::NotFuncExpr(Box::new(Expr::GtFuncExpr(expr1, expr2))),
+                    BinaryOp::And => Expr::AndFuncExpr(expr1, expr2),
+                    BinaryOp::Or => Expr::OrFuncExpr(expr1, expr2),
+                }
+            }
+            ScriptExpr::Call(name, args) => match name.as_str() {
+                "input" => read(&Number::one()),
+                "deno" => Expr::DenoFuncExpr(Box::new(self.expr(&args[0], line, scope)?)),
+                _ => self.call(name, args, line, scope)?,
+            },
+        })
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        args: &[ScriptExpr],
+        line: usize,
+        scope: &mut Scope,
+    ) -> Result<Expr, CompileError> {
+        // arguments are lowered first, as they may call the same function
+        let mut values = vec![];
+        for arg in args {
+            values.push(self.expr(arg, line, scope)?);
+        }
+        let cells = &self.functions[name];
+        let (entry, ret, result) = (cells.entry.clone(), cells.ret.clone(), cells.result.clone());
+        let params = cells.params.clone();
+        for (param, value) in params.iter().zip(values) {
+            self.emit(write(param, value), line);
+        }
+        let next = self.block();
+        let next_base = self.blocks[next].base.clone();
+        self.emit(write(&ret, cell(&next_base)), line);
+        self.terminate(jump(&entry), line, next);
+        let temp = self.cell();
+        self.emit(write(&temp, read(&result)), line);
+        Ok(read(&temp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{env::Env, eval::eval};
+
+    use super::*;
+
+    fn run_script(source: &str, input: &str) -> String {
+        let bowl = compile(source).unwrap();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut env = Env::new(
+            || input.as_bytes().to_vec(),
+            |data| output.lock().unwrap().extend(data),
+        );
+        eval(&mut env, bowl).unwrap();
+        let output = output.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn compile_loop_and_call() {
+        let source = r#"
+fn sum(n) {
+    var total = 0;
+    while n > 0 {
+        total = total + n;
+        n = n - 1;
+    }
+    return total;
+}
+
+fn main() {
+    var s = "sum: _\n";
+    s[5] = sum(3) + 48;
+    print s;
+}
+"#;
+        assert_eq!(run_script(source, ""), "sum: 6\n");
+    }
+
+    #[test]
+    fn compile_print_int() {
+        // the same routines as the hand-written `print_int` test
+        let source = r#"
+fn digits(n) {
+    var count = 0;
+    while n >= 1 {
+        n = n / 10;
+        count = count + 1;
+    }
+    return count;
+}
+
+fn to_string(n) {
+    var s = "";
+    var length = digits(n);
+    var i = length;
+    while i > 0 {
+        i = i - 1;
+        var rest = n / 10;
+        var digit = 0;
+        while deno(rest - digit / 10) != 1 {
+            digit = digit + 1;
+        }
+        s[i] = digit + 48;
+        n = (n - digit) / 10;
+    }
+    if length == 0 {
+        s[0] = 48;
+    }
+    return s;
+}
+
+fn main() {
+    print to_string(112873);
+}
+"#;
+        assert_eq!(run_script(source, ""), "112873");
+    }
+
+    #[test]
+    fn compile_if_else_and_input() {
+        let source = r#"
+fn classify(c) {
+    if c < 48 | c > 57 {
+        return "other";
+    } else if c == 48 {
+        return "zero";
+    }
+    return "digit";
+}
+
+fn main() {
+    var s = input();
+    var i = 0;
+    while s[i] > 0 {
+        print classify(s[i]);
+        print " ";
+        i = i + 1;
+    }
+}
+"#;
+        assert_eq!(run_script(source, "a07"), "other zero digit ");
+    }
+
+    #[test]
+    fn compile_errors() {
+        let message = |source: &str| format!("{}", compile(source).unwrap_err());
+        assert_eq!(
+            message("fn f(n) {\n  return f(n - 1);\n}\nfn main() { f(1); }"),
+            "line 2: recursive call to `f` is not supported, as functions use static cells"
+        );
+        assert_eq!(
+            message("fn main() {\n  x = 1;\n}"),
+            "line 2: unknown variable `x`"
+        );
+        assert_eq!(
+            message("fn main() { g(); }"),
+            "line 1: unknown function `g`"
+        );
+        assert_eq!(
+            message("fn main() {\n  var x = ;\n}"),
+            "line 2: expected an expression, found `;`"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use crate::datatype::{Bowl, Expr, Noodle, Value};
+
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub line: usize,
+    pub block: usize,
+    pub is_reached: bool,
+    pub hits: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    pub lines: BTreeMap<usize, u64>,
+    // branches of `{...}:(cond)` reads, keyed by the address of the literal bowl
+    pub branches: HashMap<usize, Branch>,
+}
+
+impl Coverage {
+    // Registers every noodle and bowl literal read of the program, so that
+    // unexecuted ones are reported too.
+    pub fn register(&mut self, bowl: &Bowl) {
+        for noodle in &bowl.noodles {
+            self.register_noodle(noodle);
+        }
+    }
+
+    fn register_noodle(&mut self, noodle: &Noodle) {
+        if let Some(line) = noodle.line {
+            self.lines.entry(line).or_insert(0);
+        }
+        self.register_expr(&noodle.nn_expr);
+        self.register_expr(&noodle.expr);
+    }
+
+    fn register_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::ValueExpr(Value::Bowl(bowl)) => self.register(&bowl.borrow()),
+            Expr::ValueExpr(_) => {}
+            Expr::BowlReadExpr(expr1, expr2) => {
+                if let Expr::ValueExpr(Value::Bowl(bowl)) = &**expr1 {
+                    let key = bowl.as_ptr() as usize;
+                    let bowl = bowl.borrow();
+                    if let Some(line) = bowl.noodles.first().and_then(|noodle| noodle.line) {
+                        if !self.branches.contains_key(&key) {
+                            let block = self
+                                .branches
+                                .values()
+                                .filter(|branch| branch.line == line)
+                                .count();
+                            self.branches.insert(
+                                key,
+                                Branch {
+                                    line,
+                                    block,
+                                    is_reached: false,
+                                    hits: vec![0; bowl.noodles.len()],
+                                },
+                            );
+                        }
+                    }
+                }
+                self.register_expr(expr1);
+                self.register_expr(expr2);
+            }
+            Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+                self.register_expr(expr1);
+                self.register_expr(expr2);
+                self.register_expr(expr3);
+            }
+            Expr::MemWriteExpr(expr1, expr2)
+            | Expr::PlusFuncExpr(expr1, expr2)
+            | Expr::MinusFuncExpr(expr1, expr2)
+            | Expr::MulFuncExpr(expr1, expr2)
+            | Expr::NumberSepFuncExpr(expr1, expr2)
+            | Expr::AndFuncExpr(expr1, expr2)
+            | Expr::OrFuncExpr(expr1, expr2)
+            | Expr::EqFuncExpr(expr1, expr2)
+            | Expr::GtFuncExpr(expr1, expr2)
+            | Expr::LtFuncExpr(expr1, expr2) => {
+                self.register_expr(expr1);
+                self.register_expr(expr2);
+            }
+            Expr::MemReadExpr(expr) | Expr::DenoFuncExpr(expr) | Expr::NotFuncExpr(expr) => {
+                self.register_expr(expr);
+            }
+        }
+    }
+
+    pub fn hit_noodle(&mut self, noodle: &Noodle) {
+        if let Some(line) = noodle.line {
+            *self.lines.entry(line).or_insert(0) += 1;
+        }
+    }
+
+    pub fn reach_bowl(&mut self, bowl: &Bowl) {
+        if let Some(branch) = self.branches.get_mut(&(bowl as *const Bowl as usize)) {
+            branch.is_reached = true;
+        }
+    }
+
+    pub fn hit_branch(&mut self, bowl: &Bowl, index: usize) {
+        if let Some(branch) = self.branches.get_mut(&(bowl as *const Bowl as usize)) {
+            if let Some(hits) = branch.hits.get_mut(index) {
+                *hits += 1;
+            }
+        }
+    }
+
+    pub fn to_lcov(&self, source_file: &str) -> String {
+        let mut lcov = String::new();
+        writeln!(lcov, "TN:").unwrap();
+        writeln!(lcov, "SF:{}", source_file).unwrap();
+        let mut branches: Vec<&Branch> = self.branches.values().collect();
+        branches.sort_by_key(|branch| (branch.line, branch.block));
+        let mut branch_hit = 0;
+        let mut branch_found = 0;
+        for branch in branches {
+            for (index, hits) in branch.hits.iter().enumerate() {
+                branch_found += 1;
+                if *hits > 0 {
+                    branch_hit += 1;
+                }
+                let taken = if branch.is_reached {
+                    hits.to_string()
+                } else {
+                    "-".to_string()
+                };
+                writeln!(
+                    lcov,
+                    "BRDA:{},{},{},{}",
+                    branch.line, branch.block, index, taken
+                )
+                .unwrap();
+            }
+        }
+        writeln!(lcov, "BRF:{}", branch_found).unwrap();
+        writeln!(lcov, "BRH:{}", branch_hit).unwrap();
+        for (line, hits) in &self.lines {
+            writeln!(lcov, "DA:{},{}", line, hits).unwrap();
+        }
+        writeln!(lcov, "LF:{}", self.lines.len()).unwrap();
+        writeln!(
+            lcov,
+            "LH:{}",
+            self.lines.values().filter(|hits| **hits > 0).count()
+        )
+        .unwrap();
+        writeln!(lcov, "end_of_record").unwrap();
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{env::Env, run};
+
+    use super::*;
+
+    fn coverage(code: &str) -> String {
+        let mut env = Env::new(Vec::new, |_| {}).with_coverage(Some(Coverage::default()));
+        run(code.to_string(), &mut env).unwrap();
+        env.coverage.unwrap().to_lcov("test.bibim")
+    }
+
+    #[test]
+    fn cover_noodles() {
+        let code = "{\n[1; @:2 = 1]\n[1; @:2 = 2]\n[2; @:3 = 3]\n}";
+        let lcov = coverage(code);
+        assert!(lcov.contains("DA:2,1\nDA:3,0\nDA:4,1\nLF:3\nLH:2\n"));
+        assert!(lcov.ends_with("end_of_record\n"));
+    }
+
+    #[test]
+    fn cover_branches() {
+        let code = "{\n[1; @:2 = {\n  [0; 10]\n  [1; 20]\n}:(1 > 0)]\n[2; {[0; 1][1; 2]}:(@:9)]\n[1; {\n[0; 1]\n}:(0)]\n}";
+        let lcov = coverage(code);
+        assert!(lcov.contains("BRDA:3,0,0,0\nBRDA:3,0,1,1\n"));
+        assert!(lcov.contains("BRDA:6,0,0,-\nBRDA:6,0,1,-\n"));
+        assert!(lcov.contains("BRDA:8,0,0,-\n"));
+        assert!(lcov.contains("BRF:5\nBRH:1\n"));
+        assert!(lcov.contains("DA:3,0\nDA:4,1\n"));
+    }
+}
@@ -1,37 +1,70 @@
-use std::{cell::RefCell, fmt, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
+    rc::Rc,
+    str::FromStr,
+};
 
 use num_bigint::{BigInt, BigUint, Sign, ToBigInt};
+use num_traits::ToPrimitive;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::error;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Bowl {
     pub noodles: Vec<Noodle>,
 }
 
 impl fmt::Display for Bowl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Formatted(self, NumberFormat::Fraction))
+    }
+}
+
+impl fmt::Display for Formatted<'_, Bowl> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut noodles = String::new();
-        for noodle in &self.noodles {
-            noodles.push_str(&format!("{}", noodle));
+        for noodle in &self.0.noodles {
+            noodles.push_str(&format!("{}", Formatted(noodle, self.1)));
         }
         write!(f, "{{{}}}", noodles)
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Noodle {
     pub nn_expr: Expr,
     pub expr: Expr,
+    pub line: Option<usize>,
 }
 
 impl fmt::Display for Noodle {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[{}; {}]", self.nn_expr, self.expr)
+        write!(f, "{}", Formatted(self, NumberFormat::Fraction))
+    }
+}
+
+impl fmt::Display for Formatted<'_, Noodle> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{}; {}]",
+            Formatted(&self.0.nn_expr, self.1),
+            Formatted(&self.0.expr, self.1)
+        )
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Expr {
     ValueExpr(Value),
     BowlReadExpr(Box<Expr>, Box<Expr>),
@@ -51,8 +84,8 @@ pub enum Expr {
     LtFuncExpr(Box<Expr>, Box<Expr>),
 }
 
-fn strip_parens(expr: &Expr) -> String {
-    let mut string = format!("{}", expr);
+fn strip_parens(expr: &Expr, format: NumberFormat) -> String {
+    let mut string = format!("{}", Formatted(expr, format));
     while string.starts_with("(") && string.ends_with(")") {
         let mut paren_count = 1;
         let mut should_strip = false;
@@ -80,9 +113,16 @@ fn strip_parens(expr: &Expr) -> String {
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
+        write!(f, "{}", Formatted(self, NumberFormat::Fraction))
+    }
+}
+
+impl fmt::Display for Formatted<'_, Expr> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let strip_parens = |expr: &Expr| strip_parens(expr, self.1);
+        match self.0 {
             Expr::ValueExpr(value) => {
-                write!(f, "{}", value)
+                write!(f, "{}", Formatted(value, self.1))
             }
             Expr::BowlReadExpr(bowl_expr, nn_expr) => write!(
                 f,
@@ -127,7 +167,7 @@ impl fmt::Display for Expr {
             Expr::OrFuncExpr(expr1, expr2) => {
                 write!(f, "({})|({})", strip_parens(expr1), strip_parens(expr2))
             }
-            Expr::NotFuncExpr(expr) => write!(f, "!({})", expr),
+            Expr::NotFuncExpr(expr) => write!(f, "!({})", Formatted(&**expr, self.1)),
             Expr::EqFuncExpr(expr1, expr2) => {
                 write!(f, "({})?=({})", strip_parens(expr1), strip_parens(expr2))
             }
@@ -141,7 +181,8 @@ impl fmt::Display for Expr {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Value {
     Number(Box<Number>),
     Bowl(Rc<RefCell<Bowl>>),
@@ -173,20 +214,82 @@ impl Value {
     }
 }
 
-impl fmt::Display for Value {
+thread_local! {
+    // the bowls being shown, outermost first
+    static SHOWING: RefCell<Vec<*const RefCell<Bowl>>> = const { RefCell::new(vec![]) };
+}
+
+// Shows `bowl` with `show`, or returns how many bowls out it is already being
+// shown when it holds itself.
+fn show_bowl(
+    bowl: &Rc<RefCell<Bowl>>,
+    show: impl FnOnce() -> fmt::Result,
+) -> Result<fmt::Result, usize> {
+    let ptr = Rc::as_ptr(bowl);
+    let depth = SHOWING.with(|showing| {
+        let mut showing = showing.borrow_mut();
+        let depth = showing.iter().rev().position(|other| *other == ptr);
+        if depth.is_none() {
+            showing.push(ptr);
+        }
+        depth
+    });
+    if let Some(depth) = depth {
+        return Err(depth + 1);
+    }
+    let result = show();
+    SHOWING.with(|showing| showing.borrow_mut().pop());
+    Ok(result)
+}
+
+impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Value::Number(number) => write!(f, "{}", number),
-            Value::Bowl(bowl) => write!(f, "{}", bowl.borrow()),
+            Value::Number(number) => f.debug_tuple("Number").field(number).finish(),
+            Value::Bowl(bowl) => {
+                match show_bowl(bowl, || {
+                    f.debug_tuple("Bowl").field(&*bowl.borrow()).finish()
+                }) {
+                    Ok(result) => result,
+                    Err(depth) => write!(f, "Bowl(^{})", depth),
+                }
+            }
+            Value::Null => write!(f, "Null"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Formatted(self, NumberFormat::Fraction))
+    }
+}
+
+impl fmt::Display for Formatted<'_, Value> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Value::Number(number) => write!(f, "{}", Formatted(&**number, self.1)),
+            // a bowl inside itself is shown as `{^n}`, n bowls out
+            Value::Bowl(bowl) => {
+                match show_bowl(bowl, || write!(f, "{}", Formatted(&*bowl.borrow(), self.1))) {
+                    Ok(result) => result,
+                    Err(depth) => write!(f, "{{^{}}}", depth),
+                }
+            }
             Value::Null => write!(f, "NULL"),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Number {
-    pub numerator: BigInt,
-    pub denominator: BigUint,
+// Numbers are kept in lowest terms with a positive denominator, in machine
+// integers while both terms fit, so equal numbers have equal representations.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Number(Repr);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Small(i64, u64),
+    Big(BigInt, BigUint),
 }
 
 fn gcd(a: BigUint, b: BigUint) -> BigUint {
@@ -198,6 +301,13 @@ fn gcd(a: BigUint, b: BigUint) -> BigUint {
     a
 }
 
+fn small_gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
 fn big_abs(a: BigInt) -> BigUint {
     if a.sign() == Sign::Plus {
         a.to_biguint().unwrap()
@@ -206,6 +316,11 @@ fn big_abs(a: BigInt) -> BigUint {
     }
 }
 
+// `numerator * factor`, without converting `factor` to a `BigInt`.
+fn scale(numerator: &BigInt, factor: &BigUint) -> BigInt {
+    BigInt::from_biguint(numerator.sign(), numerator.magnitude() * factor)
+}
+
 impl Number {
     pub fn new(
         numerator: BigInt,
@@ -214,65 +329,154 @@ impl Number {
         if denominator == BigUint::from(0u32) {
             return Err(error::ZeroDenominatorError);
         }
+        if let (Ok(numerator), Ok(denominator)) =
+            (i64::try_from(&numerator), u64::try_from(&denominator))
+        {
+            return Ok(Number::reduce(numerator as i128, denominator as u128));
+        }
         if denominator == BigUint::from(1u32) {
-            return Ok(Number {
-                numerator,
-                denominator,
-            });
+            return Ok(Number::from_terms(numerator, denominator));
         }
         let g = gcd(big_abs(numerator.clone()), denominator.clone());
-        Ok(Number {
-            numerator: numerator / g.to_bigint().unwrap(),
-            denominator: denominator / g,
-        })
+        Ok(Number::from_terms(
+            numerator / g.to_bigint().unwrap(),
+            denominator / g,
+        ))
+    }
+
+    // `numerator/denominator` in lowest terms, for a positive denominator.
+    fn reduce(numerator: i128, denominator: u128) -> Number {
+        let g = small_gcd(numerator.unsigned_abs(), denominator);
+        let magnitude = numerator.unsigned_abs() / g;
+        let denominator = denominator / g;
+        let sign = if numerator < 0 {
+            Sign::Minus
+        } else {
+            Sign::Plus
+        };
+        let small = i128::try_from(magnitude).ok().and_then(|magnitude| {
+            i64::try_from(if numerator < 0 { -magnitude } else { magnitude }).ok()
+        });
+        match (small, u64::try_from(denominator)) {
+            (Some(numerator), Ok(denominator)) => Number(Repr::Small(numerator, denominator)),
+            _ => Number(Repr::Big(
+                BigInt::from_biguint(sign, BigUint::from(magnitude)),
+                BigUint::from(denominator),
+            )),
+        }
+    }
+
+    // Terms already in lowest terms.
+    fn from_terms(numerator: BigInt, denominator: BigUint) -> Number {
+        match (i64::try_from(&numerator), u64::try_from(&denominator)) {
+            (Ok(numerator), Ok(denominator)) => Number(Repr::Small(numerator, denominator)),
+            _ => Number(Repr::Big(numerator, denominator)),
+        }
+    }
+
+    fn terms(&self) -> (Cow<'_, BigInt>, Cow<'_, BigUint>) {
+        match &self.0 {
+            Repr::Small(numerator, denominator) => (
+                Cow::Owned(BigInt::from(*numerator)),
+                Cow::Owned(BigUint::from(*denominator)),
+            ),
+            Repr::Big(numerator, denominator) => {
+                (Cow::Borrowed(numerator), Cow::Borrowed(denominator))
+            }
+        }
+    }
+
+    pub fn numerator(&self) -> BigInt {
+        self.terms().0.into_owned()
+    }
+
+    pub fn denominator(&self) -> BigUint {
+        self.terms().1.into_owned()
+    }
+
+    // The number of bits of the larger of the numerator and the denominator.
+    pub fn bits(&self) -> u64 {
+        match &self.0 {
+            Repr::Small(numerator, denominator) => {
+                let numerator = 64 - numerator.unsigned_abs().leading_zeros();
+                let denominator = 64 - denominator.leading_zeros();
+                numerator.max(denominator) as u64
+            }
+            Repr::Big(numerator, denominator) => numerator.bits().max(denominator.bits()),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        match &self.0 {
+            Repr::Small(_, denominator) => *denominator == 1,
+            Repr::Big(_, denominator) => *denominator == BigUint::from(1u32),
+        }
     }
 
     pub fn one() -> Number {
-        Number::new(BigInt::from(1u32), BigUint::from(1u32)).unwrap()
+        Number(Repr::Small(1, 1))
     }
 
     pub fn zero() -> Number {
-        Number::new(BigInt::from(0u32), BigUint::from(1u32)).unwrap()
+        Number(Repr::Small(0, 1))
+    }
+
+    // `numerator/denominator`, usable in constants such as the bowls `bibim!`
+    // expands to.
+    pub const fn ratio(numerator: i64, denominator: u64) -> Number {
+        assert!(denominator != 0, "zero denominator");
+        let (mut a, mut b) = (numerator.unsigned_abs(), denominator);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        Number(Repr::Small(
+            (numerator as i128 / a as i128) as i64,
+            denominator / a,
+        ))
+    }
+
+    // A number from the little endian base 2^32 digits of its terms, for
+    // numbers too large for `ratio`.
+    pub fn from_digits(
+        is_negative: bool,
+        numerator: &[u32],
+        denominator: &[u32],
+    ) -> Result<Number, error::ZeroDenominatorError> {
+        let sign = if is_negative { Sign::Minus } else { Sign::Plus };
+        let numerator = BigInt::from_biguint(sign, BigUint::from_slice(numerator));
+        Number::new(numerator, BigUint::from_slice(denominator))
     }
 
+    #[deprecated(note = "use the `-` operator")]
     pub fn neg(&self) -> Number {
-        Number::new(
-            BigInt::from(0u32) - self.numerator.clone(),
-            self.denominator.clone(),
-        )
-        .unwrap()
+        -self
     }
 
+    #[deprecated(note = "use the `+` operator")]
     pub fn add(&self, other: &Number) -> Number {
-        let numerator = self.numerator.clone() * other.denominator.to_bigint().unwrap()
-            + other.numerator.clone() * self.denominator.to_bigint().unwrap();
-        let denominator = self.denominator.clone() * other.denominator.clone();
-        Number::new(numerator, denominator).unwrap()
+        self + other
     }
 
+    #[deprecated(note = "use the `-` operator")]
     pub fn sub(&self, other: &Number) -> Number {
-        self.add(&other.neg())
+        self - other
     }
 
+    #[deprecated(note = "use the `*` operator")]
     pub fn mul(&self, other: &Number) -> Number {
-        Number::new(
-            self.numerator.clone() * other.numerator.clone(),
-            self.denominator.clone() * other.denominator.clone(),
-        )
-        .unwrap()
+        self * other
     }
 
+    #[deprecated(note = "use the `/` operator")]
     pub fn div(&self, other: &Number) -> Number {
-        let mut numerator = self.numerator.clone() * other.denominator.to_bigint().unwrap();
-        let denomerator = self.denominator.to_bigint().unwrap() * other.numerator.clone();
-        if denomerator.sign() == Sign::Minus {
-            numerator = -numerator;
-        }
-        Number::new(numerator, denomerator.to_biguint().unwrap()).unwrap()
+        self / other
     }
 
     pub fn bool(&self) -> bool {
-        self.numerator != BigInt::from(0u32)
+        match &self.0 {
+            Repr::Small(numerator, _) => *numerator != 0,
+            Repr::Big(numerator, _) => *numerator != BigInt::from(0u32),
+        }
     }
 
     pub fn and(&self, other: &Number) -> bool {
@@ -283,36 +487,683 @@ impl Number {
         self.bool() || other.bool()
     }
 
+    #[deprecated(note = "use the `<` operator")]
     pub fn lt(&self, other: &Number) -> bool {
-        self.numerator.clone() * other.denominator.to_bigint().unwrap()
-            < other.numerator.clone() * self.denominator.to_bigint().unwrap()
+        self < other
     }
 
+    #[deprecated(note = "use the `>` operator")]
     pub fn gt(&self, other: &Number) -> bool {
-        self.numerator.clone() * other.denominator.to_bigint().unwrap()
-            > other.numerator.clone() * self.denominator.to_bigint().unwrap()
+        self > other
     }
 
+    #[deprecated(note = "use the `==` operator")]
+    #[allow(clippy::should_implement_trait)]
     pub fn eq(&self, other: &Number) -> bool {
-        self.numerator.clone() == other.numerator.clone()
-            && self.denominator.clone() == other.denominator.clone()
+        self == other
     }
 
+    #[deprecated(note = "use the `>=` operator")]
     pub fn ge(&self, other: &Number) -> bool {
-        self.eq(other) || self.gt(other)
+        self >= other
     }
 
+    #[deprecated(note = "use the `<=` operator")]
     pub fn le(&self, other: &Number) -> bool {
-        self.eq(other) || self.lt(other)
+        self <= other
+    }
+
+    // Rounds to the nearest `f64`, even when the numerator and the denominator
+    // are too large for one.
+    pub fn to_f64(&self) -> f64 {
+        const EXACT: u64 = 1 << 53;
+        if let Repr::Small(numerator, denominator) = &self.0 {
+            // both terms convert exactly, so the division rounds once
+            if numerator.unsigned_abs() <= EXACT && *denominator <= EXACT {
+                return *numerator as f64 / *denominator as f64;
+            }
+        }
+        let (numerator, denominator) = self.terms();
+        if numerator.sign() == Sign::NoSign {
+            return 0.0;
+        }
+        // an integer quotient of 65 bits or more, with a bit set for any
+        // remainder, rounds like the exact ratio
+        let shift = denominator.bits() as i64 - numerator.bits() as i64 + 65;
+        let (dividend, divisor) = if shift >= 0 {
+            (numerator.magnitude() << shift, denominator.into_owned())
+        } else {
+            (numerator.magnitude().clone(), &*denominator << -shift)
+        };
+        let mut quotient = &dividend / &divisor;
+        if (&quotient * &divisor) != dividend {
+            quotient |= BigUint::from(1u32);
+        }
+        let magnitude = scale_f64(quotient.to_f64().unwrap(), -shift);
+        if numerator.sign() == Sign::Minus {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+// `value * 2^exponent`, in steps that keep the power of two finite.
+fn scale_f64(mut value: f64, mut exponent: i64) -> f64 {
+    while exponent != 0 && value != 0.0 && value.is_finite() {
+        let step = exponent.clamp(-1000, 1000);
+        value *= 2f64.powi(step as i32);
+        exponent -= step;
+    }
+    value
+}
+
+// Each operator tries machine integers first, where products of two terms fit
+// in 128 bits, and falls back to big integers when a result overflows.
+impl Add<&Number> for &Number {
+    type Output = Number;
+
+    fn add(self, other: &Number) -> Number {
+        if let (Repr::Small(a, b), Repr::Small(c, d)) = (&self.0, &other.0) {
+            if *b == 1 && *d == 1 {
+                if let Some(sum) = a.checked_add(*c) {
+                    return Number(Repr::Small(sum, 1));
+                }
+            } else if let Some(numerator) =
+                (*a as i128 * *d as i128).checked_add(*c as i128 * *b as i128)
+            {
+                return Number::reduce(numerator, *b as u128 * *d as u128);
+            }
+        }
+        let ((a, b), (c, d)) = (self.terms(), other.terms());
+        Number::new(scale(&a, &d) + scale(&c, &b), &*b * &*d).unwrap()
+    }
+}
+
+impl Sub<&Number> for &Number {
+    type Output = Number;
+
+    fn sub(self, other: &Number) -> Number {
+        if let (Repr::Small(a, b), Repr::Small(c, d)) = (&self.0, &other.0) {
+            if *b == 1 && *d == 1 {
+                if let Some(difference) = a.checked_sub(*c) {
+                    return Number(Repr::Small(difference, 1));
+                }
+            } else if let Some(numerator) =
+                (*a as i128 * *d as i128).checked_sub(*c as i128 * *b as i128)
+            {
+                return Number::reduce(numerator, *b as u128 * *d as u128);
+            }
+        }
+        let ((a, b), (c, d)) = (self.terms(), other.terms());
+        Number::new(scale(&a, &d) - scale(&c, &b), &*b * &*d).unwrap()
+    }
+}
+
+impl Mul<&Number> for &Number {
+    type Output = Number;
+
+    fn mul(self, other: &Number) -> Number {
+        if let (Repr::Small(a, b), Repr::Small(c, d)) = (&self.0, &other.0) {
+            if *b == 1 && *d == 1 {
+                if let Some(product) = a.checked_mul(*c) {
+                    return Number(Repr::Small(product, 1));
+                }
+            }
+            return Number::reduce(*a as i128 * *c as i128, *b as u128 * *d as u128);
+        }
+        let ((a, b), (c, d)) = (self.terms(), other.terms());
+        Number::new(&*a * &*c, &*b * &*d).unwrap()
+    }
+}
+
+impl Div<&Number> for &Number {
+    type Output = Number;
+
+    // Panics when dividing by zero, as integers do.
+    fn div(self, other: &Number) -> Number {
+        if let (Repr::Small(a, b), Repr::Small(c, d)) = (&self.0, &other.0) {
+            if *c != 0 {
+                let numerator = *a as i128 * *d as i128;
+                let denominator = *b as u128 * c.unsigned_abs() as u128;
+                return Number::reduce(if *c < 0 { -numerator } else { numerator }, denominator);
+            }
+        }
+        let ((a, b), (c, d)) = (self.terms(), other.terms());
+        let mut numerator = scale(&a, &d);
+        if c.sign() == Sign::Minus {
+            numerator = -numerator;
+        }
+        Number::new(numerator, c.magnitude() * &*b).unwrap()
+    }
+}
+
+impl Neg for &Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match &self.0 {
+            Repr::Small(numerator, denominator) => match numerator.checked_neg() {
+                Some(numerator) => Number(Repr::Small(numerator, *denominator)),
+                None => Number(Repr::Big(
+                    -BigInt::from(*numerator),
+                    BigUint::from(*denominator),
+                )),
+            },
+            Repr::Big(numerator, denominator) => {
+                Number::from_terms(-numerator, denominator.clone())
+            }
+        }
+    }
+}
+
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match self.0 {
+            Repr::Big(numerator, denominator) => Number::from_terms(-numerator, denominator),
+            _ => -&self,
+        }
+    }
+}
+
+// Forwards the owned forms of an operator, and its assigning form, to the
+// one taking references.
+macro_rules! forward_op {
+    ($op:ident, $method:ident, $assign_op:ident, $assign_method:ident) => {
+        impl $op<Number> for Number {
+            type Output = Number;
+
+            fn $method(self, other: Number) -> Number {
+                <&Number as $op<&Number>>::$method(&self, &other)
+            }
+        }
+
+        impl $op<&Number> for Number {
+            type Output = Number;
+
+            fn $method(self, other: &Number) -> Number {
+                <&Number as $op<&Number>>::$method(&self, other)
+            }
+        }
+
+        impl $op<Number> for &Number {
+            type Output = Number;
+
+            fn $method(self, other: Number) -> Number {
+                <&Number as $op<&Number>>::$method(self, &other)
+            }
+        }
+
+        impl $assign_op<&Number> for Number {
+            fn $assign_method(&mut self, other: &Number) {
+                *self = <&Number as $op<&Number>>::$method(self, other);
+            }
+        }
+
+        impl $assign_op<Number> for Number {
+            fn $assign_method(&mut self, other: Number) {
+                *self = <&Number as $op<&Number>>::$method(self, &other);
+            }
+        }
+    };
+}
+
+forward_op!(Add, add, AddAssign, add_assign);
+forward_op!(Sub, sub, SubAssign, sub_assign);
+forward_op!(Mul, mul, MulAssign, mul_assign);
+forward_op!(Div, div, DivAssign, div_assign);
+
+impl Ord for Number {
+    fn cmp(&self, other: &Number) -> Ordering {
+        if let (Repr::Small(a, b), Repr::Small(c, d)) = (&self.0, &other.0) {
+            return (*a as i128 * *d as i128).cmp(&(*c as i128 * *b as i128));
+        }
+        let ((a, b), (c, d)) = (self.terms(), other.terms());
+        scale(&a, &d).cmp(&scale(&c, &b))
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<i64> for Number {
+    fn from(integer: i64) -> Number {
+        Number(Repr::Small(integer, 1))
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(integer: BigInt) -> Number {
+        Number::from_terms(integer, BigUint::from(1u32))
+    }
+}
+
+// Converts integers in the range of the target type.
+macro_rules! try_into_integer {
+    ($integer:ty) => {
+        impl TryFrom<&Number> for $integer {
+            type Error = error::NumberRangeError;
+
+            fn try_from(number: &Number) -> Result<$integer, error::NumberRangeError> {
+                match &number.0 {
+                    Repr::Small(numerator, 1) => {
+                        <$integer>::try_from(*numerator).map_err(|_| error::NumberRangeError)
+                    }
+                    Repr::Big(numerator, denominator) if *denominator == BigUint::from(1u32) => {
+                        <$integer>::try_from(numerator).map_err(|_| error::NumberRangeError)
+                    }
+                    _ => Err(error::NumberRangeError),
+                }
+            }
+        }
+
+        impl TryFrom<Number> for $integer {
+            type Error = error::NumberRangeError;
+
+            fn try_from(number: Number) -> Result<$integer, error::NumberRangeError> {
+                <$integer>::try_from(&number)
+            }
+        }
+    };
+}
+
+try_into_integer!(i64);
+try_into_integer!(u64);
+try_into_integer!(u8);
+
+impl FromStr for Number {
+    type Err = error::NumberParseError;
+
+    // Parses `numerator` or `numerator/denominator`, as `Number` displays.
+    fn from_str(s: &str) -> Result<Number, error::NumberParseError> {
+        let (numerator, denominator) = s.split_once('/').unwrap_or((s, "1"));
+        let numerator = numerator.parse().map_err(|_| error::NumberParseError)?;
+        let denominator = denominator.parse().map_err(|_| error::NumberParseError)?;
+        Number::new(numerator, denominator).map_err(|_| error::NumberParseError)
+    }
+}
+
+// Numbers are written as strings, so other languages need no big integers.
+#[cfg(feature = "serde")]
+impl Serialize for Number {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Number, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        string.parse().map_err(de::Error::custom)
     }
 }
 
 impl fmt::Display for Number {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.denominator == BigUint::from(1u32) {
-            write!(f, "{}", self.numerator)
+        match &self.0 {
+            Repr::Small(numerator, 1) => write!(f, "{}", numerator),
+            Repr::Small(numerator, denominator) => write!(f, "{}/{}", numerator, denominator),
+            Repr::Big(numerator, denominator) if *denominator == BigUint::from(1u32) => {
+                write!(f, "{}", numerator)
+            }
+            Repr::Big(numerator, denominator) => write!(f, "{}/{}", numerator, denominator),
+        }
+    }
+}
+
+// How numbers are shown in debug traces, memory dumps and the REPL. Only
+// fractions can be parsed back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberFormat {
+    // `-7/2`
+    #[default]
+    Fraction,
+    // `-3.5`, with the repeating part in parentheses, `1/6` as `0.1(6)`
+    Decimal,
+    // rounded to the given number of places, `-3.50`
+    Fixed(usize),
+    // `-3 1/2`
+    Mixed,
+    // with the given number of places, `-3.50e0`
+    Scientific(usize),
+}
+
+// Shows a bowl, noodle, expression, value or number with its numbers in a format.
+pub struct Formatted<'a, T>(pub &'a T, pub NumberFormat);
+
+// Decimals stop with `...` after this many places without repeating, and fixed
+// and scientific formats show no more places.
+pub const MAX_DECIMAL_PLACES: usize = 1000;
+
+fn pow10(exponent: u32) -> BigUint {
+    BigUint::from(10u32).pow(exponent)
+}
+
+// `numerator/denominator` rounded half away from zero to `places` places, as
+// digits with a point.
+fn fixed_point(numerator: &BigUint, denominator: &BigUint, places: usize) -> String {
+    let places = places.min(MAX_DECIMAL_PLACES);
+    let scaled = numerator * pow10(places as u32) * 2u32 + denominator;
+    let digits = (scaled / (denominator * 2u32)).to_string();
+    if places == 0 {
+        return digits;
+    }
+    let digits = format!("{:0>width$}", digits, width = places + 1);
+    let (int, frac) = digits.split_at(digits.len() - places);
+    format!("{}.{}", int, frac)
+}
+
+impl Number {
+    pub fn format(&self, format: NumberFormat) -> String {
+        let (numerator, denominator) = self.terms();
+        let sign = if numerator.sign() == Sign::Minus {
+            "-"
         } else {
-            write!(f, "{}/{}", self.numerator, self.denominator)
+            ""
+        };
+        let magnitude = numerator.magnitude();
+        match format {
+            NumberFormat::Fraction => self.to_string(),
+            NumberFormat::Decimal => {
+                let mut string = format!("{}{}", sign, magnitude / &*denominator);
+                let mut remainder = magnitude % &*denominator;
+                if remainder == BigUint::from(0u32) {
+                    return string;
+                }
+                string.push('.');
+                // long division, until a remainder repeats
+                let mut digits = String::new();
+                let mut seen = HashMap::new();
+                while remainder != BigUint::from(0u32) {
+                    if let Some(&start) = seen.get(&remainder) {
+                        return format!("{}{}({})", string, &digits[..start], &digits[start..]);
+                    }
+                    if digits.len() == MAX_DECIMAL_PLACES {
+                        return format!("{}{}...", string, digits);
+                    }
+                    seen.insert(remainder.clone(), digits.len());
+                    remainder *= 10u32;
+                    digits.push_str(&(&remainder / &*denominator).to_string());
+                    remainder %= &*denominator;
+                }
+                string + &digits
+            }
+            NumberFormat::Fixed(places) => {
+                let digits = fixed_point(magnitude, &denominator, places);
+                if digits.chars().all(|c| c == '0' || c == '.') {
+                    digits
+                } else {
+                    format!("{}{}", sign, digits)
+                }
+            }
+            NumberFormat::Mixed => {
+                let int = magnitude / &*denominator;
+                let remainder = magnitude % &*denominator;
+                if int == BigUint::from(0u32) || remainder == BigUint::from(0u32) {
+                    self.to_string()
+                } else {
+                    format!("{}{} {}/{}", sign, int, remainder, denominator)
+                }
+            }
+            NumberFormat::Scientific(places) => {
+                if magnitude.bits() == 0 {
+                    return format!("{}e0", fixed_point(magnitude, &denominator, places));
+                }
+                // the exponent is off by at most one from the difference in digits
+                let mut exponent =
+                    magnitude.to_string().len() as i64 - denominator.to_string().len() as i64;
+                let below = |exponent: i64| {
+                    if exponent >= 0 {
+                        *magnitude < &*denominator * pow10(exponent as u32)
+                    } else {
+                        magnitude * pow10(-exponent as u32) < *denominator
+                    }
+                };
+                if below(exponent) {
+                    exponent -= 1;
+                }
+                let (mantissa_numerator, mantissa_denominator) = if exponent >= 0 {
+                    (magnitude.clone(), &*denominator * pow10(exponent as u32))
+                } else {
+                    (
+                        magnitude * pow10(-exponent as u32),
+                        denominator.into_owned(),
+                    )
+                };
+                let mut mantissa = fixed_point(&mantissa_numerator, &mantissa_denominator, places);
+                // rounded up to ten
+                if mantissa.starts_with("10") {
+                    exponent += 1;
+                    mantissa = fixed_point(&BigUint::from(1u32), &BigUint::from(1u32), places);
+                }
+                format!("{}{}e{}", sign, mantissa, exponent)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Formatted<'_, Number> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.format(self.1))
+    }
+}
+
+impl FromStr for NumberFormat {
+    type Err = error::NumberFormatParseError;
+
+    // Parses `fraction`, `decimal`, `fixed:<places>`, `mixed` or
+    // `scientific:<places>`.
+    fn from_str(s: &str) -> Result<NumberFormat, error::NumberFormatParseError> {
+        let (name, places) = match s.split_once(':') {
+            Some((name, places)) => match places.parse() {
+                Ok(places) if places <= MAX_DECIMAL_PLACES => (name, Some(places)),
+                _ => return Err(error::NumberFormatParseError),
+            },
+            None => (s, None),
+        };
+        match (name, places) {
+            ("fraction", None) => Ok(NumberFormat::Fraction),
+            ("decimal", None) => Ok(NumberFormat::Decimal),
+            ("fixed", places) => Ok(NumberFormat::Fixed(places.unwrap_or(2))),
+            ("mixed", None) => Ok(NumberFormat::Mixed),
+            ("scientific", places) => Ok(NumberFormat::Scientific(places.unwrap_or(6))),
+            _ => Err(error::NumberFormatParseError),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_number() {
+        let number: Number = "-6/4".parse().unwrap();
+
+        assert_eq!(number.to_string(), "-3/2");
+        assert_eq!("12".parse::<Number>().unwrap().to_string(), "12");
+        assert!("1/0".parse::<Number>().is_err());
+        assert!("1/-2".parse::<Number>().is_err());
+    }
+
+    #[test]
+    fn number_operators() {
+        let a: Number = "3/4".parse().unwrap();
+        let b = Number::from(-2);
+
+        assert_eq!((&a + &b).to_string(), "-5/4");
+        assert_eq!((&a - &b).to_string(), "11/4");
+        assert_eq!((&a * &b).to_string(), "-3/2");
+        assert_eq!((&a / &b).to_string(), "-3/8");
+        assert_eq!((-&a).to_string(), "-3/4");
+        assert_eq!(a.clone() + Number::from(1), "7/4".parse().unwrap());
+
+        let mut c = a.clone();
+        c *= &b;
+        c -= Number::one();
+        assert_eq!(c.to_string(), "-5/2");
+    }
+
+    #[test]
+    fn small_and_big_numbers() {
+        let max = Number::from(i64::MAX);
+        let big = &max + &Number::one();
+        assert_eq!(big.to_string(), "9223372036854775808");
+        assert!(matches!(big.0, Repr::Big(..)));
+
+        // results that fit again go back to machine integers
+        let back = &big - &Number::one();
+        assert_eq!(back, max);
+        assert!(matches!(back.0, Repr::Small(..)));
+        assert_eq!(-Number::from(i64::MIN), &big * &Number::one());
+
+        let third = "1/3".parse::<Number>().unwrap();
+        let tiny = &third / &Number::from(i64::MAX);
+        assert_eq!(tiny.denominator().to_string(), "27670116110564327421");
+        assert_eq!(&tiny * &Number::from(i64::MAX), third);
+        assert!(tiny < third && tiny > Number::zero());
+    }
+
+    #[test]
+    fn show_cycles() {
+        let bowl = Value::from_bowl(crate::parse("{[0; 7]}").unwrap());
+        let inner = Value::from_bowl(Bowl { noodles: vec![] });
+        if let (Value::Bowl(outer), Value::Bowl(inner_bowl)) = (&bowl, &inner) {
+            // the outer bowl holds the inner one, which holds both
+            let noodle = |nn: i64, value: &Value| Noodle {
+                nn_expr: Expr::ValueExpr(Value::from_number(&Number::from(nn))),
+                expr: Expr::ValueExpr(value.clone()),
+                line: None,
+            };
+            outer.borrow_mut().noodles.push(noodle(1, &inner));
+            inner_bowl.borrow_mut().noodles = vec![noodle(0, &bowl), noodle(1, &inner)];
+        }
+        assert_eq!(bowl.to_string(), "{[0; 7][1; {[0; {^2}][1; {^1}]}]}");
+        assert_eq!(inner.to_string(), "{[0; {[0; 7][1; {^2}]}][1; {^1}]}");
+        assert!(format!("{:?}", bowl).contains("Bowl(^2)"));
+    }
+
+    #[test]
+    fn number_formats() {
+        let number = |s: &str| s.parse::<Number>().unwrap();
+
+        assert_eq!(number("1/3").format(NumberFormat::Decimal), "0.(3)");
+        assert_eq!(number("-7/6").format(NumberFormat::Decimal), "-1.1(6)");
+        assert_eq!(number("22/7").format(NumberFormat::Decimal), "3.(142857)");
+        assert_eq!(number("-3/8").format(NumberFormat::Decimal), "-0.375");
+        assert_eq!(number("12").format(NumberFormat::Decimal), "12");
+        let long = number("1/1000003").format(NumberFormat::Decimal);
+        assert!(long.starts_with("0.000000999") && long.ends_with("..."));
+
+        assert_eq!(number("2/3").format(NumberFormat::Fixed(2)), "0.67");
+        assert_eq!(number("-5/2").format(NumberFormat::Fixed(0)), "-3");
+        assert_eq!(number("-1/1000").format(NumberFormat::Fixed(2)), "0.00");
+        assert_eq!(number("7").format(NumberFormat::Fixed(1)), "7.0");
+
+        assert_eq!(number("-7/2").format(NumberFormat::Mixed), "-3 1/2");
+        assert_eq!(number("1/2").format(NumberFormat::Mixed), "1/2");
+        assert_eq!(number("4").format(NumberFormat::Mixed), "4");
+
+        let huge = Number::from(BigInt::from(10).pow(40) * 3 / 7);
+        assert_eq!(huge.format(NumberFormat::Scientific(3)), "4.286e39");
+        assert_eq!(
+            number("-1/800").format(NumberFormat::Scientific(1)),
+            "-1.3e-3"
+        );
+        assert_eq!(number("9999").format(NumberFormat::Scientific(2)), "1.00e4");
+        assert_eq!(number("1000").format(NumberFormat::Scientific(0)), "1e3");
+        assert_eq!(Number::zero().format(NumberFormat::Scientific(1)), "0.0e0");
+
+        assert_eq!("fixed:3".parse(), Ok(NumberFormat::Fixed(3)));
+        assert_eq!("scientific".parse(), Ok(NumberFormat::Scientific(6)));
+        assert!("decimal:2".parse::<NumberFormat>().is_err());
+        assert_eq!("fixed:1000".parse(), Ok(NumberFormat::Fixed(1000)));
+        assert!("fixed:1001".parse::<NumberFormat>().is_err());
+        assert!("scientific:1000000000".parse::<NumberFormat>().is_err());
+        assert_eq!(
+            number("1/3").format(NumberFormat::Fixed(usize::MAX)),
+            number("1/3").format(NumberFormat::Fixed(1000))
+        );
+        assert!("roman".parse::<NumberFormat>().is_err());
+
+        let mut bowl = crate::parse("{[1/2; @:2 = {[0; 5/4]}]}").unwrap();
+        crate::optimize::optimize(&mut bowl, None);
+        assert_eq!(
+            Formatted(&bowl, NumberFormat::Decimal).to_string(),
+            "{[0.5; (@:(2) = ({[0; 1.25]}))]}"
+        );
+    }
+
+    #[test]
+    fn number_comparisons() {
+        let mut numbers: Vec<Number> = ["1/2", "-3", "2/4", "7/3", "0"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        numbers.sort();
+        let sorted: Vec<String> = numbers.iter().map(Number::to_string).collect();
+        assert_eq!(sorted, ["-3", "0", "1/2", "1/2", "7/3"]);
+
+        let set: std::collections::HashSet<Number> = numbers.into_iter().collect();
+        assert_eq!(set.len(), 4);
+        assert!(Number::from(1) > "2/3".parse().unwrap());
+    }
+
+    #[test]
+    fn number_conversions() {
+        assert_eq!(i64::try_from(Number::from(-42)).unwrap(), -42);
+        assert_eq!(u8::try_from(&Number::from(255)).unwrap(), 255);
+        assert!(u8::try_from(&Number::from(256)).is_err());
+        assert!(i64::try_from(&"1/2".parse::<Number>().unwrap()).is_err());
+
+        assert_eq!("-3/8".parse::<Number>().unwrap().to_f64(), -0.375);
+        // both terms are beyond the range of f64
+        let big = BigInt::from(3) * BigInt::from(10).pow(400) + 1;
+        let number = Number::new(big, BigUint::from(10u32).pow(400) * 2u32).unwrap();
+        assert!(number.denominator().bits() > 1024);
+        assert_eq!(number.to_f64(), 1.5);
+        // a huge numerator over a small denominator, and the other way round
+        let number = Number::new(BigInt::from(1) << 1009, BigUint::from(31u32)).unwrap();
+        assert_eq!(number.to_f64(), 2f64.powi(1009) / 31.0);
+        let number = Number::new(BigInt::from(31), BigUint::from(1u32) << 1009).unwrap();
+        assert_eq!(number.to_f64(), 31.0 / 2f64.powi(1009));
+        let number = Number::new(BigInt::from(-1), BigUint::from(3u32) << 2000).unwrap();
+        assert_eq!(number.to_f64(), -0.0);
+        let number = Number::new(BigInt::from(3) << 2000, BigUint::from(1u32)).unwrap();
+        assert_eq!(number.to_f64(), f64::INFINITY);
+        // terms too large for f64 keep the precision of their ratio
+        let third = BigUint::from(10u32).pow(400);
+        let number = Number::new(BigInt::from(third.clone()), third * 3u32 + 1u32).unwrap();
+        assert_eq!(number.to_f64(), 1.0 / 3.0);
+        let number = Number::ratio(i64::MAX, 7);
+        assert_eq!(number.to_f64(), 1317624576693539401.0);
+
+        const HALF: Number = Number::ratio(-2, 4);
+        assert_eq!(HALF, "-1/2".parse().unwrap());
+        assert_eq!(Number::ratio(i64::MIN, 1 << 63), Number::from(-1));
+        let big = BigInt::from(-7) * BigInt::from(2).pow(100);
+        let (_, digits) = big.to_u32_digits();
+        assert_eq!(
+            Number::from_digits(true, &digits, &[7]).unwrap(),
+            Number::from(big) / Number::from(7)
+        );
+        assert!(Number::from_digits(false, &[1], &[0]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let bowl = crate::parse("{[0; @:1 = {[0; 72]}][1/2; !(@:2)]}").unwrap();
+        let json = serde_json::to_string(&bowl).unwrap();
+        let decoded: Bowl = serde_json::from_str(&json).unwrap();
+
+        assert!(json.contains(r#"{"ValueExpr":{"Number":"72"}}"#));
+        assert_eq!(decoded.to_string(), bowl.to_string());
+    }
+}
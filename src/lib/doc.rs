@@ -0,0 +1,366 @@
+use std::fmt::Write;
+
+use crate::{
+    datatype::{Bowl, Expr, Number, Value},
+    env::Extensions,
+    lint::{const_expr, Lint},
+    optimize::fold_expr,
+    parse_with,
+    syntax::{self, BowlNode, NoodleNode},
+};
+
+#[derive(Debug, Clone)]
+pub struct CellDoc {
+    pub cell: String,
+    pub number: Option<Number>,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RoutineDoc {
+    pub function: CellDoc,
+    pub inputs: Vec<CellDoc>,
+    pub outputs: Vec<CellDoc>,
+    // noodle number of the first noodle after the annotations
+    pub entry: Option<String>,
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+// Collects routines annotated with `~# 함수: ... #~`, `~# 입력: ... #~` and
+// `~# 출력: ... #~` comments, and warns about documented cells the code never uses.
+pub fn document(code: &str, extensions: &Extensions) -> (Vec<RoutineDoc>, Vec<Lint>) {
+    let outline = syntax::scan(code);
+    let mut routines: Vec<RoutineDoc> = vec![];
+    for comment in &outline.comments {
+        let text = comment_text(&code[comment.start..comment.end]);
+        if let Some(rest) = strip_label(text, "함수") {
+            routines.push(RoutineDoc {
+                function: cell_doc(rest, extensions),
+                inputs: vec![],
+                outputs: vec![],
+                entry: None,
+                line: syntax::line_of(code, comment.start),
+                start: comment.start,
+                end: comment.end,
+            });
+            continue;
+        }
+        let routine = match routines.last_mut() {
+            Some(routine) if !has_noodle_between(&outline.bowls, routine.end, comment.start) => {
+                routine
+            }
+            _ => continue,
+        };
+        if let Some(rest) = strip_label(text, "입력") {
+            routine.inputs.extend(cell_docs(rest, extensions));
+            routine.end = comment.end;
+        } else if let Some(rest) = strip_label(text, "출력") {
+            routine.outputs.extend(cell_docs(rest, extensions));
+            routine.end = comment.end;
+        }
+    }
+    for routine in routines.iter_mut() {
+        routine.entry = first_noodle_after(&outline.bowls, routine.end).map(|noodle| {
+            let (start, end) = noodle.nn_range();
+            code[start..end].trim().to_string()
+        });
+    }
+
+    let mut lints = vec![];
+    if let Ok(bowl) = parse_with(code, extensions) {
+        let mut cells = vec![];
+        used_cells(&bowl, &mut cells);
+        for routine in &routines {
+            let documented = std::iter::once(&routine.function)
+                .chain(&routine.inputs)
+                .chain(&routine.outputs);
+            for doc in documented {
+                if let Some(number) = &doc.number {
+                    if !cells.iter().any(|cell: &Number| cell == number) {
+                        lints.push(Lint {
+                            start: routine.start,
+                            end: routine.end,
+                            message: format!(
+                                "documented cell {} is never read or written",
+                                doc.cell
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    (routines, lints)
+}
+
+fn comment_text(comment: &str) -> &str {
+    comment
+        .trim_start_matches('~')
+        .trim()
+        .trim_start_matches('#')
+        .trim_end_matches('~')
+        .trim()
+        .trim_end_matches('#')
+        .trim()
+}
+
+fn strip_label<'a>(text: &'a str, label: &str) -> Option<&'a str> {
+    text.strip_prefix(label)?
+        .trim_start()
+        .strip_prefix(':')
+        .map(str::trim)
+}
+
+fn cell_doc(text: &str, extensions: &Extensions) -> CellDoc {
+    let (cell, description) = match text.split_once('=') {
+        Some((cell, description)) => (cell.trim(), description.trim()),
+        None => (text.trim(), ""),
+    };
+    CellDoc {
+        cell: cell.to_string(),
+        number: cell
+            .strip_prefix("@:")
+            .and_then(|cell| const_expr(cell, extensions)),
+        description: description.to_string(),
+    }
+}
+
+// Splits `@:2/5 = a, @:3/5 = b` into cells. A comma not followed by a cell
+// belongs to the description.
+fn cell_docs(text: &str, extensions: &Extensions) -> Vec<CellDoc> {
+    let mut parts: Vec<String> = vec![];
+    for part in text.split(',') {
+        match parts.last_mut() {
+            Some(last) if !part.trim_start().starts_with("@:") => {
+                last.push(',');
+                last.push_str(part);
+            }
+            _ => parts.push(part.to_string()),
+        }
+    }
+    parts
+        .iter()
+        .map(|part| cell_doc(part, extensions))
+        .collect()
+}
+
+fn noodles(bowls: &[BowlNode]) -> Vec<&NoodleNode> {
+    let mut result = vec![];
+    for bowl in bowls {
+        for noodle in &bowl.noodles {
+            result.push(noodle);
+            result.extend(noodles(&noodle.bowls));
+        }
+    }
+    result
+}
+
+fn first_noodle_after(bowls: &[BowlNode], offset: usize) -> Option<&NoodleNode> {
+    noodles(bowls)
+        .into_iter()
+        .filter(|noodle| noodle.start >= offset)
+        .min_by_key(|noodle| noodle.start)
+}
+
+fn has_noodle_between(bowls: &[BowlNode], start: usize, end: usize) -> bool {
+    first_noodle_after(bowls, start).is_some_and(|noodle| noodle.start < end)
+}
+
+fn used_cells(bowl: &Bowl, cells: &mut Vec<Number>) {
+    for noodle in &bowl.noodles {
+        used_cells_expr(&noodle.nn_expr, cells);
+        used_cells_expr(&noodle.expr, cells);
+    }
+}
+
+fn used_cells_expr(expr: &Expr, cells: &mut Vec<Number>) {
+    match expr {
+        Expr::ValueExpr(Value::Bowl(bowl)) => used_cells(&bowl.borrow(), cells),
+        Expr::ValueExpr(_) => {}
+        Expr::MemReadExpr(nn_expr) | Expr::MemWriteExpr(nn_expr, _) => {
+            let mut nn_expr = *nn_expr.clone();
+            fold_expr(&mut nn_expr, None);
+            if let Expr::ValueExpr(Value::Number(number)) = &nn_expr {
+                cells.push(*number.clone());
+            }
+            used_cells_expr(&nn_expr, cells);
+            if let Expr::MemWriteExpr(_, value_expr) = expr {
+                used_cells_expr(value_expr, cells);
+            }
+        }
+        Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+            used_cells_expr(expr1, cells);
+            used_cells_expr(expr2, cells);
+            used_cells_expr(expr3, cells);
+        }
+        Expr::BowlReadExpr(expr1, expr2)
+        | Expr::PlusFuncExpr(expr1, expr2)
+        | Expr::MinusFuncExpr(expr1, expr2)
+        | Expr::MulFuncExpr(expr1, expr2)
+        | Expr::NumberSepFuncExpr(expr1, expr2)
+        | Expr::AndFuncExpr(expr1, expr2)
+        | Expr::OrFuncExpr(expr1, expr2)
+        | Expr::EqFuncExpr(expr1, expr2)
+        | Expr::GtFuncExpr(expr1, expr2)
+        | Expr::LtFuncExpr(expr1, expr2) => {
+            used_cells_expr(expr1, cells);
+            used_cells_expr(expr2, cells);
+        }
+        Expr::DenoFuncExpr(expr) | Expr::NotFuncExpr(expr) => used_cells_expr(expr, cells),
+    }
+}
+
+pub fn to_markdown(routines: &[RoutineDoc]) -> String {
+    let mut result = String::new();
+    for routine in routines {
+        writeln!(result, "## `{}`", routine.function.cell).unwrap();
+        writeln!(result).unwrap();
+        if !routine.function.description.is_empty() {
+            writeln!(result, "{}", routine.function.description).unwrap();
+            writeln!(result).unwrap();
+        }
+        match &routine.entry {
+            Some(entry) => writeln!(result, "Entry noodle: `{}` (line {})", entry, routine.line),
+            None => writeln!(result, "Entry noodle: none (line {})", routine.line),
+        }
+        .unwrap();
+        for (title, cells) in [("Inputs", &routine.inputs), ("Outputs", &routine.outputs)] {
+            if cells.is_empty() {
+                continue;
+            }
+            writeln!(result).unwrap();
+            writeln!(result, "| {} | Description |", title).unwrap();
+            writeln!(result, "| --- | --- |").unwrap();
+            for cell in cells {
+                writeln!(
+                    result,
+                    "| `{}` | {} |",
+                    cell.cell,
+                    cell.description.replace('|', "\\|")
+                )
+                .unwrap();
+            }
+        }
+        writeln!(result).unwrap();
+    }
+    result
+}
+
+pub fn to_html(routines: &[RoutineDoc]) -> String {
+    let mut result = String::new();
+    writeln!(result, "<!DOCTYPE html>").unwrap();
+    writeln!(result, "<html><head><meta charset=\"utf-8\"></head><body>").unwrap();
+    for routine in routines {
+        writeln!(
+            result,
+            "<h2><code>{}</code></h2>",
+            escape(&routine.function.cell)
+        )
+        .unwrap();
+        if !routine.function.description.is_empty() {
+            writeln!(result, "<p>{}</p>", escape(&routine.function.description)).unwrap();
+        }
+        match &routine.entry {
+            Some(entry) => writeln!(
+                result,
+                "<p>Entry noodle: <code>{}</code> (line {})</p>",
+                escape(entry),
+                routine.line
+            ),
+            None => writeln!(result, "<p>Entry noodle: none (line {})</p>", routine.line),
+        }
+        .unwrap();
+        for (title, cells) in [("Inputs", &routine.inputs), ("Outputs", &routine.outputs)] {
+            if cells.is_empty() {
+                continue;
+            }
+            writeln!(result, "<table>").unwrap();
+            writeln!(result, "<tr><th>{}</th><th>Description</th></tr>", title).unwrap();
+            for cell in cells {
+                writeln!(
+                    result,
+                    "<tr><td><code>{}</code></td><td>{}</td></tr>",
+                    escape(&cell.cell),
+                    escape(&cell.description)
+                )
+                .unwrap();
+            }
+            writeln!(result, "</table>").unwrap();
+        }
+    }
+    writeln!(result, "</body></html>").unwrap();
+    result
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "{
+[0; @:2/5 = 7]
+[1; @:3/5 = 2]
+[1/2; @:1/2 = @:0 + 1]
+[@:2; @:9 = @:4/5]
+
+~# 함수: @:1/2 = 정수의 길이를 반환 #~
+~# 입력: @:2/5 = 확인할 정수, 음수는 안 됨, @:3/5 = 함수 종료 후 이동할 위치 #~
+~# 출력: @:4/5 = 확인한 정수의 길이, @:6/5 = 쓰이지 않는 칸 #~
+[@:1/2; @:4/5 = 1]
+[@:1/2 + 1; @:(@:3/5) = @:0 + 1]
+}";
+
+    #[test]
+    fn document_routine() {
+        let (routines, _) = document(CODE, &Extensions::default());
+        assert_eq!(routines.len(), 1);
+        let routine = &routines[0];
+        assert_eq!(routine.function.cell, "@:1/2");
+        assert_eq!(routine.function.description, "정수의 길이를 반환");
+        assert_eq!(routine.entry.as_deref(), Some("@:1/2"));
+        assert_eq!(routine.line, 7);
+        let inputs: Vec<&str> = routine
+            .inputs
+            .iter()
+            .map(|cell| cell.description.as_str())
+            .collect();
+        assert_eq!(
+            inputs,
+            vec!["확인할 정수, 음수는 안 됨", "함수 종료 후 이동할 위치"]
+        );
+        assert_eq!(routine.outputs.len(), 2);
+
+        let markdown = to_markdown(&routines);
+        assert!(markdown
+            .contains("## `@:1/2`\n\n정수의 길이를 반환\n\nEntry noodle: `@:1/2` (line 7)\n"));
+        assert!(markdown.contains("| `@:3/5` | 함수 종료 후 이동할 위치 |\n"));
+        assert!(to_html(&routines)
+            .contains("<tr><td><code>@:4/5</code></td><td>확인한 정수의 길이</td></tr>"));
+    }
+
+    #[test]
+    fn document_unused_cells() {
+        let (_, lints) = document(CODE, &Extensions::default());
+        let messages: Vec<String> = lints.into_iter().map(|lint| lint.message).collect();
+        assert_eq!(
+            messages,
+            vec!["documented cell @:6/5 is never read or written"]
+        );
+    }
+
+    #[test]
+    fn document_comments_after_noodle() {
+        let code = "{~# 함수: @:1/2 = a #~ [@:1/2; 0] ~# 입력: @:2/5 = b #~ [1; @:2/5 = 1]}";
+        let (routines, _) = document(code, &Extensions::default());
+        assert_eq!(routines.len(), 1);
+        assert!(routines[0].inputs.is_empty());
+    }
+}
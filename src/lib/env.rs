@@ -1,16 +1,183 @@
+use std::{cell::RefCell, error::Error, mem, rc::Rc, time::Instant};
+
 use num_bigint::{BigInt, BigUint, Sign};
 
 use crate::{
-    datatype::{Bowl, Expr, Noodle, Number, Value},
+    coverage::Coverage,
+    datatype::{Bowl, Expr, Noodle, Number, NumberFormat, Value},
+    error,
     eval::eval_expr,
+    gc,
+    profile::Profiler,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    #[default]
+    Tree,
+    Vm,
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+// Opt-in language extensions, off by default so programs keep the semantics of
+// the reference implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Extensions {
+    // `-5`, `0.25`, `0x48`, `0b101`, and fractions of them such as `1/3` read as
+    // single numbers
+    pub extended_literals: bool,
+    // `?=` comparing bowls and NULL structurally, as `Value::structural_eq` does
+    pub structural_eq: bool,
+}
+
+impl Extensions {
+    // Enables an extension by its command line name, returning whether it
+    // exists.
+    pub fn enable(&mut self, name: &str) -> bool {
+        match name {
+            "extended-literals" => self.extended_literals = true,
+            "structural-eq" => self.structural_eq = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+// What assigning a bowl gives: the bowl itself, shared by every place it is
+// assigned to, or a value of its own, copied once either place writes to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Assignment {
+    #[default]
+    Reference,
+    Value,
+}
+
+// the fewest overwritten bowls swept for cycles at once
+const MIN_SWEEP: usize = 1024;
+
+// Why the evaluation of a noodle stopped, until the engine reports it.
+#[derive(Debug, Clone, Copy)]
+enum Stop {
+    // a number of this many bits, over `max_bits`
+    TooLarge(u64),
+    TooDeep,
+    TimeUp,
+}
+
 pub struct Env<'a> {
     pub mem: Vec<(Number, Value)>,
     pub cursor: Option<Number>,
     pub is_debug: bool,
+    pub number_format: NumberFormat,
+    pub is_optimize: bool,
+    pub engine: Engine,
+    pub extensions: Extensions,
+    pub assignment: Assignment,
+    // the largest number of bits of a numerator or denominator
+    pub max_bits: Option<u64>,
+    // the deepest evaluations of noodles in bowls may nest, as bowls are read
+    pub max_depth: Option<usize>,
+    // when evaluation stops, checked as noodles in bowls are evaluated
+    pub deadline: Option<Instant>,
+    depth: usize,
+    entered: usize,
+    stop: Option<Stop>,
+    // bowls overwritten since the last sweep, which may have left cycles
+    // unreachable
+    garbage: Vec<Value>,
+    next_sweep: usize,
+    pub profiler: Option<Profiler>,
+    pub coverage: Option<Coverage>,
     pub on_read_io: Box<dyn Fn() -> Vec<u8> + 'a>,
-    pub on_write_io: Box<dyn Fn(Vec<u8>) -> () + 'a>,
+    pub on_write_io: Box<dyn Fn(Vec<u8>) + 'a>,
+}
+
+impl<'a> Env<'a> {
+    // An environment with the defaults: the tree walking engine running the
+    // reference semantics, without debug output, limits or instrumentation.
+    pub fn new(
+        on_read_io: impl Fn() -> Vec<u8> + 'a,
+        on_write_io: impl Fn(Vec<u8>) + 'a,
+    ) -> Env<'a> {
+        Env {
+            cursor: None,
+            mem: vec![],
+            is_debug: false,
+            number_format: NumberFormat::Fraction,
+            is_optimize: false,
+            engine: Engine::Tree,
+            extensions: Extensions::default(),
+            assignment: Assignment::Reference,
+            max_bits: None,
+            max_depth: None,
+            deadline: None,
+            depth: 0,
+            entered: 0,
+            stop: None,
+            garbage: vec![],
+            next_sweep: MIN_SWEEP,
+            profiler: None,
+            coverage: None,
+            on_read_io: Box::new(on_read_io),
+            on_write_io: Box::new(on_write_io),
+        }
+    }
+
+    pub fn with_debug(mut self, is_debug: bool) -> Env<'a> {
+        self.is_debug = is_debug;
+        self
+    }
+
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Env<'a> {
+        self.number_format = number_format;
+        self
+    }
+
+    pub fn with_optimize(mut self, is_optimize: bool) -> Env<'a> {
+        self.is_optimize = is_optimize;
+        self
+    }
+
+    pub fn with_engine(mut self, engine: Engine) -> Env<'a> {
+        self.engine = engine;
+        self
+    }
+
+    pub fn with_extensions(mut self, extensions: Extensions) -> Env<'a> {
+        self.extensions = extensions;
+        self
+    }
+
+    pub fn with_assignment(mut self, assignment: Assignment) -> Env<'a> {
+        self.assignment = assignment;
+        self
+    }
+
+    pub fn with_max_bits(mut self, max_bits: Option<u64>) -> Env<'a> {
+        self.max_bits = max_bits;
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Env<'a> {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Env<'a> {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn with_profiler(mut self, profiler: Option<Profiler>) -> Env<'a> {
+        self.profiler = profiler;
+        self
+    }
+
+    pub fn with_coverage(mut self, coverage: Option<Coverage>) -> Env<'a> {
+        self.coverage = coverage;
+        self
+    }
 }
 
 impl Env<'_> {
@@ -25,24 +192,25 @@ impl Env<'_> {
                     &BigInt::from(byte),
                     &BigUint::from(1u32),
                 )),
+                line: None,
             });
-            index = index.add(&Number::one());
+            index += Number::one();
         }
         Bowl { noodles }
     }
 
-    pub fn write_io(&mut self, bowl: &Bowl) {
+    pub fn write_io(&mut self, bowl: &Rc<RefCell<Bowl>>) {
         let mut data = vec![];
         let mut nn_index = Number::zero();
 
         loop {
-            let value = bowl.read(self, &Value::from_number(&nn_index));
+            let value = Bowl::read(bowl, self, &Value::from_number(&nn_index));
             if let Value::Number(number) = value {
-                if number.denominator != BigUint::from(1u32) {
+                if !number.is_integer() {
                     // panic!("Cannot write non-integer value");
                     break;
                 }
-                let num_vec = number.numerator.to_bytes_be();
+                let num_vec = number.numerator().to_bytes_be();
                 if num_vec.0 == Sign::Minus {
                     // panic!("Cannot write negative value");
                     break;
@@ -52,7 +220,7 @@ impl Env<'_> {
                     break;
                 }
                 data.push(num_vec.1[0]);
-                nn_index = nn_index.add(&Number::one());
+                nn_index += Number::one();
             } else {
                 break;
             }
@@ -60,20 +228,123 @@ impl Env<'_> {
         (self.on_write_io)(data);
     }
 
-    pub fn read_mem(&self, noodle_number: &Value) -> Value {
+    // The result of an arithmetic operation, or NULL stopping the evaluation
+    // when it is over `max_bits`.
+    pub fn limit(&mut self, number: Number) -> Value {
+        if let Some(max_bits) = self.max_bits {
+            let bits = number.bits();
+            if bits > max_bits {
+                self.stop.get_or_insert(Stop::TooLarge(bits));
+                return Value::Null;
+            }
+        }
+        Value::Number(Box::new(number))
+    }
+
+    // Whether a limit was reached, after which engines evaluate nothing more
+    // and write nothing until `check_limit` reports it.
+    pub fn is_stopped(&self) -> bool {
+        self.stop.is_some()
+    }
+
+    // Enters the evaluation of a noodle in a bowl, returning whether to go on
+    // with it. Every evaluation entered is left with `leave`, even when it
+    // stops.
+    pub fn enter(&mut self) -> bool {
+        self.depth += 1;
+        if self.stop.is_some() {
+            return false;
+        }
+        if self
+            .max_depth
+            .is_some_and(|max_depth| self.depth > max_depth)
+        {
+            self.stop = Some(Stop::TooDeep);
+            return false;
+        }
+        // the clock is only read now and then, as bowls are read often
+        self.entered = self.entered.wrapping_add(1);
+        if self.entered.is_multiple_of(1024) && self.deadline.is_some_and(|at| Instant::now() >= at)
+        {
+            self.stop = Some(Stop::TimeUp);
+            return false;
+        }
+        true
+    }
+
+    pub fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    // Reports a limit reached while evaluating `noodle`.
+    pub fn check_limit(&mut self, noodle: &Noodle) -> Result<(), Box<dyn Error>> {
+        let stop = match self.stop.take() {
+            Some(stop) => stop,
+            None => return Ok(()),
+        };
+        let (line, noodle) = (noodle.line, noodle.to_string());
+        Err(match stop {
+            Stop::TooLarge(bits) => Box::new(error::NumberTooLargeError {
+                line,
+                noodle,
+                bits,
+                max_bits: self.max_bits.unwrap_or(0),
+            }),
+            Stop::TooDeep => Box::new(error::DepthLimitError {
+                line,
+                noodle,
+                max_depth: self.max_depth.unwrap_or(0),
+            }),
+            Stop::TimeUp => Box::new(error::TimeLimitError { line, noodle }),
+        })
+    }
+
+    // Drops a value a write replaced. Bowls are kept until `sweep` looks for
+    // the cycles they may have left unreachable.
+    pub fn discard(&mut self, value: Value) {
+        if let Value::Bowl(_) = value {
+            self.garbage.push(value);
+        }
+    }
+
+    // Frees the cycles overwritten bowls left unreachable, once there are
+    // enough of them. Engines sweep between noodles, when no evaluation holds
+    // a bowl.
+    pub fn sweep(&mut self) {
+        if self.garbage.len() < self.next_sweep {
+            return;
+        }
+        let collected = gc::collect(mem::take(&mut self.garbage));
+        // waiting for as many writes as bowls found alive keeps the cost of
+        // sweeping to a share of each write
+        self.next_sweep = MIN_SWEEP.max(collected.alive);
+    }
+
+    // Empties the memory once a program is done, freeing the bowls it and the
+    // values overwritten held, cycles included.
+    pub fn collect(&mut self) {
+        let mut values = mem::take(&mut self.garbage);
+        values.extend(mem::take(&mut self.mem).into_iter().map(|(_, value)| value));
+        gc::collect(values);
+    }
+
+    pub fn read_mem(&mut self, noodle_number: &Value) -> Value {
         if let Value::Number(nn_number) = noodle_number {
-            if nn_number.eq(&Number::zero()) {
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.read_cell(nn_number);
+            }
+            if **nn_number == Number::zero() {
                 return match self.cursor {
-                    Some(ref cursor) => Value::from_number(&cursor),
+                    Some(ref cursor) => Value::from_number(cursor),
                     None => Value::Null,
                 };
             }
-            if nn_number.eq(&Number::one()) {
+            if **nn_number == Number::one() {
                 return Value::from_bowl(self.read_io());
             }
             for noodle_like in &self.mem {
                 let inner_nn = &noodle_like.0;
-                if inner_nn.eq(nn_number) {
+                if inner_nn == &**nn_number {
                     return noodle_like.1.clone();
                 }
             }
@@ -83,25 +354,28 @@ impl Env<'_> {
 
     pub fn write_mem(&mut self, noodle_number: &Value, value: &Value) {
         if let Value::Number(nn_number) = noodle_number {
-            if nn_number.eq(&Number::one()) {
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.write_cell(nn_number);
+            }
+            if **nn_number == Number::one() {
                 if let Value::Bowl(bowl) = value {
-                    return self.write_io(&bowl.borrow());
+                    return self.write_io(bowl);
                 } else {
                     // panic!("Cannot write non-bowl value");
                     return;
                 }
             }
-            let mut is_written = false;
+            let mut overwritten = None;
             for noodle_like in self.mem.iter_mut() {
                 let inner_nn = &noodle_like.0;
-                if inner_nn.eq(nn_number) {
-                    noodle_like.1 = value.clone();
-                    is_written = true;
+                if inner_nn == &**nn_number {
+                    overwritten = Some(mem::replace(&mut noodle_like.1, value.clone()));
                     break;
                 }
             }
-            if !is_written {
-                self.mem.push((*nn_number.clone(), value.clone()));
+            match overwritten {
+                Some(overwritten) => self.discard(overwritten),
+                None => self.mem.push((*nn_number.clone(), value.clone())),
             }
         }
     }
@@ -109,7 +383,7 @@ impl Env<'_> {
     pub fn is_nextable(&mut self, noodle_number: &Value) -> bool {
         match noodle_number {
             Value::Number(noodle_number_number) => match &self.cursor {
-                Some(current_cursor_number) => noodle_number_number.gt(current_cursor_number),
+                Some(current_cursor_number) => **noodle_number_number > *current_cursor_number,
                 _ => true,
             },
             _ => false,
@@ -117,30 +391,37 @@ impl Env<'_> {
     }
 
     pub fn get_next_noodle(&mut self, bowl: &Bowl) -> Option<Noodle> {
+        self.get_next_noodle_index(bowl)
+            .map(|index| bowl.noodles[index].clone())
+    }
+
+    pub fn get_next_noodle_index(&mut self, bowl: &Bowl) -> Option<usize> {
         let mut min_nextable_noodle_number = Value::Null;
         let mut min_nextable_noodle = None;
-        for noodle in &bowl.noodles {
+        for (index, noodle) in bowl.noodles.iter().enumerate() {
             let noodle_number = eval_expr(self, &noodle.nn_expr);
+            if self.is_stopped() {
+                // the engine reports the number as coming from this noodle
+                return Some(index);
+            }
             if self.is_nextable(&noodle_number) {
                 match (&min_nextable_noodle_number, &noodle_number) {
                     (Value::Null, _) => {
                         min_nextable_noodle_number = noodle_number.clone();
-                        min_nextable_noodle = Some(noodle);
+                        min_nextable_noodle = Some(index);
                     }
                     (
                         Value::Number(min_nextable_noodle_number_number),
                         Value::Number(noodle_number_number),
-                    ) => {
-                        if noodle_number_number.lt(&min_nextable_noodle_number_number) {
-                            min_nextable_noodle_number = noodle_number.clone();
-                            min_nextable_noodle = Some(noodle);
-                        }
+                    ) if noodle_number_number < min_nextable_noodle_number_number => {
+                        min_nextable_noodle_number = noodle_number.clone();
+                        min_nextable_noodle = Some(index);
                     }
                     _ => {}
                 }
             }
         }
-        min_nextable_noodle.cloned()
+        min_nextable_noodle
     }
 
     pub fn mem_to_bowl(&self) -> Bowl {
@@ -149,6 +430,7 @@ impl Env<'_> {
             noodles.push(Noodle {
                 nn_expr: Expr::ValueExpr(Value::from_number(&noodle_like.0)),
                 expr: Expr::ValueExpr(noodle_like.1.clone()),
+                line: None,
             });
         }
         Bowl { noodles }
@@ -9,6 +9,43 @@ impl fmt::Display for ParseError {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+impl error::Error for AsmError {}
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+impl error::Error for CompileError {}
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxError {
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+impl error::Error for SyntaxError {}
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ZeroDenominatorError;
 impl error::Error for ZeroDenominatorError {}
@@ -17,3 +54,105 @@ impl fmt::Display for ZeroDenominatorError {
         write!(f, "denominator cannot be zero")
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct NumberParseError;
+impl error::Error for NumberParseError {}
+impl fmt::Display for NumberParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid number, expected `numerator` or `numerator/denominator`"
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFormatParseError;
+impl error::Error for NumberFormatParseError {}
+impl fmt::Display for NumberFormatParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid number format, expected `fraction`, `decimal`, `fixed:<places>`, `mixed` or `scientific:<places>`, with at most {} places",
+            crate::datatype::MAX_DECIMAL_PLACES
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NumberRangeError;
+impl error::Error for NumberRangeError {}
+impl fmt::Display for NumberRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "number is not an integer in range")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodeError {
+    pub offset: usize,
+    pub message: String,
+}
+impl error::Error for DecodeError {}
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "offset {}: {}", self.offset, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NumberTooLargeError {
+    pub line: Option<usize>,
+    pub noodle: String,
+    pub bits: u64,
+    pub max_bits: u64,
+}
+impl error::Error for NumberTooLargeError {}
+impl fmt::Display for NumberTooLargeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(
+            f,
+            "a number of {} bits is over the limit of {} bits, in {}",
+            self.bits, self.max_bits, self.noodle
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DepthLimitError {
+    pub line: Option<usize>,
+    pub noodle: String,
+    pub max_depth: usize,
+}
+impl error::Error for DepthLimitError {}
+impl fmt::Display for DepthLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(
+            f,
+            "evaluation is nested over the limit of {} levels, in {}",
+            self.max_depth, self.noodle
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeLimitError {
+    pub line: Option<usize>,
+    pub noodle: String,
+}
+impl error::Error for TimeLimitError {}
+impl fmt::Display for TimeLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(f, "ran out of time, in {}", self.noodle)
+    }
+}
@@ -1,20 +1,58 @@
-use std::error;
+use std::{cell::RefCell, error, mem, rc::Rc, time::Instant};
 
 use num_bigint::{BigUint, ToBigInt};
 
 use crate::{
-    datatype::{Bowl, Expr, Noodle, Number, Value},
-    env::Env,
+    datatype::{Bowl, Expr, Formatted, Noodle, Number, Value},
+    env::{Assignment, Env},
 };
 
 pub fn eval(env: &mut Env, bowl: Bowl) -> Result<bool, Box<dyn error::Error>> {
-    while let Some(noodle) = env.get_next_noodle(&bowl) {
+    eval_steps(env, &bowl, None)
+}
+
+// Evaluates at most `steps` noodles, returning whether the bowl has finished.
+pub fn eval_steps(
+    env: &mut Env,
+    bowl: &Bowl,
+    steps: Option<usize>,
+) -> Result<bool, Box<dyn error::Error>> {
+    let mut step = 0;
+    loop {
+        if steps == Some(step) {
+            return Ok(false);
+        }
+        step += 1;
+        env.sweep();
+        let scan_started = env.profiler.as_ref().map(|_| Instant::now());
+        let index = match env.get_next_noodle_index(bowl) {
+            Some(index) => index,
+            None => {
+                if let (Some(profiler), Some(started)) = (env.profiler.as_mut(), scan_started) {
+                    profiler.scan_time += started.elapsed();
+                }
+                break;
+            }
+        };
+        let noodle = &bowl.noodles[index];
+        env.check_limit(noodle)?;
+        if let (Some(profiler), Some(started)) = (env.profiler.as_mut(), scan_started) {
+            profiler.select(index, noodle, started.elapsed());
+        }
+        if let Some(coverage) = env.coverage.as_mut() {
+            coverage.hit_noodle(noodle);
+        }
+        let eval_started = env.profiler.as_ref().map(|_| Instant::now());
         if env.is_debug {
-            println!("[=] noodle: {}", noodle);
+            println!("[=] noodle: {}", Formatted(noodle, env.number_format));
         }
         let new_cursor = eval_expr(env, &noodle.nn_expr);
+        env.check_limit(noodle)?;
         if env.is_debug {
-            println!("[.] noodle number: {}", new_cursor);
+            println!(
+                "[.] noodle number: {}",
+                Formatted(&new_cursor, env.number_format)
+            );
         }
         if let Value::Number(number) = new_cursor {
             env.cursor = Some(*number.clone());
@@ -22,22 +60,44 @@ pub fn eval(env: &mut Env, bowl: Bowl) -> Result<bool, Box<dyn error::Error>> {
             panic!("Cannot set cursor to non-number value");
         }
         eval_expr(env, &noodle.expr);
+        env.check_limit(noodle)?;
+        if let (Some(profiler), Some(started)) = (env.profiler.as_mut(), eval_started) {
+            profiler.finish(started.elapsed());
+        }
         if env.is_debug {
-            println!("[.] cursor: {}", env.cursor.as_ref().unwrap());
-            println!("[.] mem state: {}", env.mem_to_bowl());
+            println!(
+                "[.] cursor: {}",
+                env.cursor.as_ref().unwrap().format(env.number_format)
+            );
+            println!(
+                "[.] mem state: {}",
+                Formatted(&env.mem_to_bowl(), env.number_format)
+            );
         }
     }
     Ok(true)
 }
 
 pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
+    if env.is_stopped() {
+        return Value::Null;
+    }
     match expr {
+        Expr::ValueExpr(Value::Bowl(bowl)) => Value::Bowl(new_bowl(bowl, &mut |_, _| {})),
         Expr::ValueExpr(value) => value.clone(),
         Expr::BowlReadExpr(bowl_expr, nn_expr) => {
+            // a literal is read in place, finding what a new bowl from it would
+            if let Expr::ValueExpr(Value::Bowl(bowl)) = &**bowl_expr {
+                let nn = eval_expr(env, nn_expr);
+                return match nn {
+                    Value::Number(_) => Bowl::read_with(bowl, env, &nn, eval_expr),
+                    _ => Value::Null,
+                };
+            }
             let bowl = eval_expr(env, bowl_expr);
             let nn = eval_expr(env, nn_expr);
             if let (Value::Bowl(bowl), Value::Number(_)) = (bowl, &nn) {
-                bowl.borrow().read(env, &nn)
+                Bowl::read(&bowl, env, &nn)
             } else {
                 Value::Null
             }
@@ -50,13 +110,26 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
                 Value::Null
             }
         }
+        Expr::BowlWriteExpr(bowl_expr, nn_expr, value_expr)
+            if env.assignment == Assignment::Value =>
+        {
+            let place = place(env, bowl_expr);
+            let nn = eval_expr(env, nn_expr);
+            let value = eval_expr(env, value_expr);
+            if let (Value::Number(_), false) = (&nn, env.is_stopped()) {
+                if let Some(bowl) = unshare(env, place, &mut Tree) {
+                    Bowl::write(&bowl, env, &nn, &value);
+                }
+            }
+            Value::Null
+        }
         Expr::BowlWriteExpr(bowl_expr, nn_expr, value_expr) => {
             let bowl = eval_expr(env, bowl_expr);
             let nn = eval_expr(env, nn_expr);
             let value = eval_expr(env, value_expr);
             // println!("BowlWriteExpr: {}, {}, {}", bowl, nn, value);
-            if let (Value::Bowl(bowl), Value::Number(_)) = (bowl, &nn) {
-                bowl.borrow_mut().write(env, &nn, &value);
+            if let (Value::Bowl(bowl), Value::Number(_), false) = (bowl, &nn, env.is_stopped()) {
+                Bowl::write(&bowl, env, &nn, &value);
             }
             Value::Null
         }
@@ -64,7 +137,7 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
             let nn = eval_expr(env, nn_expr);
             let value = eval_expr(env, value_expr);
             // println!("MemWriteExpr: {}", value);
-            if let Value::Number(_) = nn {
+            if let (Value::Number(_), false) = (&nn, env.is_stopped()) {
                 env.write_mem(&nn, &value);
             }
             Value::Null
@@ -73,7 +146,7 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
             let value = eval_expr(env, expr);
             if let Value::Number(number) = value {
                 Value::from_big_int(
-                    &number.denominator.to_bigint().unwrap(),
+                    &number.denominator().to_bigint().unwrap(),
                     &BigUint::from(1u32),
                 )
             } else {
@@ -84,7 +157,7 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
             let value1 = eval_expr(env, expr1);
             let value2 = eval_expr(env, expr2);
             if let (Value::Number(number1), Value::Number(number2)) = (value1, value2) {
-                Value::from_number(&number1.add(&number2))
+                env.limit(&*number1 + &*number2)
             } else {
                 Value::Null
             }
@@ -93,7 +166,7 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
             let value1 = eval_expr(env, expr1);
             let value2 = eval_expr(env, expr2);
             if let (Value::Number(number1), Value::Number(number2)) = (value1, value2) {
-                Value::from_number(&number1.sub(&number2))
+                env.limit(&*number1 - &*number2)
             } else {
                 Value::Null
             }
@@ -102,7 +175,7 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
             let value1 = eval_expr(env, expr1);
             let value2 = eval_expr(env, expr2);
             if let (Value::Number(number1), Value::Number(number2)) = (value1, value2) {
-                Value::from_number(&number1.mul(&number2))
+                env.limit(&*number1 * &*number2)
             } else {
                 Value::Null
             }
@@ -111,7 +184,7 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
             let value1 = eval_expr(env, expr1);
             let value2 = eval_expr(env, expr2);
             if let (Value::Number(number1), Value::Number(number2)) = (value1, value2) {
-                Value::from_number(&number1.div(&number2))
+                env.limit(&*number1 / &*number2)
             } else {
                 Value::Null
             }
@@ -145,7 +218,7 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
         Expr::NotFuncExpr(expr) => {
             let value = eval_expr(env, expr);
             if let Value::Number(number) = value {
-                if number.eq(&Number::one()) {
+                if *number == Number::one() {
                     Value::new_zero()
                 } else {
                     Value::new_one()
@@ -157,7 +230,13 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
         Expr::EqFuncExpr(expr1, expr2) => {
             let value1 = eval_expr(env, expr1);
             let value2 = eval_expr(env, expr2);
-            if let (Value::Number(number1), Value::Number(number2)) = (value1, value2) {
+            if env.extensions.structural_eq {
+                if value1.structural_eq(&value2, env) {
+                    Value::new_one()
+                } else {
+                    Value::new_zero()
+                }
+            } else if let (Value::Number(number1), Value::Number(number2)) = (value1, value2) {
                 if number1.eq(&number2) {
                     Value::new_one()
                 } else {
@@ -196,43 +275,490 @@ pub fn eval_expr(env: &mut Env, expr: &Expr) -> Value {
     }
 }
 
+// Evaluates an expression held by a bowl. Values written to the bowl, and the
+// bowls copied into it from literals, belong to it and are not copied again.
+fn eval_stored(env: &mut Env, expr: &Expr) -> Value {
+    match expr {
+        Expr::ValueExpr(value) => value.clone(),
+        _ => eval_expr(env, expr),
+    }
+}
+
+// Evaluates `expr` of a noodle in a bowl with `eval`, nested in the evaluation
+// reading the bowl, or gives NULL when a limit stops it.
+fn eval_nested(env: &mut Env, expr: &Expr, eval: fn(&mut Env, &Expr) -> Value) -> Value {
+    let value = if env.enter() {
+        eval(env, expr)
+    } else {
+        Value::Null
+    };
+    env.leave();
+    value
+}
+
+// Makes a new bowl from the bowl literal `literal`, as evaluating it does. The
+// bowl literals its noodles hold directly are copied along, while those inside
+// other expressions make their own bowls whenever they are evaluated.
+// `on_copy` is called with every literal copied and its copy.
+pub fn new_bowl(
+    literal: &Rc<RefCell<Bowl>>,
+    on_copy: &mut impl FnMut(&Rc<RefCell<Bowl>>, &Rc<RefCell<Bowl>>),
+) -> Rc<RefCell<Bowl>> {
+    let mut copy_expr = |expr: &Expr| match expr {
+        Expr::ValueExpr(Value::Bowl(bowl)) => Expr::ValueExpr(Value::Bowl(new_bowl(bowl, on_copy))),
+        _ => expr.clone(),
+    };
+    let noodles = literal
+        .borrow()
+        .noodles
+        .iter()
+        .map(|noodle| Noodle {
+            nn_expr: copy_expr(&noodle.nn_expr),
+            expr: copy_expr(&noodle.expr),
+            line: noodle.line,
+        })
+        .collect();
+    let bowl = Rc::new(RefCell::new(Bowl { noodles }));
+    on_copy(literal, &bowl);
+    bowl
+}
+
+// Where a bowl written to is found: a memory cell or a value, then the noodle
+// numbers read from there.
+pub struct Place {
+    pub root: Root,
+    pub path: Vec<Value>,
+}
+
+pub enum Root {
+    Mem(Value),
+    Temp(Value),
+}
+
+// Evaluates the expressions bowls hold, as an engine does when reading them.
+pub trait StoredEval {
+    fn eval_stored(
+        &mut self,
+        env: &mut Env,
+        bowl: &Rc<RefCell<Bowl>>,
+        index: usize,
+        is_nn: bool,
+    ) -> Value;
+}
+
+struct Tree;
+
+impl StoredEval for Tree {
+    fn eval_stored(
+        &mut self,
+        env: &mut Env,
+        bowl: &Rc<RefCell<Bowl>>,
+        index: usize,
+        is_nn: bool,
+    ) -> Value {
+        let expr = {
+            let noodle = &bowl.borrow().noodles[index];
+            if is_nn {
+                noodle.nn_expr.clone()
+            } else {
+                noodle.expr.clone()
+            }
+        };
+        eval_nested(env, &expr, eval_stored)
+    }
+}
+
+fn place(env: &mut Env, expr: &Expr) -> Place {
+    match expr {
+        Expr::MemReadExpr(nn_expr) => Place {
+            root: Root::Mem(eval_expr(env, nn_expr)),
+            path: vec![],
+        },
+        Expr::BowlReadExpr(bowl_expr, nn_expr) => {
+            let mut place = place(env, bowl_expr);
+            place.path.push(eval_expr(env, nn_expr));
+            place
+        }
+        _ => Place {
+            root: Root::Temp(eval_expr(env, expr)),
+            path: vec![],
+        },
+    }
+}
+
+// Copies `bowl` in place when other places share it, as `Rc::make_mut` does.
+fn make_mut(bowl: &mut Rc<RefCell<Bowl>>) {
+    if Rc::strong_count(bowl) > 1 {
+        let copy = bowl.borrow().clone();
+        *bowl = Rc::new(RefCell::new(copy));
+    }
+}
+
+fn temp(value: Value) -> Option<Rc<RefCell<Bowl>>> {
+    match value {
+        Value::Bowl(mut bowl) => {
+            make_mut(&mut bowl);
+            Some(bowl)
+        }
+        _ => None,
+    }
+}
+
+// Finds the bowl at `place` to write to with value semantics. Every bowl on
+// the way that another place shares is copied first, so the write changes no
+// other value. Bowls only found by evaluating a noodle are not held anywhere,
+// and what is written to them is lost.
+pub fn unshare(
+    env: &mut Env,
+    place: Place,
+    stored: &mut impl StoredEval,
+) -> Option<Rc<RefCell<Bowl>>> {
+    let mut bowl = match place.root {
+        Root::Mem(Value::Number(nn)) if *nn != Number::zero() && *nn != Number::one() => {
+            let (_, value) = env.mem.iter_mut().find(|(cell, _)| *cell == *nn)?;
+            match value {
+                Value::Bowl(bowl) => {
+                    make_mut(bowl);
+                    bowl.clone()
+                }
+                _ => return None,
+            }
+        }
+        Root::Mem(nn) => temp(env.read_mem(&nn))?,
+        Root::Temp(value) => temp(value)?,
+    };
+    for nn in place.path {
+        let nn = match nn {
+            Value::Number(nn) => nn,
+            _ => return None,
+        };
+        let len = bowl.borrow().noodles.len();
+        let index = (0..len).find(|&index| {
+            matches!(stored.eval_stored(env, &bowl, index, true),
+                Value::Number(number) if *number == *nn)
+        })?;
+        let held = match &bowl.borrow().noodles[index].expr {
+            Expr::ValueExpr(Value::Bowl(held)) => Some(held.clone()),
+            Expr::ValueExpr(_) => return None,
+            _ => None,
+        };
+        bowl = match held {
+            // shared unless only the noodle and `held` have it
+            Some(held) if Rc::strong_count(&held) > 2 => {
+                let copy = Rc::new(RefCell::new(held.borrow().clone()));
+                bowl.borrow_mut().noodles[index].expr = Expr::ValueExpr(Value::Bowl(copy.clone()));
+                copy
+            }
+            Some(held) => held,
+            None => temp(stored.eval_stored(env, &bowl, index, false))?,
+        };
+    }
+    Some(bowl)
+}
+
+// Bowl access never holds a borrow of the bowl while its noodles are
+// evaluated, so noodles may read and write the bowl they are in.
 impl Bowl {
-    pub fn read(&self, env: &mut Env, noodle_number: &Value) -> Value {
-        for noodle in &self.noodles {
-            let inner_nn = eval_expr(env, &noodle.nn_expr);
-            match (inner_nn, noodle_number) {
-                (Value::Number(a), Value::Number(b)) => {
-                    if a.eq(b) {
-                        return eval_expr(env, &noodle.expr);
-                    }
+    pub fn read(bowl: &Rc<RefCell<Bowl>>, env: &mut Env, noodle_number: &Value) -> Value {
+        Bowl::read_with(bowl, env, noodle_number, eval_stored)
+    }
+
+    fn read_with(
+        bowl: &Rc<RefCell<Bowl>>,
+        env: &mut Env,
+        noodle_number: &Value,
+        eval: fn(&mut Env, &Expr) -> Value,
+    ) -> Value {
+        if let Some(coverage) = env.coverage.as_mut() {
+            coverage.reach_bowl(&bowl.borrow());
+        }
+        let index = match Bowl::find(bowl, env, noodle_number) {
+            Some(index) => index,
+            None => return Value::Null,
+        };
+        let expr = {
+            let inner = bowl.borrow();
+            if let Some(coverage) = env.coverage.as_mut() {
+                coverage.hit_noodle(&inner.noodles[index]);
+                coverage.hit_branch(&inner, index);
+            }
+            inner.noodles[index].expr.clone()
+        };
+        eval_nested(env, &expr, eval)
+    }
+
+    pub fn write(bowl: &Rc<RefCell<Bowl>>, env: &mut Env, noodle_number: &Value, value: &Value) {
+        let index = Bowl::find(bowl, env, noodle_number);
+        // the noodle may not have been found, only given up on
+        if env.is_stopped() {
+            return;
+        }
+        let mut inner = bowl.borrow_mut();
+        match index {
+            Some(index) => {
+                let expr = Expr::ValueExpr(value.clone());
+                let overwritten = mem::replace(&mut inner.noodles[index].expr, expr);
+                drop(inner);
+                if let Expr::ValueExpr(overwritten) = overwritten {
+                    env.discard(overwritten);
                 }
-                _ => {}
             }
+            None => inner.noodles.push(Noodle {
+                nn_expr: Expr::ValueExpr(noodle_number.clone()),
+                expr: Expr::ValueExpr(value.clone()),
+                line: None,
+            }),
         }
-        Value::Null
     }
 
-    pub fn write(&mut self, env: &mut Env, noodle_number: &Value, value: &Value) -> Value {
-        let mut is_written = false;
-        for noodle in self.noodles.iter_mut() {
-            let inner_nn = eval_expr(env, &noodle.nn_expr);
-            match (inner_nn, noodle_number) {
-                (Value::Number(a), Value::Number(b)) => {
-                    if a.eq(b) {
-                        noodle.expr = Expr::ValueExpr(value.clone());
-                        is_written = true;
-                        break;
+    // The index of the first noodle with the number `noodle_number`. Noodles
+    // are only ever replaced or added, so the index stays valid.
+    fn find(bowl: &Rc<RefCell<Bowl>>, env: &mut Env, noodle_number: &Value) -> Option<usize> {
+        let noodle_number = match noodle_number {
+            Value::Number(number) => number,
+            _ => return None,
+        };
+        let mut index = 0;
+        loop {
+            let nn_expr = match bowl.borrow().noodles.get(index) {
+                Some(Noodle {
+                    nn_expr: Expr::ValueExpr(value),
+                    ..
+                }) => {
+                    if matches!(value, Value::Number(number) if number == noodle_number) {
+                        return Some(index);
                     }
+                    index += 1;
+                    continue;
+                }
+                Some(noodle) => noodle.nn_expr.clone(),
+                None => return None,
+            };
+            if let Value::Number(number) = eval_nested(env, &nn_expr, eval_expr) {
+                if number == *noodle_number {
+                    return Some(index);
                 }
-                _ => {}
             }
+            index += 1;
         }
-        if !is_written {
-            self.noodles.push(Noodle {
-                nn_expr: Expr::ValueExpr(noodle_number.clone()),
-                expr: Expr::ValueExpr(value.clone()),
+    }
+}
+
+impl Value {
+    // Deep equality: numbers by value, NULL with NULL, and bowls holding equal
+    // values at the same noodle numbers, as reading them would find. Noodles
+    // are evaluated in `env`, and bowls met again while comparing them, through
+    // aliasing or cycles, are taken as equal.
+    pub fn structural_eq(&self, other: &Value, env: &mut Env) -> bool {
+        structural_eq(self, other, env, &mut vec![])
+    }
+}
+
+fn structural_eq(
+    value1: &Value,
+    value2: &Value,
+    env: &mut Env,
+    comparing: &mut Vec<(*const RefCell<Bowl>, *const RefCell<Bowl>)>,
+) -> bool {
+    match (value1, value2) {
+        (Value::Null, Value::Null) => true,
+        (Value::Number(number1), Value::Number(number2)) => number1 == number2,
+        (Value::Bowl(bowl1), Value::Bowl(bowl2)) => {
+            let pair = (Rc::as_ptr(bowl1), Rc::as_ptr(bowl2));
+            if Rc::ptr_eq(bowl1, bowl2) || comparing.contains(&pair) {
+                return true;
+            }
+            let entries1 = entries(bowl1, env);
+            let entries2 = entries(bowl2, env);
+            if entries1.len() != entries2.len() {
+                return false;
+            }
+            comparing.push(pair);
+            let is_equal = entries1.iter().all(|(nn, value1)| {
+                match entries2.iter().find(|(other_nn, _)| other_nn == nn) {
+                    Some((_, value2)) => structural_eq(value1, value2, env, comparing),
+                    None => false,
+                }
             });
+            comparing.pop();
+            is_equal
         }
-        Value::Null
+        _ => false,
+    }
+}
+
+// The values reading `bowl` finds, by noodle number.
+fn entries(bowl: &Rc<RefCell<Bowl>>, env: &mut Env) -> Vec<(Number, Value)> {
+    // evaluated from a copy, so noodles may use the bowl themselves
+    let noodles = bowl.borrow().noodles.clone();
+    let mut entries: Vec<(Number, Value)> = vec![];
+    for noodle in &noodles {
+        if let Value::Number(nn) = eval_stored(env, &noodle.nn_expr) {
+            // the first noodle with a number shadows the others
+            if entries.iter().all(|(other_nn, _)| *other_nn != *nn) {
+                entries.push((*nn, eval_stored(env, &noodle.expr)));
+            }
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn env<'a>() -> Env<'a> {
+        Env::new(Vec::new, |_| {})
+    }
+
+    fn bowl(code: &str) -> Value {
+        Value::from_bowl(parse(code).unwrap())
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let mut env = env();
+        env.assignment = Assignment::Value;
+        let code = "{[0; @:2 = {[0; {}][1; {}]}][1; @:3 = @:2][2; ((@:3):(0)):(0) = 1]}";
+        eval_steps(&mut env, &parse(code).unwrap(), None).unwrap();
+        let held = |env: &mut Env, cell: i64, nn: i64| {
+            let bowl = env.read_mem(&Value::from_number(&Number::from(cell)));
+            match bowl {
+                Value::Bowl(bowl) => {
+                    match Bowl::read(&bowl, env, &Value::from_number(&Number::from(nn))) {
+                        Value::Bowl(held) => held,
+                        _ => panic!("no bowl held"),
+                    }
+                }
+                _ => panic!("not a bowl"),
+            }
+        };
+
+        // only the bowls written to on the way are copied
+        assert!(!Rc::ptr_eq(&held(&mut env, 2, 0), &held(&mut env, 3, 0)));
+        assert!(Rc::ptr_eq(&held(&mut env, 2, 1), &held(&mut env, 3, 1)));
+        assert_eq!(held(&mut env, 2, 0).borrow().noodles.len(), 0);
+        assert_eq!(held(&mut env, 3, 0).borrow().noodles.len(), 1);
+    }
+
+    #[test]
+    fn new_bowls() {
+        let mut env = env();
+        let literal = parse("{[0; {[0; {}]}]}").unwrap().noodles.remove(0).expr;
+        let zero = Value::new_zero();
+        let inner = |env: &mut Env, value: &Value| match value {
+            Value::Bowl(bowl) => match Bowl::read(bowl, env, &zero) {
+                Value::Bowl(inner) => inner,
+                _ => panic!("no inner bowl"),
+            },
+            _ => panic!("not a bowl"),
+        };
+
+        // every evaluation makes new bowls, down to the literals held
+        let (a, b) = (eval_expr(&mut env, &literal), eval_expr(&mut env, &literal));
+        if let (Value::Bowl(a), Value::Bowl(b)) = (&a, &b) {
+            assert!(!Rc::ptr_eq(a, b));
+        }
+        assert!(!Rc::ptr_eq(&inner(&mut env, &a), &inner(&mut env, &b)));
+
+        // and the bowls made are kept, not made again on reads
+        assert!(Rc::ptr_eq(&inner(&mut env, &a), &inner(&mut env, &a)));
+    }
+
+    #[test]
+    fn structural_equality() {
+        let mut env = env();
+        let a = bowl("{[0; 72][1; {[0; 1/2]}][0; 5]}");
+        assert!(a.structural_eq(&bowl("{[1; {[1 - 1; 2/4]}][2 - 2; 70 + 2]}"), &mut env));
+        assert!(!a.structural_eq(&bowl("{[0; 72][1; {[0; 1/3]}]}"), &mut env));
+        assert!(!a.structural_eq(&bowl("{[0; 72][1; {[0; 1/2]}][2; 0]}"), &mut env));
+        assert!(!a.structural_eq(&Value::Null, &mut env));
+        assert!(Value::Null.structural_eq(&Value::Null, &mut env));
+        assert!(!Value::new_one().structural_eq(&bowl("{}"), &mut env));
+    }
+
+    #[test]
+    fn cyclic_equality() {
+        let mut env = env();
+        // two bowls holding themselves at 1, and another holding the first
+        let (a, b) = (bowl("{[0; 7]}"), bowl("{[0; 7]}"));
+        for bowl in [&a, &b] {
+            if let Value::Bowl(inner) = bowl {
+                Bowl::write(inner, &mut env, &Value::new_one(), bowl);
+            }
+        }
+        assert!(a.structural_eq(&b, &mut env));
+        let c = bowl("{[0; 7]}");
+        if let Value::Bowl(inner) = &c {
+            Bowl::write(inner, &mut env, &Value::new_one(), &a);
+        }
+        assert!(a.structural_eq(&c, &mut env));
+        if let Value::Bowl(inner) = &b {
+            Bowl::write(inner, &mut env, &Value::new_zero(), &Value::Null);
+        }
+        assert!(!a.structural_eq(&b, &mut env));
+    }
+
+    #[test]
+    fn stop_over_limit() {
+        let mut env = env().with_max_bits(Some(3));
+        // the write on the right is never run once 4 * 4 is over the limit
+        let code = "{[0; (4 * 4) + (@:3 = 1)]}";
+        let bowl = parse(code).unwrap();
+        assert!(matches!(
+            eval_expr(&mut env, &bowl.noodles[0].expr),
+            Value::Null
+        ));
+        assert!(env.is_stopped());
+        assert!(env.mem.is_empty());
+        let error = env.check_limit(&bowl.noodles[0]).unwrap_err();
+        assert!(error.to_string().contains("a number of 5 bits"));
+        assert!(!env.is_stopped());
+    }
+
+    #[test]
+    fn stop_nested_too_deep() {
+        // a bowl reading itself forever, and one found by a noodle number
+        // reading itself forever, which is not written to
+        for (code, noodle) in [
+            (
+                "{[0; @:2 = {[0; (@:2):0]}][1; @:3 = (@:2):0]}",
+                "[1; (@:(3) = ((@:(2)):(0)))]",
+            ),
+            (
+                "{[0; @:2 = {[(@:2):1; 0]}][1; (@:2):1 = 5]}",
+                "[1; ((@:(2)):(1) = (5))]",
+            ),
+        ] {
+            let mut env = env().with_max_depth(Some(100));
+            let error = eval_steps(&mut env, &parse(code).unwrap(), None).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "line 1: evaluation is nested over the limit of 100 levels, in {}",
+                    noodle
+                )
+            );
+            match &env.mem[..] {
+                [(_, Value::Bowl(bowl))] => assert_eq!(bowl.borrow().noodles.len(), 1),
+                _ => panic!("no bowl in memory"),
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_cycles() {
+        let mut env = env();
+        // every step makes a bowl holding itself in @:3, leaving the last one
+        let code = "{[0; 0][@:0 + 1; (@:3 = {[0; 0]}) + ((@:3):1 = @:3)]}";
+        let bowl = parse(code).unwrap();
+        eval_steps(&mut env, &bowl, Some(2)).unwrap();
+        let first = match &env.mem[..] {
+            [(_, Value::Bowl(first))] => Rc::downgrade(first),
+            _ => panic!("no bowl in memory"),
+        };
+        eval_steps(&mut env, &bowl, Some(2000)).unwrap();
+        assert!(first.upgrade().is_none());
     }
 }
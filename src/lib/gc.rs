@@ -0,0 +1,182 @@
+// A collector for cycles of bowls. Bowls are reference counted, so a bowl
+// holding itself, directly or through other bowls, is never freed. `collect`
+// drops values the way trial deletion does: of the bowls reachable from them,
+// those referred to only from within are emptied, which breaks their cycles.
+// The environment collects the values programs overwrite as they run, and its
+// memory once they are done.
+
+use std::{cell::RefCell, collections::HashMap, mem, rc::Rc};
+
+use crate::datatype::{Bowl, Expr, Value};
+
+struct Found {
+    bowls: Vec<Rc<RefCell<Bowl>>>,
+    // the index of each bowl, and the references to it from the values
+    // dropped and the bowls found
+    refs: HashMap<*const RefCell<Bowl>, (usize, usize)>,
+}
+
+impl Found {
+    fn value(&mut self, value: &Value) {
+        if let Value::Bowl(bowl) = value {
+            let index = self.bowls.len();
+            let (_, refs) = self.refs.entry(Rc::as_ptr(bowl)).or_insert((index, 0));
+            *refs += 1;
+            if *refs == 1 {
+                self.bowls.push(bowl.clone());
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::ValueExpr(value) => self.value(value),
+            Expr::MemReadExpr(expr) | Expr::DenoFuncExpr(expr) | Expr::NotFuncExpr(expr) => {
+                self.expr(expr)
+            }
+            Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+                self.expr(expr1);
+                self.expr(expr2);
+                self.expr(expr3);
+            }
+            Expr::BowlReadExpr(expr1, expr2)
+            | Expr::MemWriteExpr(expr1, expr2)
+            | Expr::PlusFuncExpr(expr1, expr2)
+            | Expr::MinusFuncExpr(expr1, expr2)
+            | Expr::MulFuncExpr(expr1, expr2)
+            | Expr::NumberSepFuncExpr(expr1, expr2)
+            | Expr::AndFuncExpr(expr1, expr2)
+            | Expr::OrFuncExpr(expr1, expr2)
+            | Expr::EqFuncExpr(expr1, expr2)
+            | Expr::GtFuncExpr(expr1, expr2)
+            | Expr::LtFuncExpr(expr1, expr2) => {
+                self.expr(expr1);
+                self.expr(expr2);
+            }
+        }
+    }
+
+    fn bowl(&mut self, bowl: &Bowl) {
+        for noodle in &bowl.noodles {
+            self.expr(&noodle.nn_expr);
+            self.expr(&noodle.expr);
+        }
+    }
+}
+
+pub struct Collected {
+    pub freed: usize,
+    // the bowls found that are referred to from elsewhere, or held by them
+    pub alive: usize,
+}
+
+// Drops `values`, freeing the bowls that only they and cycles keep alive.
+pub fn collect(values: Vec<Value>) -> Collected {
+    let mut found = Found {
+        bowls: vec![],
+        refs: HashMap::new(),
+    };
+    for value in &values {
+        found.value(value);
+    }
+    let mut index = 0;
+    while index < found.bowls.len() {
+        let bowl = found.bowls[index].clone();
+        found.bowl(&bowl.borrow());
+        index += 1;
+    }
+
+    // bowls with references from elsewhere are alive, with all they hold
+    let mut is_alive: Vec<bool> = found
+        .bowls
+        .iter()
+        .map(|bowl| {
+            let (_, refs) = found.refs[&Rc::as_ptr(bowl)];
+            // `found.bowls` holds one more
+            Rc::strong_count(bowl) > refs + 1
+        })
+        .collect();
+    let mut alive: Vec<usize> = (0..is_alive.len()).filter(|&i| is_alive[i]).collect();
+    while let Some(index) = alive.pop() {
+        let mut held = Found {
+            bowls: vec![],
+            refs: HashMap::new(),
+        };
+        held.bowl(&found.bowls[index].borrow());
+        for bowl in &held.bowls {
+            let (index, _) = found.refs[&Rc::as_ptr(bowl)];
+            if !is_alive[index] {
+                is_alive[index] = true;
+                alive.push(index);
+            }
+        }
+    }
+
+    let mut freed = vec![];
+    for (bowl, is_alive) in found.bowls.iter().zip(is_alive) {
+        if !is_alive {
+            freed.push(mem::take(&mut bowl.borrow_mut().noodles));
+        }
+    }
+    let collected = Collected {
+        freed: freed.len(),
+        alive: found.bowls.len() - freed.len(),
+    };
+    drop(freed);
+    drop(found);
+    drop(values);
+    collected
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Weak;
+
+    use super::*;
+    use crate::parse;
+
+    fn bowl(code: &str) -> Rc<RefCell<Bowl>> {
+        Rc::new(RefCell::new(parse(code).unwrap()))
+    }
+
+    // Puts `value` at the end of `bowl`.
+    fn hold(bowl: &Rc<RefCell<Bowl>>, value: &Rc<RefCell<Bowl>>) {
+        let mut holder = parse("{[9; 0]}").unwrap().noodles.remove(0);
+        holder.expr = Expr::ValueExpr(Value::Bowl(value.clone()));
+        bowl.borrow_mut().noodles.push(holder);
+    }
+
+    #[test]
+    fn collect_cycles() {
+        // a holds b, which holds a and itself
+        let (a, b) = (bowl("{[0; 1]}"), bowl("{[0; 2]}"));
+        hold(&a, &b);
+        hold(&b, &a);
+        hold(&b, &b);
+        let (weak_a, weak_b): (Weak<_>, Weak<_>) = (Rc::downgrade(&a), Rc::downgrade(&b));
+        drop(b);
+
+        assert_eq!(collect(vec![Value::Bowl(a)]).freed, 2);
+        assert!(weak_a.upgrade().is_none());
+        assert!(weak_b.upgrade().is_none());
+    }
+
+    #[test]
+    fn keep_referenced_bowls() {
+        // c holds d and d holds c, but d is still held elsewhere
+        let (c, d) = (bowl("{[0; 1]}"), bowl("{[0; 2]}"));
+        hold(&c, &d);
+        hold(&d, &c);
+        let weak_c = Rc::downgrade(&c);
+
+        assert_eq!(collect(vec![Value::Bowl(c)]).alive, 2);
+        let c = weak_c.upgrade().unwrap();
+        assert_eq!(c.borrow().noodles.len(), 2);
+        assert_eq!(d.borrow().noodles.len(), 2);
+
+        // once d goes, both are freed
+        drop(c);
+        assert_eq!(collect(vec![Value::Bowl(d)]).freed, 2);
+        assert!(weak_c.upgrade().is_none());
+    }
+}
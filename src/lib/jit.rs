@@ -0,0 +1,498 @@
+// Compiles frequently selected noodles to native code with Cranelift. Only
+// expressions on integers are compiled: constants, `+`, `-`, `*`, logic,
+// comparisons and memory reads at constant noodle numbers, optionally stored to
+// a constant cell as the whole noodle expression. Native code works on an
+// array of `i64` slots loaded from memory before the call, and returns to the
+// interpreter (deoptimizes) when a cell it reads does not hold a small integer
+// or an operation overflows. Nothing is written back in that case, so the
+// interpreter evaluates the noodle from scratch.
+
+use std::{error, mem, mem::ManuallyDrop};
+
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, Block, InstBuilder, MemFlags, Value as IrValue},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::{
+    datatype::{Bowl, Expr, Formatted, Number, Value},
+    env::Env,
+    eval::eval_expr,
+    optimize::fold_expr,
+};
+
+// selections of a noodle before it is compiled
+const HOT_THRESHOLD: u32 = 16;
+// deoptimizations of a native function before it is dropped
+const MAX_DEOPTS: u32 = 16;
+
+// slot 0 holds the result and slot 1 the cursor, followed by the cells
+const RESULT_SLOT: usize = 0;
+const CURSOR_SLOT: usize = 1;
+const FIRST_CELL_SLOT: usize = 2;
+
+type NativeFn = extern "C" fn(*mut i64) -> i64;
+
+struct Native {
+    function: NativeFn,
+    cells: Vec<Number>,
+    is_cursor_read: bool,
+    // the cell the result is stored to
+    write: Option<Number>,
+    deopts: u32,
+    slots: Vec<i64>,
+    positions: Vec<Option<usize>>,
+    write_position: Option<usize>,
+}
+
+#[derive(Default)]
+struct HotNoodle {
+    count: u32,
+    nn: Option<Native>,
+    expr: Option<Native>,
+}
+
+// The code compiled by a `Jit` is freed with it, so the natives it compiles
+// must be dropped first.
+pub struct Jit {
+    module: ManuallyDrop<JITModule>,
+    builder_context: FunctionBuilderContext,
+}
+
+// Runs `bowl` like `eval::eval`, compiling hot noodles on the way.
+pub fn run(env: &mut Env, bowl: &Bowl) -> Result<bool, Box<dyn error::Error>> {
+    // declared first, so the natives in `noodles` are dropped before it
+    let mut jit = Jit::new()?;
+    let mut noodles: Vec<HotNoodle> = bowl.noodles.iter().map(|_| HotNoodle::default()).collect();
+    loop {
+        env.sweep();
+        let mut next: Option<(usize, Value)> = None;
+        for (index, noodle) in bowl.noodles.iter().enumerate() {
+            let nn = eval(env, &noodle.nn_expr, &mut noodles[index].nn);
+            env.check_limit(noodle)?;
+            if env.is_nextable(&nn) {
+                let is_min = match (&next, &nn) {
+                    (Some((_, Value::Number(min))), Value::Number(number)) => number.lt(min),
+                    _ => true,
+                };
+                if is_min {
+                    next = Some((index, nn));
+                }
+            }
+        }
+        let index = match next {
+            Some((index, _)) => index,
+            None => break,
+        };
+        let noodle = &bowl.noodles[index];
+        let hot = &mut noodles[index];
+        hot.count += 1;
+        if hot.count == HOT_THRESHOLD {
+            hot.nn = jit.compile(&noodle.nn_expr, env.max_bits);
+            hot.expr = jit.compile(&noodle.expr, env.max_bits);
+        }
+        if env.is_debug {
+            println!("[=] noodle: {}", Formatted(noodle, env.number_format));
+        }
+        let new_cursor = eval(env, &noodle.nn_expr, &mut hot.nn);
+        env.check_limit(noodle)?;
+        if env.is_debug {
+            println!(
+                "[.] noodle number: {}",
+                Formatted(&new_cursor, env.number_format)
+            );
+        }
+        if let Value::Number(number) = new_cursor {
+            env.cursor = Some(*number);
+        } else {
+            panic!("Cannot set cursor to non-number value");
+        }
+        eval(env, &noodle.expr, &mut hot.expr);
+        env.check_limit(noodle)?;
+        if env.is_debug {
+            println!(
+                "[.] cursor: {}",
+                env.cursor.as_ref().unwrap().format(env.number_format)
+            );
+            println!(
+                "[.] mem state: {}",
+                Formatted(&env.mem_to_bowl(), env.number_format)
+            );
+        }
+    }
+    Ok(true)
+}
+
+impl Jit {
+    pub fn new() -> Result<Jit, Box<dyn error::Error>> {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("use_colocated_libcalls", "false")?;
+        flag_builder.set("is_pic", "false")?;
+        let isa = cranelift_native::builder()?.finish(settings::Flags::new(flag_builder))?;
+        Ok(Jit {
+            module: ManuallyDrop::new(JITModule::new(JITBuilder::with_isa(
+                isa,
+                default_libcall_names(),
+            ))),
+            builder_context: FunctionBuilderContext::new(),
+        })
+    }
+
+    // Compiles `expr` if it only works on integers.
+    fn compile(&mut self, expr: &Expr, max_bits: Option<u64>) -> Option<Native> {
+        // native code only stops at integers over 64 bits, so smaller limits
+        // are left to the interpreter
+        if max_bits.is_some_and(|max_bits| max_bits < 64) {
+            return None;
+        }
+        let mut expr = expr.clone();
+        fold_expr(&mut expr, max_bits);
+        let (write, expr) = match &expr {
+            Expr::MemWriteExpr(nn_expr, value_expr) => match const_number(nn_expr) {
+                Some(number) if !is_reserved(&number) => (Some(number), value_expr.as_ref()),
+                _ => return None,
+            },
+            _ => (None, &expr),
+        };
+        let mut cells = vec![];
+        let mut is_cursor_read = false;
+        if !collect_cells(expr, &mut cells, &mut is_cursor_read) {
+            return None;
+        }
+
+        let pointer_type = self.module.target_config().pointer_type();
+        let mut context = self.module.make_context();
+        context
+            .func
+            .signature
+            .params
+            .push(AbiParam::new(pointer_type));
+        context
+            .func
+            .signature
+            .returns
+            .push(AbiParam::new(types::I64));
+        let id = self
+            .module
+            .declare_anonymous_function(&context.func.signature)
+            .ok()?;
+        {
+            let mut builder = FunctionBuilder::new(&mut context.func, &mut self.builder_context);
+            let entry = builder.create_block();
+            let deopt = builder.create_block();
+            builder.append_block_params_for_function_params(entry);
+            builder.switch_to_block(entry);
+            let slots = builder.block_params(entry)[0];
+            let mut translator = Translator {
+                builder,
+                slots,
+                deopt,
+                cells: &cells,
+            };
+            let result = translator.expr(expr);
+            let mut builder = translator.builder;
+            builder
+                .ins()
+                .store(MemFlags::trusted(), result, slots, slot_offset(RESULT_SLOT));
+            let ok = builder.ins().iconst(types::I64, 0);
+            builder.ins().return_(&[ok]);
+            builder.switch_to_block(deopt);
+            let failed = builder.ins().iconst(types::I64, 1);
+            builder.ins().return_(&[failed]);
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+        self.module.define_function(id, &mut context).ok()?;
+        self.module.clear_context(&mut context);
+        self.module.finalize_definitions().ok()?;
+        let code = self.module.get_finalized_function(id);
+        Some(Native {
+            function: unsafe { mem::transmute::<*const u8, NativeFn>(code) },
+            slots: vec![0; FIRST_CELL_SLOT + cells.len()],
+            positions: vec![None; cells.len()],
+            cells,
+            is_cursor_read,
+            write,
+            deopts: 0,
+            write_position: None,
+        })
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        // the module is not used again, and no native compiled by it outlives
+        // the `Jit`
+        unsafe { ManuallyDrop::take(&mut self.module).free_memory() };
+    }
+}
+
+// Evaluates `expr` with its native function if it has one, otherwise or on
+// deoptimization with the interpreter.
+fn eval(env: &mut Env, expr: &Expr, native: &mut Option<Native>) -> Value {
+    if let Some(compiled) = native {
+        match call(env, compiled) {
+            Some(value) => return value,
+            None => {
+                compiled.deopts += 1;
+                if compiled.deopts >= MAX_DEOPTS {
+                    *native = None;
+                }
+            }
+        }
+    }
+    eval_expr(env, expr)
+}
+
+// Calls a native function, or returns `None` to deoptimize.
+fn call(env: &mut Env, native: &mut Native) -> Option<Value> {
+    if native.is_cursor_read {
+        native.slots[CURSOR_SLOT] = small_integer(env.cursor.as_ref()?)?;
+    }
+    for (index, cell) in native.cells.iter().enumerate() {
+        let position = find_cell(env, cell, &mut native.positions[index])?;
+        native.slots[FIRST_CELL_SLOT + index] = match &env.mem[position].1 {
+            Value::Number(number) => small_integer(number)?,
+            _ => return None,
+        };
+    }
+    if (native.function)(native.slots.as_mut_ptr()) != 0 {
+        return None;
+    }
+    let result = env.limit(Number::from(native.slots[RESULT_SLOT]));
+    if env.is_stopped() {
+        return Some(Value::Null);
+    }
+    match &native.write {
+        Some(cell) => {
+            match find_cell(env, cell, &mut native.write_position) {
+                Some(position) => {
+                    let overwritten = mem::replace(&mut env.mem[position].1, result);
+                    env.discard(overwritten);
+                }
+                None => env.mem.push((cell.clone(), result)),
+            }
+            Some(Value::Null)
+        }
+        None => Some(result),
+    }
+}
+
+// Finds `cell` in memory, first where it was found last time, as cells keep
+// their positions once written.
+fn find_cell(env: &Env, cell: &Number, position: &mut Option<usize>) -> Option<usize> {
+    let is_cell = |index: usize| match env.mem.get(index) {
+        Some((number, _)) => number == cell,
+        None => false,
+    };
+    if !position.is_some_and(is_cell) {
+        *position = (0..env.mem.len()).find(|&index| is_cell(index));
+    }
+    *position
+}
+
+fn small_integer(number: &Number) -> Option<i64> {
+    i64::try_from(number).ok()
+}
+
+fn const_number(expr: &Expr) -> Option<Number> {
+    match expr {
+        Expr::ValueExpr(Value::Number(number)) => Some(*number.clone()),
+        _ => None,
+    }
+}
+
+// The cursor and IO cells are not compiled as memory.
+fn is_reserved(number: &Number) -> bool {
+    *number == Number::zero() || *number == Number::one()
+}
+
+// Collects the cells `expr` reads, and returns whether it can be compiled.
+fn collect_cells(expr: &Expr, cells: &mut Vec<Number>, is_cursor_read: &mut bool) -> bool {
+    match expr {
+        Expr::ValueExpr(Value::Number(number)) => small_integer(number).is_some(),
+        Expr::MemReadExpr(nn_expr) => match const_number(nn_expr) {
+            Some(number) if number == Number::zero() => {
+                *is_cursor_read = true;
+                true
+            }
+            Some(number) if !is_reserved(&number) => {
+                if !cells.contains(&number) {
+                    cells.push(number);
+                }
+                true
+            }
+            _ => false,
+        },
+        Expr::NotFuncExpr(expr) => collect_cells(expr, cells, is_cursor_read),
+        Expr::PlusFuncExpr(expr1, expr2)
+        | Expr::MinusFuncExpr(expr1, expr2)
+        | Expr::MulFuncExpr(expr1, expr2)
+        | Expr::AndFuncExpr(expr1, expr2)
+        | Expr::OrFuncExpr(expr1, expr2)
+        | Expr::EqFuncExpr(expr1, expr2)
+        | Expr::GtFuncExpr(expr1, expr2)
+        | Expr::LtFuncExpr(expr1, expr2) => {
+            collect_cells(expr1, cells, is_cursor_read)
+                && collect_cells(expr2, cells, is_cursor_read)
+        }
+        _ => false,
+    }
+}
+
+fn slot_offset(slot: usize) -> i32 {
+    (slot * mem::size_of::<i64>()) as i32
+}
+
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    slots: IrValue,
+    deopt: Block,
+    cells: &'a [Number],
+}
+
+impl Translator<'_> {
+    // Translates an expression accepted by `collect_cells`.
+    fn expr(&mut self, expr: &Expr) -> IrValue {
+        match expr {
+            Expr::ValueExpr(Value::Number(number)) => {
+                let number = small_integer(number).unwrap();
+                self.builder.ins().iconst(types::I64, number)
+            }
+            Expr::MemReadExpr(nn_expr) => {
+                let number = const_number(nn_expr).unwrap();
+                let slot = match self.cells.iter().position(|cell| *cell == number) {
+                    Some(index) => FIRST_CELL_SLOT + index,
+                    None => CURSOR_SLOT,
+                };
+                self.builder.ins().load(
+                    types::I64,
+                    MemFlags::trusted(),
+                    self.slots,
+                    slot_offset(slot),
+                )
+            }
+            Expr::NotFuncExpr(expr) => {
+                let value = self.expr(expr);
+                let is_one = self.builder.ins().icmp_imm(IntCC::Equal, value, 1);
+                let is_not_one = self.builder.ins().bxor_imm(is_one, 1);
+                self.builder.ins().uextend(types::I64, is_not_one)
+            }
+            Expr::PlusFuncExpr(expr1, expr2) => {
+                let (a, b) = (self.expr(expr1), self.expr(expr2));
+                let (value, overflow) = self.builder.ins().sadd_overflow(a, b);
+                self.guard(overflow);
+                value
+            }
+            Expr::MinusFuncExpr(expr1, expr2) => {
+                let (a, b) = (self.expr(expr1), self.expr(expr2));
+                let (value, overflow) = self.builder.ins().ssub_overflow(a, b);
+                self.guard(overflow);
+                value
+            }
+            Expr::MulFuncExpr(expr1, expr2) => {
+                let (a, b) = (self.expr(expr1), self.expr(expr2));
+                let (value, overflow) = self.builder.ins().smul_overflow(a, b);
+                self.guard(overflow);
+                value
+            }
+            Expr::AndFuncExpr(expr1, expr2) => {
+                let (a, b) = (self.expr(expr1), self.expr(expr2));
+                let a = self.builder.ins().icmp_imm(IntCC::NotEqual, a, 0);
+                let b = self.builder.ins().icmp_imm(IntCC::NotEqual, b, 0);
+                let value = self.builder.ins().band(a, b);
+                self.builder.ins().uextend(types::I64, value)
+            }
+            Expr::OrFuncExpr(expr1, expr2) => {
+                let (a, b) = (self.expr(expr1), self.expr(expr2));
+                let a = self.builder.ins().icmp_imm(IntCC::NotEqual, a, 0);
+                let b = self.builder.ins().icmp_imm(IntCC::NotEqual, b, 0);
+                let value = self.builder.ins().bor(a, b);
+                self.builder.ins().uextend(types::I64, value)
+            }
+            Expr::EqFuncExpr(expr1, expr2) => self.compare(IntCC::Equal, expr1, expr2),
+            Expr::GtFuncExpr(expr1, expr2) => self.compare(IntCC::SignedGreaterThan, expr1, expr2),
+            Expr::LtFuncExpr(expr1, expr2) => self.compare(IntCC::SignedLessThan, expr1, expr2),
+            _ => unreachable!("expression is not accepted by collect_cells"),
+        }
+    }
+
+    fn compare(&mut self, cc: IntCC, expr1: &Expr, expr2: &Expr) -> IrValue {
+        let (a, b) = (self.expr(expr1), self.expr(expr2));
+        let value = self.builder.ins().icmp(cc, a, b);
+        self.builder.ins().uextend(types::I64, value)
+    }
+
+    // Deoptimizes when `overflow` is set.
+    fn guard(&mut self, overflow: IrValue) {
+        let next = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(overflow, self.deopt, &[], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn noodle_expr(code: &str) -> Expr {
+        parse(code).unwrap().noodles.remove(0).expr
+    }
+
+    #[test]
+    fn compile_integer_noodles() {
+        let mut jit = Jit::new().unwrap();
+
+        assert!(jit
+            .compile(&noodle_expr("{[0; @:2 = @:2 + @:3 * 2]}"), None)
+            .is_some());
+        assert!(jit
+            .compile(&noodle_expr("{[0; @:2 = @:2 + @:3 * 2]}"), Some(32))
+            .is_none());
+        assert!(jit
+            .compile(&noodle_expr("{[0; @:3/11 = !(@:0 < 3) & 1]}"), None)
+            .is_some());
+        // division, bowls and IO stay in the interpreter
+        assert!(jit
+            .compile(&noodle_expr("{[0; @:2 = (@:2) / 3]}"), None)
+            .is_none());
+        assert!(jit
+            .compile(&noodle_expr("{[0; @:2 = {[0; 1]}:0]}"), None)
+            .is_none());
+        assert!(jit
+            .compile(&noodle_expr("{[0; @:1 = @:2]}"), None)
+            .is_none());
+    }
+
+    #[test]
+    fn deoptimize_non_integers() {
+        let mut jit = Jit::new().unwrap();
+        let mut native = jit
+            .compile(&noodle_expr("{[0; @:2 = @:3 + 1]}"), None)
+            .unwrap();
+        let mut env = Env::new(Vec::new, |_| {}).with_engine(crate::env::Engine::Jit);
+        let three = Number::from(3);
+        let half = Number::one() / Number::from(2);
+        let max = Number::from(i64::MAX);
+
+        // a missing cell is null
+        assert!(call(&mut env, &mut native).is_none());
+        env.write_mem(&Value::from_number(&three), &Value::new_one());
+        assert!(call(&mut env, &mut native).is_some());
+        assert_eq!(format!("{}", env.mem_to_bowl()), "{[3; 1][2; 2]}");
+        env.write_mem(&Value::from_number(&three), &Value::from_number(&half));
+        assert!(call(&mut env, &mut native).is_none());
+        env.write_mem(&Value::from_number(&three), &Value::from_number(&max));
+        assert!(call(&mut env, &mut native).is_none());
+        assert_eq!(
+            format!("{}", env.mem_to_bowl()),
+            format!("{{[3; {}][2; 2]}}", i64::MAX)
+        );
+    }
+}
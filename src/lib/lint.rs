@@ -0,0 +1,182 @@
+use crate::{
+    datatype::{Bowl, Expr, Number, Value},
+    env::Extensions,
+    optimize::fold_expr,
+    parse_with,
+    syntax::{self, BowlNode, NoodleNode},
+};
+
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+pub fn lint(code: &str, extensions: &Extensions) -> Vec<Lint> {
+    let outline = syntax::scan(code);
+    let mut lints = vec![];
+    for bowl in &outline.bowls {
+        lint_bowl(code, bowl, extensions, &mut lints);
+    }
+    lints
+}
+
+// Evaluates a noodle number written in source, if it does not depend on memory.
+pub fn const_noodle_number(
+    code: &str,
+    noodle: &NoodleNode,
+    extensions: &Extensions,
+) -> Option<Number> {
+    let (start, end) = noodle.nn_range();
+    const_expr(&code[start..end], extensions)
+}
+
+pub fn const_expr(code: &str, extensions: &Extensions) -> Option<Number> {
+    let mut expr = parse_expr(code, extensions)?;
+    fold_expr(&mut expr, None);
+    match expr {
+        Expr::ValueExpr(Value::Number(number)) => Some(*number),
+        _ => None,
+    }
+}
+
+pub fn parse_expr(code: &str, extensions: &Extensions) -> Option<Expr> {
+    if code.trim().is_empty() {
+        return None;
+    }
+    let bowl = parse_with(&format!("{{[{}; 0]}}", code), extensions).ok()?;
+    bowl.noodles.into_iter().next().map(|noodle| noodle.nn_expr)
+}
+
+fn lint_bowl(code: &str, bowl: &BowlNode, extensions: &Extensions, lints: &mut Vec<Lint>) {
+    let mut numbers: Vec<(Number, usize)> = vec![];
+    for noodle in &bowl.noodles {
+        if let Some(number) = const_noodle_number(code, noodle, extensions) {
+            match numbers.iter().find(|(other, _)| *other == number) {
+                Some((_, offset)) => lints.push(Lint {
+                    start: noodle.start,
+                    end: noodle.end,
+                    message: format!(
+                        "noodle number {} is already used at line {}, so this noodle is never reached",
+                        number,
+                        syntax::line_of(code, *offset)
+                    ),
+                }),
+                None => numbers.push((number, noodle.start)),
+            }
+        }
+        let noodle_code = format!("{{{}}}", &code[noodle.start..noodle.end]);
+        if let Ok(Bowl { noodles }) = parse_with(&noodle_code, extensions) {
+            for parsed in &noodles {
+                lint_expr(&parsed.nn_expr, noodle, lints);
+                lint_expr(&parsed.expr, noodle, lints);
+            }
+        }
+        for inner in &noodle.bowls {
+            lint_bowl(code, inner, extensions, lints);
+        }
+    }
+}
+
+fn lint_expr(expr: &Expr, noodle: &NoodleNode, lints: &mut Vec<Lint>) {
+    let mut warn = |message: &str| {
+        lints.push(Lint {
+            start: noodle.start,
+            end: noodle.end,
+            message: message.to_string(),
+        })
+    };
+    match expr {
+        // nested bowls are linted on their own
+        Expr::ValueExpr(_) => {}
+        Expr::NumberSepFuncExpr(expr1, expr2) => {
+            let mut divisor = *expr2.clone();
+            fold_expr(&mut divisor, None);
+            if let Expr::ValueExpr(Value::Number(number)) = divisor {
+                if !number.bool() {
+                    warn("division by zero");
+                }
+            }
+            lint_expr(expr1, noodle, lints);
+            lint_expr(expr2, noodle, lints);
+        }
+        Expr::MemWriteExpr(nn_expr, value_expr) => {
+            let mut nn_expr = *nn_expr.clone();
+            fold_expr(&mut nn_expr, None);
+            if let Expr::ValueExpr(Value::Number(number)) = &nn_expr {
+                if **number == Number::zero() {
+                    warn("@:0 always reads the cursor, so this write has no effect");
+                }
+                if **number == Number::one() {
+                    if let Expr::ValueExpr(Value::Number(_)) = **value_expr {
+                        warn("only bowls can be written to @:1");
+                    }
+                }
+            }
+            lint_expr(&nn_expr, noodle, lints);
+            lint_expr(value_expr, noodle, lints);
+        }
+        Expr::BowlReadExpr(expr1, expr2)
+        | Expr::PlusFuncExpr(expr1, expr2)
+        | Expr::MinusFuncExpr(expr1, expr2)
+        | Expr::MulFuncExpr(expr1, expr2)
+        | Expr::AndFuncExpr(expr1, expr2)
+        | Expr::OrFuncExpr(expr1, expr2)
+        | Expr::EqFuncExpr(expr1, expr2)
+        | Expr::GtFuncExpr(expr1, expr2)
+        | Expr::LtFuncExpr(expr1, expr2) => {
+            lint_expr(expr1, noodle, lints);
+            lint_expr(expr2, noodle, lints);
+        }
+        Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+            lint_expr(expr1, noodle, lints);
+            lint_expr(expr2, noodle, lints);
+            lint_expr(expr3, noodle, lints);
+        }
+        Expr::MemReadExpr(expr) | Expr::DenoFuncExpr(expr) | Expr::NotFuncExpr(expr) => {
+            lint_expr(expr, noodle, lints);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(code: &str) -> Vec<String> {
+        lint(code, &Extensions::default())
+            .into_iter()
+            .map(|lint| lint.message)
+            .collect()
+    }
+
+    #[test]
+    fn lint_duplicated_noodle_number() {
+        let code = "{\n[1/2; @:2 = 1]\n[2/4; @:2 = 2]\n}";
+        assert_eq!(
+            messages(code),
+            vec!["noodle number 1/2 is already used at line 2, so this noodle is never reached"]
+        );
+    }
+
+    #[test]
+    fn lint_useless_writes() {
+        let code = "{[0; @:0 = 3][1; @:1 = 72][2; @:2 = 1/(1 - 1)][3; {[0; @:1/0 = 5]}]}";
+        assert_eq!(
+            messages(code),
+            vec![
+                "@:0 always reads the cursor, so this write has no effect",
+                "only bowls can be written to @:1",
+                "division by zero",
+                "division by zero",
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_clean_code() {
+        let code = "{[0; @:1 = {[0; 72][1; 10]}][@:2 + 1; @:2 = @:0 + 1]}";
+        assert!(messages(code).is_empty());
+    }
+}
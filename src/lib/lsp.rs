@@ -0,0 +1,540 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    datatype::{Expr, Number, Value},
+    env::Extensions,
+    lint::{self, const_noodle_number},
+    optimize, parse_with,
+    syntax::{self, BowlNode, NoodleNode},
+};
+
+const SEVERITY_ERROR: u32 = 1;
+const SEVERITY_WARNING: u32 = 2;
+const SYMBOL_FUNCTION: u32 = 12;
+// the largest message body read, well over any program edited by hand
+const MAX_MESSAGE: usize = 16 << 20;
+
+pub struct Server {
+    documents: HashMap<String, String>,
+    is_shutdown: bool,
+    // the extensions documents are checked with
+    extensions: Extensions,
+}
+
+pub fn serve<R: BufRead, W: Write>(
+    mut input: R,
+    mut output: W,
+    extensions: &Extensions,
+) -> io::Result<()> {
+    let mut server = Server {
+        documents: HashMap::new(),
+        is_shutdown: false,
+        extensions: *extensions,
+    };
+    while let Some(body) = read_message(&mut input)? {
+        let message: Json = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = json!({"code": -32700, "message": e.to_string()});
+                write_message(
+                    &mut output,
+                    &json!({"jsonrpc": "2.0", "id": null, "error": error}),
+                )?;
+                continue;
+            }
+        };
+        if message["method"] == "exit" {
+            break;
+        }
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.unwrap();
+    if length > MAX_MESSAGE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes is over {} bytes", length, MAX_MESSAGE),
+        ));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+impl Server {
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return self.handle_notification(method, params),
+        };
+        if self.is_shutdown {
+            return vec![error_reply(id, -32600, "server is shut down")];
+        }
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "documentFormattingProvider": true,
+                    "foldingRangeProvider": true,
+                },
+                "serverInfo": {"name": "rustbibim"},
+            }),
+            "shutdown" => {
+                self.is_shutdown = true;
+                Json::Null
+            }
+            "textDocument/hover" => self.with_document(params, hover),
+            "textDocument/documentSymbol" => self.with_document(params, document_symbols),
+            "textDocument/formatting" => self.with_document(params, formatting),
+            "textDocument/foldingRange" => self.with_document(params, folding_ranges),
+            _ => return vec![error_reply(id, -32601, "method not found")],
+        };
+        vec![json!({"jsonrpc": "2.0", "id": id, "result": result})]
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), text.to_string());
+            }
+            "textDocument/didChange" => {
+                // only full document sync is advertised, and changes to
+                // documents never opened are ignored
+                let document = match self.documents.get_mut(&uri) {
+                    Some(document) => document,
+                    None => return vec![],
+                };
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    *document = text.to_string();
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish_diagnostics(&uri, vec![])];
+            }
+            _ => return vec![],
+        }
+        let diagnostics = diagnostics(&self.documents[&uri], &self.extensions);
+        vec![publish_diagnostics(&uri, diagnostics)]
+    }
+
+    fn with_document(&self, params: &Json, handler: fn(&str, &Json, &Extensions) -> Json) -> Json {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match self.documents.get(uri) {
+            Some(code) => handler(code, params, &self.extensions),
+            None => Json::Null,
+        }
+    }
+}
+
+fn error_reply(id: Json, code: i32, message: &str) -> Json {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+pub fn diagnostics(code: &str, extensions: &Extensions) -> Vec<Json> {
+    let mut diagnostics = vec![];
+    if let Err(errs) = parse_with(code, extensions) {
+        for e in errs {
+            diagnostics.push(json!({
+                "range": range(code, e.start, e.end),
+                "severity": SEVERITY_ERROR,
+                "source": "rustbibim",
+                "message": e.message,
+            }));
+        }
+    }
+    for lint in lint::lint(code, extensions) {
+        diagnostics.push(json!({
+            "range": range(code, lint.start, lint.end),
+            "severity": SEVERITY_WARNING,
+            "source": "rustbibim",
+            "message": lint.message,
+        }));
+    }
+    diagnostics
+}
+
+fn hover(code: &str, params: &Json, extensions: &Extensions) -> Json {
+    let offset = offset(code, &params["position"]);
+    let outline = syntax::scan(code);
+    let (bowls, noodles) = syntax::path_at(&outline, offset);
+    if let Some(noodle) = noodles.last() {
+        let (start, end) = noodle.nn_range();
+        if start <= offset && offset <= end {
+            if let Some(number) = const_noodle_number(code, noodle, extensions) {
+                return hover_reply(code, start, end, format!("noodle number `{}`", number));
+            }
+        }
+    }
+    for bowl in bowls.iter().rev() {
+        if let Some(string) = bowl_string(&code[bowl.start..bowl.end], extensions) {
+            return hover_reply(code, bowl.start, bowl.end, format!("`{:?}`", string));
+        }
+    }
+    Json::Null
+}
+
+fn hover_reply(code: &str, start: usize, end: usize, value: String) -> Json {
+    json!({
+        "contents": {"kind": "markdown", "value": value},
+        "range": range(code, start, end),
+    })
+}
+
+// Renders a literal bowl the way writing it to @:1 would, if every noodle is constant.
+pub fn bowl_string(code: &str, extensions: &Extensions) -> Option<String> {
+    let mut bowl = parse_with(code, extensions).ok()?;
+    optimize::optimize(&mut bowl, None);
+    let mut bytes = vec![];
+    let mut index = Number::zero();
+    loop {
+        let mut found = None;
+        for noodle in &bowl.noodles {
+            match (&noodle.nn_expr, &noodle.expr) {
+                (Expr::ValueExpr(Value::Number(number)), expr) => {
+                    if **number == index && found.is_none() {
+                        found = Some(expr);
+                    }
+                }
+                _ => return None,
+            }
+        }
+        match found {
+            Some(Expr::ValueExpr(Value::Number(number))) => match u8::try_from(&**number) {
+                Ok(byte) => bytes.push(byte),
+                Err(_) => return None,
+            },
+            Some(_) => return None,
+            None => break,
+        }
+        index += Number::one();
+    }
+    if bytes.is_empty() || bytes.len() != bowl.noodles.len() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn document_symbols(code: &str, _params: &Json, _extensions: &Extensions) -> Json {
+    let outline = syntax::scan(code);
+    Json::Array(
+        outline
+            .bowls
+            .iter()
+            .flat_map(|bowl| bowl_symbols(code, bowl))
+            .collect(),
+    )
+}
+
+fn bowl_symbols(code: &str, bowl: &BowlNode) -> Vec<Json> {
+    bowl.noodles
+        .iter()
+        .filter(|noodle| noodle.sep.is_some())
+        .map(|noodle| noodle_symbol(code, noodle))
+        .collect()
+}
+
+fn noodle_symbol(code: &str, noodle: &NoodleNode) -> Json {
+    let (start, end) = noodle.nn_range();
+    let name = code[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let detail = match noodle.expr_range() {
+        Some((start, end)) => code[start..end]
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_string(),
+        None => String::new(),
+    };
+    let children: Vec<Json> = noodle
+        .bowls
+        .iter()
+        .flat_map(|bowl| bowl_symbols(code, bowl))
+        .collect();
+    json!({
+        "name": if name.is_empty() { "?".to_string() } else { name },
+        "detail": detail,
+        "kind": SYMBOL_FUNCTION,
+        "range": range(code, noodle.start, noodle.end),
+        "selectionRange": range(code, start, end),
+        "children": children,
+    })
+}
+
+fn formatting(code: &str, _params: &Json, _extensions: &Extensions) -> Json {
+    let formatted = syntax::format_code(code);
+    if formatted == code {
+        return json!([]);
+    }
+    json!([{"range": range(code, 0, code.len()), "newText": formatted}])
+}
+
+fn folding_ranges(code: &str, _params: &Json, _extensions: &Extensions) -> Json {
+    let outline = syntax::scan(code);
+    let mut ranges = vec![];
+    let mut bowls: Vec<&BowlNode> = outline.bowls.iter().collect();
+    while let Some(bowl) = bowls.pop() {
+        let start_line = syntax::line_of(code, bowl.start) - 1;
+        let end_line = syntax::line_of(code, bowl.end) - 1;
+        if start_line < end_line {
+            ranges.push(json!({"startLine": start_line, "endLine": end_line}));
+        }
+        for noodle in &bowl.noodles {
+            bowls.extend(noodle.bowls.iter());
+        }
+    }
+    for comment in &outline.comments {
+        let start_line = syntax::line_of(code, comment.start) - 1;
+        let end_line = syntax::line_of(code, comment.end) - 1;
+        if start_line < end_line {
+            ranges.push(json!({"startLine": start_line, "endLine": end_line, "kind": "comment"}));
+        }
+    }
+    Json::Array(ranges)
+}
+
+// LSP positions count UTF-16 code units within a line.
+fn position(code: &str, offset: usize) -> Json {
+    let offset = offset.min(code.len());
+    let line_start = code[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = code[..line_start].matches('\n').count();
+    let character: usize = code[line_start..offset].chars().map(char::len_utf16).sum();
+    json!({"line": line, "character": character})
+}
+
+fn range(code: &str, start: usize, end: usize) -> Json {
+    json!({"start": position(code, start), "end": position(code, end)})
+}
+
+fn offset(code: &str, position: &Json) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let mut line_start = 0;
+    for _ in 0..line {
+        match code[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return code.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in code[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    code.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: u32, method: &str, params: Json) -> Json {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    fn open(code: &str) -> Json {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": "file:///a.bibim", "languageId": "bibim", "version": 1, "text": code}},
+        })
+    }
+
+    fn document(extra: Json) -> Json {
+        let mut params = json!({"textDocument": {"uri": "file:///a.bibim"}});
+        if let (Json::Object(params), Json::Object(extra)) = (&mut params, extra) {
+            params.extend(extra);
+        }
+        params
+    }
+
+    fn session(messages: &[Json]) -> Vec<Json> {
+        session_with(messages, &Extensions::default())
+    }
+
+    fn session_with(messages: &[Json], extensions: &Extensions) -> Vec<Json> {
+        let mut input = vec![];
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = vec![];
+        serve(input.as_slice(), &mut output, extensions).unwrap();
+        let mut replies = vec![];
+        let mut output = output.as_slice();
+        while let Some(body) = read_message(&mut output).unwrap() {
+            replies.push(serde_json::from_slice(&body).unwrap());
+        }
+        replies
+    }
+
+    #[test]
+    fn initialize_and_shutdown() {
+        let replies = session(&[
+            request(1, "initialize", json!({})),
+            request(2, "shutdown", Json::Null),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+            request(3, "unknown", Json::Null),
+        ]);
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(replies[1]["result"], Json::Null);
+    }
+
+    #[test]
+    fn ignore_unopened_and_oversized() {
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": document(json!({"contentChanges": [{}]})),
+        });
+        assert_eq!(session(&[change]).len(), 0);
+
+        let mut input = format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE + 1);
+        input.push_str("{}");
+        let e = serve(input.as_bytes(), &mut vec![], &Extensions::default()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn publish_parse_errors_and_lints() {
+        let replies = session(&[open("{\n[0; @:1 = 72]\n[1; @:2 = ]\n}")]);
+        let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0]["severity"], SEVERITY_ERROR);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 2);
+        assert_eq!(diagnostics[1]["severity"], SEVERITY_WARNING);
+        assert_eq!(diagnostics[1]["range"]["start"]["line"], 1);
+    }
+
+    #[test]
+    fn check_with_extensions() {
+        let code = "{\n[0; @:2 = -1]\n}";
+        let replies = session(&[open(code)]);
+        let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+
+        let extensions = Extensions {
+            extended_literals: true,
+            ..Extensions::default()
+        };
+        let replies = session_with(&[open(code)], &extensions);
+        let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 0);
+    }
+
+    #[test]
+    fn hover_values() {
+        let code = "{\n    [1/2 + 1; @:1 = {[0; 72][1; 73][2; 5 + 5]}]\n    [@:2; 0]\n}";
+        let replies = session(&[
+            open(code),
+            request(
+                1,
+                "textDocument/hover",
+                document(json!({"position": {"line": 1, "character": 6}})),
+            ),
+            request(
+                2,
+                "textDocument/hover",
+                document(json!({"position": {"line": 1, "character": 26}})),
+            ),
+            request(
+                3,
+                "textDocument/hover",
+                document(json!({"position": {"line": 2, "character": 6}})),
+            ),
+        ]);
+        assert_eq!(
+            replies[1]["result"]["contents"]["value"],
+            "noodle number `3/2`"
+        );
+        assert_eq!(replies[2]["result"]["contents"]["value"], "`\"HI\\n\"`");
+        assert_eq!(replies[3]["result"], Json::Null);
+    }
+
+    #[test]
+    fn symbols_folding_and_formatting() {
+        let code = "{\n[0; {\n[1; @:2 = 1]\n}:(1)]\n}";
+        let replies = session(&[
+            open(code),
+            request(1, "textDocument/documentSymbol", document(json!({}))),
+            request(2, "textDocument/foldingRange", document(json!({}))),
+            request(
+                3,
+                "textDocument/formatting",
+                document(json!({"options": {"tabSize": 4, "insertSpaces": true}})),
+            ),
+        ]);
+        let symbols = replies[1]["result"].as_array().unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0]["name"], "0");
+        assert_eq!(symbols[0]["children"][0]["name"], "1");
+        assert_eq!(symbols[0]["children"][0]["detail"], "@:2 = 1");
+        let ranges = replies[2]["result"].as_array().unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(
+            replies[3]["result"][0]["newText"],
+            "{\n    [0; {\n        [1; @:2 = 1]\n    }:(1)]\n}"
+        );
+    }
+}
@@ -1,29 +1,120 @@
+pub mod asm;
+pub mod binary;
+pub mod bundle;
+pub mod compile;
+pub mod coverage;
 pub mod datatype;
+pub mod doc;
 pub mod env;
 pub mod error;
 pub mod eval;
-
-use env::Env;
+pub mod gc;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod lint;
+pub mod lsp;
+pub mod optimize;
+pub mod playground;
+pub mod profile;
+pub mod script;
+pub mod snapshot;
+pub mod syntax;
+pub mod transpile;
+pub mod vm;
+
+use datatype::{Bowl, Value};
+use env::{Engine, Env, Extensions};
 use lrlex::lrlex_mod;
-use lrpar::lrpar_mod;
+use lrpar::{lrpar_mod, LexParseError};
 use std::error as std_error;
 
 lrlex_mod!("bibim.l");
 lrpar_mod!("bibim.y");
 
-pub fn run(code: String, env: &mut Env) -> Result<(), Box<dyn std_error::Error>> {
+pub fn parse(code: &str) -> Result<Bowl, Vec<error::SyntaxError>> {
+    parse_with(code, &Extensions::default())
+}
+
+// Parses code, accepting the syntax of the enabled extensions.
+pub fn parse_with(code: &str, extensions: &Extensions) -> Result<Bowl, Vec<error::SyntaxError>> {
+    if !extensions.extended_literals {
+        let literals = syntax::extended_literals(code);
+        if !literals.is_empty() {
+            return Err(literals
+                .into_iter()
+                .map(|(start, end)| error::SyntaxError {
+                    start,
+                    end,
+                    message: format!(
+                        "`{}` at line {} needs the extended-literals extension",
+                        &code[start..end],
+                        syntax::line_of(code, start)
+                    ),
+                })
+                .collect());
+        }
+    }
     let lexerdef = bibim_l::lexerdef();
-    let lexer = lexerdef.lexer(code.as_str());
+    let lexer = lexerdef.lexer(code);
     let (res, errs) = bibim_y::parse(&lexer);
-    let mut is_failed = false;
-    for e in errs {
-        println!("{}", e.pp(&lexer, &bibim_y::token_epp));
-        is_failed = true;
+    if !errs.is_empty() {
+        return Err(errs
+            .iter()
+            .map(|e| {
+                let span = match e {
+                    LexParseError::LexError(e) => e.span(),
+                    LexParseError::ParseError(e) => e.lexeme().span(),
+                };
+                error::SyntaxError {
+                    start: span.start(),
+                    end: span.end(),
+                    message: e.pp(&lexer, &bibim_y::token_epp),
+                }
+            })
+            .collect());
+    }
+    match res {
+        Some(Ok(mut bowl)) => {
+            if extensions.extended_literals {
+                optimize::fold_fractions(&mut bowl);
+            }
+            Ok(bowl)
+        }
+        _ => Err(vec![]),
+    }
+}
+
+pub fn run(code: String, env: &mut Env) -> Result<(), Box<dyn std_error::Error>> {
+    let bowl = match parse_with(code.as_str(), &env.extensions) {
+        Ok(bowl) => bowl,
+        Err(errs) => {
+            for e in errs {
+                println!("{}", e.message);
+            }
+            return Err(Box::new(error::ParseError));
+        }
+    };
+    run_bowl(bowl, env)
+}
+
+pub fn run_bowl(mut bowl: Bowl, env: &mut Env) -> Result<(), Box<dyn std_error::Error>> {
+    if env.is_optimize {
+        optimize::optimize(&mut bowl, env.max_bits);
     }
-    if is_failed {
-        return Err(Box::new(error::ParseError));
+    if let Some(coverage) = env.coverage.as_mut() {
+        coverage.register(&bowl);
     }
-    match eval::eval(env, res.unwrap().unwrap()) {
+    // the profiler and coverage instrument the tree walking engine only
+    let is_instrumented = env.profiler.is_some() || env.coverage.is_some();
+    let result = match env.engine {
+        Engine::Vm if !is_instrumented => vm::run(env, &bowl),
+        #[cfg(feature = "jit")]
+        Engine::Jit if !is_instrumented => jit::run(env, &bowl),
+        _ => eval::eval_steps(env, &bowl, None),
+    };
+    // bowls the program holds in cycles are not freed by dropping it
+    gc::collect(vec![Value::from_bowl(bowl)]);
+    match result {
         Ok(_) => Ok(()),
         Err(e) => Err(e),
     }
@@ -34,6 +125,7 @@ mod tests {
     use std::sync::{Arc, Mutex};
 
     use super::*;
+    use crate::env::Assignment;
 
     #[test]
     fn simple_code() {
@@ -42,22 +134,8 @@ mod tests {
             [1; !0]
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
-
-        // test output
-        assert_eq!(*output.lock().unwrap(), "".as_bytes());
+        // run code and test output
+        check_output(code, "", |env| env, "");
     }
 
     #[test]
@@ -80,22 +158,11 @@ mod tests {
             }]
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        // run code and test output
+        check_output(code, "", |env| env, "HELLO WORLD\n");
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "HELLO WORLD\n".as_bytes());
+        // test the compiled C program
+        check_c(code, "", "HELLO WORLD\n");
     }
 
     #[test]
@@ -120,22 +187,11 @@ mod tests {
             }]
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
+        // run code and test output
+        check_output(code, "", |env| env, "HELLO WORLD\n");
 
-        // run code
-        run(code.to_string(), &mut env).unwrap();
-
-        // test output
-        assert_eq!(*output.lock().unwrap(), "HELLO WORLD\n".as_bytes());
+        // test the compiled C program
+        check_c(code, "", "HELLO WORLD\n");
     }
 
     #[test]
@@ -160,22 +216,11 @@ mod tests {
             [2; @:1 = @:2]
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        // run code and test output
+        check_output(code, "", |env| env, "HELLO WORLD\n");
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "HELLO WORLD\n".as_bytes());
+        // test the compiled C program
+        check_c(code, "", "HELLO WORLD\n");
     }
 
     #[test]
@@ -185,22 +230,11 @@ mod tests {
             [0; @:1 = @:1]
         }";
 
-        // setup env
-        let input = "test\n".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        // run code and test output
+        check_output(code, "test\n", |env| env, "test\n");
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "test\n".as_bytes());
+        // test the compiled C program
+        check_c(code, "test\n", "test\n");
     }
 
     #[test]
@@ -253,22 +287,11 @@ mod tests {
             [@:1/23 + 3; @:1/13 = @:0 - 0] ~# @:1/13 + 1 로 이동 (자리값 초기화 수행하는 자리로) #~
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        // run code and test output
+        check_output(code, "", |env| env, "112873");
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "112873".as_bytes());
+        // test the compiled C program
+        check_c(code, "", "112873");
     }
 
     #[test]
@@ -348,22 +371,11 @@ mod tests {
             [@:1/37 + 1; @:(@:3/11) = @:0 + 1] ~# 함수 종료 #~
         }";
 
-        // setup env
-        let input = "4\n".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        // run code and test output
+        check_output(code, "4\n", |env| env, "16\n");
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "16\n".as_bytes());
+        // test the compiled C program
+        check_c(code, "4\n", "16\n");
     }
 
     #[test]
@@ -399,22 +411,11 @@ mod tests {
             }:(@:1/2 < 100 + 1)]
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        // run code and test output
+        check_output(code, "", |env| env, "\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n");
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n".as_bytes());
+        // test the compiled C program
+        check_c(code, "", "\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n\nfizz\n\n\nfizzbuzz\n\n\nfizz\n\nbuzz\nfizz\n\n\nfizz\nbuzz\n");
     }
 
     #[test]
@@ -486,22 +487,11 @@ mod tests {
             [@:1/23 + 3; @:1/13 = @:0 - 0] ~# @:1/13 + 1 로 이동 (자리값 초기화 수행하는 자리로) #~
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        // run code and test output
+        check_output(code, "", |env| env, "233168\n");
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "233168\n".as_bytes());
+        // test the compiled C program
+        check_c(code, "", "233168\n");
     }
 
     #[test]
@@ -577,22 +567,11 @@ mod tests {
             [@:1/23 + 3; @:1/13 = @:0 - 0] ~# @:1/13 + 1 로 이동 (자리값 초기화 수행하는 자리로) #~
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
-        };
-
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        // run code and test output
+        check_output(code, "", |env| env, "4613732\n");
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "4613732\n".as_bytes());
+        // test the compiled C program
+        check_c(code, "", "4613732\n");
     }
 
     #[test]
@@ -668,21 +647,312 @@ mod tests {
             [@:1/23 + 3; @:1/13 = @:0 - 0] ~# @:1/13 + 1 로 이동 (자리값 초기화 수행하는 자리로) #~
         }";
 
-        // setup env
-        let input = "".as_bytes();
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: true,
-            on_read_io: Box::new(|| input.to_vec()),
-            on_write_io: Box::new(|data| output.lock().unwrap().extend(data)),
+        // run code and test output
+        check_output(code, "", |env| env, "6857\n");
+
+        // test the compiled C program
+        check_c(code, "", "6857\n");
+    }
+
+    #[test]
+    fn extended_literals() {
+        // code to test
+        let code = r"{
+            [0; @:2 = -0x30 / -1]
+            [1; @:1 = {[0; @:2 + 0.5 * 2][1; 0b1010]}]
+        }";
+
+        let errs = parse(code).unwrap_err();
+        assert_eq!(errs.len(), 5);
+        assert_eq!(
+            errs[0].message,
+            "`-` at line 2 needs the extended-literals extension"
+        );
+
+        let extensions = Extensions {
+            extended_literals: true,
+            ..Extensions::default()
         };
+        let bowl = parse_with("{[1/3; -2/4]}", &extensions).unwrap();
+        assert_eq!(bowl.to_string(), "{[1/3; -1/2]}");
 
-        // run code
-        run(code.to_string(), &mut env).unwrap();
+        check_output(code, "", |env| env.with_extensions(extensions), "1\n");
+    }
 
-        // test output
-        assert_eq!(*output.lock().unwrap(), "6857\n".as_bytes());
+    #[test]
+    fn structural_eq() {
+        // code to test, comparing the input and two empty cells
+        let code = r"{
+            [0; @:2 = @:1]
+            [1; @:1 = {[0; 48 + (@:2 ?= {[0; 97][1; 98]})][1; 48 + (@:9 ?= @:8)]}]
+        }";
+
+        for (is_structural_eq, expected) in [(false, "00"), (true, "11")] {
+            let extensions = Extensions {
+                structural_eq: is_structural_eq,
+                ..Extensions::default()
+            };
+            check_output(code, "ab", |env| env.with_extensions(extensions), expected);
+            check_c_with(code, &extensions, "ab", expected);
+        }
+    }
+
+    #[test]
+    fn loop_allocated_bowls() {
+        // code to test, keeping the bowl a literal makes on every iteration
+        let code = r"{
+            [0; @:7 = 0]
+            [1/3; @:3 = 0]
+            [1/2; @:6 = {}]
+            [@:7 + 1; @:5 = {[0; {[0; 0]}]}]
+            [@:7 + 2; ((@:5):(0)):(0) = 48 + @:3]
+            [@:7 + 3; (@:6):(@:3) = (@:5):(0)]
+            [@:7 + 4; @:3 = @:3 + 1]
+            [@:7 + 5; @:7 = @:7 + ({[0; 0][1; 10]}:(@:3 < 3))]
+            [1000; @:1 = {[0; ((@:6):(0)):(0)][1; ((@:6):(1)):(0)][2; ((@:6):(2)):(0)]}]
+        }";
+
+        check_output(code, "", |env| env, "012");
+        check_c(code, "", "012");
+    }
+
+    #[test]
+    fn assignment() {
+        // code to test, writing to a bowl assigned to another cell, and to a
+        // bowl holding itself
+        let code = r"{
+            [0; @:2 = {[0; 48][1; {[0; 48]}]}]
+            [1; @:3 = @:2]
+            [2; (@:3):(0) = 49]
+            [3; ((@:3):(1)):(0) = 49]
+            [4; @:1 = {[0; (@:2):(0)][1; ((@:2):(1)):(0)][2; (@:3):(0)][3; ((@:3):(1)):(0)]}]
+            [5; @:4 = {[0; 48]}]
+            [6; (@:4):(1) = @:4]
+            [7; (@:4):(0) = 49]
+            [8; @:1 = {[0; ((@:4):(1)):(0)]}]
+        }";
+
+        for (assignment, expected) in [
+            (Assignment::Reference, "11111"),
+            (Assignment::Value, "00110"),
+        ] {
+            check_output(code, "", |env| env.with_assignment(assignment), expected);
+        }
+    }
+
+    #[test]
+    fn self_referential_bowls() {
+        // code to test, with noodles reading and writing the bowl they are in
+        let code = r"{
+            [0; @:2 = {[0; 48][1; (@:2):(0) + 1][(@:2):(0) + 2; 50]}]
+            [1; @:1 = {[0; (@:2):(0)][1; (@:2):(1)][2; (@:2):(50)]}]
+            [2; (@:2):(50) = 51]
+            [3; (@:2):(4) = @:2]
+            [4; (@:2):(5) = ((@:2):(4)):(0) + 5]
+            [5; @:1 = {[0; (@:2):(50)][1; (@:2):(5)]}]
+            [6; @:2 = {[0; (@:2):(1) = 55][1; 0]}]
+            [7; @:3 = (@:2):(0)]
+            [8; @:1 = {[0; (@:2):(1)]}]
+        }";
+
+        check_output(code, "", |env| env, "012357");
+    }
+
+    #[test]
+    fn number_limit() {
+        // code to test, squaring 3 forever
+        let code = r"{
+            [0; @:2 = 3]
+            [@:0 + 1; @:2 = @:2 * @:2]
+        }";
+
+        check_configs(
+            code,
+            "",
+            |env| env.with_debug(false).with_max_bits(Some(1000)),
+            |result, env, _| {
+                // test the error, and that the write over the limit never happened
+                assert_eq!(
+                    result.unwrap_err().to_string(),
+                    "line 3: a number of 1624 bits is over the limit of 1000 bits, in [(@:(0))+(1); (@:(2) = ((@:(2))*(@:(2))))]"
+                );
+                match &env.mem[0].1 {
+                    Value::Number(number) => assert_eq!(number.bits(), 812),
+                    value => panic!("expected 3^512, got {}", value),
+                }
+            },
+        );
+
+        // counting until a sum on the way is over a limit below 64 bits, long
+        // after the JIT compiles the noodle
+        let code = r"{
+            [0; @:2 = 0]
+            [@:0 + 1; @:2 = @:2 + 1000 - 999]
+        }";
+
+        check_configs(
+            code,
+            "",
+            |env| env.with_debug(false).with_max_bits(Some(10)),
+            |result, env, _| {
+                assert_eq!(
+                    result.unwrap_err().to_string(),
+                    "line 3: a number of 11 bits is over the limit of 10 bits, in [(@:(0))+(1); (@:(2) = (((@:(2))+(1000))-(999)))]"
+                );
+                assert_eq!(env.mem[0].1.to_string(), "24");
+            },
+        );
+    }
+
+    #[test]
+    fn depth_limit() {
+        // code to test, a bowl reading itself forever, and one found by a
+        // noodle number reading itself forever
+        for (code, noodle) in [
+            (
+                r"{
+                    [0; @:2 = {[0; (@:2):0]}]
+                    [1; @:3 = (@:2):0]
+                }",
+                "[1; (@:(3) = ((@:(2)):(0)))]",
+            ),
+            (
+                r"{
+                    [0; @:2 = {[(@:2):1; 0]}]
+                    [1; (@:2):1 = 5]
+                }",
+                "[1; ((@:(2)):(1) = (5))]",
+            ),
+        ] {
+            check_configs(
+                code,
+                "",
+                |env| env.with_debug(false).with_max_depth(Some(100)),
+                |result, env, _| {
+                    // test the error, and that nothing was written by the noodle
+                    assert_eq!(
+                        result.unwrap_err().to_string(),
+                        format!(
+                            "line 3: evaluation is nested over the limit of 100 levels, in {}",
+                            noodle
+                        )
+                    );
+                    match &env.mem[..] {
+                        [(_, Value::Bowl(bowl))] => assert_eq!(bowl.borrow().noodles.len(), 1),
+                        _ => panic!("no bowl in memory"),
+                    }
+                },
+            );
+        }
+    }
+
+    // Every combination of optimization and engine the programs run with.
+    fn configs() -> Vec<(bool, Engine)> {
+        let mut configs = vec![];
+        #[allow(unused_mut)]
+        let mut engines = vec![Engine::Tree, Engine::Vm];
+        #[cfg(feature = "jit")]
+        engines.push(Engine::Jit);
+        for engine in engines {
+            for is_optimize in [false, true] {
+                configs.push((is_optimize, engine));
+            }
+        }
+        configs
+    }
+
+    // Runs `code` reading `input` in every config, on a debug environment
+    // `setup` changes, then checks the result, the environment and the output.
+    fn check_configs(
+        code: &str,
+        input: &str,
+        setup: impl for<'a> Fn(Env<'a>) -> Env<'a>,
+        check: impl Fn(Result<(), Box<dyn std_error::Error>>, &Env, &[u8]),
+    ) {
+        for (is_optimize, engine) in configs() {
+            let output = Arc::new(Mutex::new(Vec::new()));
+            let mut env = setup(
+                Env::new(
+                    || input.as_bytes().to_vec(),
+                    |data| output.lock().unwrap().extend(data),
+                )
+                .with_debug(true)
+                .with_optimize(is_optimize)
+                .with_engine(engine),
+            );
+            let result = run(code.to_string(), &mut env);
+            let output = output.lock().unwrap().clone();
+            check(result, &env, &output);
+        }
+    }
+
+    // Runs `code` with `input` in every configuration set up by `setup`, and
+    // tests its output.
+    fn check_output(
+        code: &str,
+        input: &str,
+        setup: impl for<'a> Fn(Env<'a>) -> Env<'a>,
+        expected: &str,
+    ) {
+        check_configs(code, input, setup, |result, _, output| {
+            result.unwrap();
+            assert_eq!(output, expected.as_bytes());
+        });
+    }
+
+    // Compiles `code` to C with the system compiler, then runs it with `input`.
+    // Skipped when no C compiler is installed.
+    fn check_c(code: &str, input: &str, expected: &str) {
+        check_c_with(code, &Extensions::default(), input, expected);
+    }
+
+    fn check_c_with(code: &str, extensions: &Extensions, input: &str, expected: &str) {
+        use std::{
+            io::Write,
+            process::{Command, Stdio},
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "rustbibim-c-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("main.c");
+        let binary = dir.join("main");
+        std::fs::write(
+            &source,
+            transpile::to_c(&parse_with(code, extensions).unwrap(), extensions),
+        )
+        .unwrap();
+        let status = match Command::new("cc")
+            .arg("-O2")
+            .arg("-o")
+            .arg(&binary)
+            .arg(&source)
+            .status()
+        {
+            Ok(status) => status,
+            Err(_) => return,
+        };
+        assert!(status.success());
+
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, expected.as_bytes());
     }
 }
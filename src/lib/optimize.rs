@@ -0,0 +1,249 @@
+use crate::{
+    datatype::{Bowl, Expr, Noodle, Value},
+    env::Env,
+    eval::eval_expr,
+};
+
+pub fn optimize(bowl: &mut Bowl, max_bits: Option<u64>) {
+    for noodle in bowl.noodles.iter_mut() {
+        optimize_noodle(noodle, max_bits);
+    }
+}
+
+pub fn optimize_noodle(noodle: &mut Noodle, max_bits: Option<u64>) {
+    fold_expr(&mut noodle.nn_expr, max_bits);
+    fold_expr(&mut noodle.expr, max_bits);
+}
+
+// Folds every subtree made only of number literals into a single `ValueExpr`.
+// Memory reads and writes are never folded, so side effects keep their order,
+// and neither are numbers over `max_bits`, so the engine reports them.
+pub fn fold_expr(expr: &mut Expr, max_bits: Option<u64>) {
+    let is_foldable = match expr {
+        Expr::ValueExpr(Value::Bowl(bowl)) => {
+            optimize(&mut bowl.borrow_mut(), max_bits);
+            false
+        }
+        Expr::ValueExpr(_) => false,
+        Expr::BowlReadExpr(expr1, expr2) => {
+            fold_expr(expr1, max_bits);
+            fold_expr(expr2, max_bits);
+            false
+        }
+        Expr::MemReadExpr(expr) => {
+            fold_expr(expr, max_bits);
+            false
+        }
+        Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+            fold_expr(expr1, max_bits);
+            fold_expr(expr2, max_bits);
+            fold_expr(expr3, max_bits);
+            false
+        }
+        Expr::MemWriteExpr(expr1, expr2) => {
+            fold_expr(expr1, max_bits);
+            fold_expr(expr2, max_bits);
+            false
+        }
+        Expr::DenoFuncExpr(expr) | Expr::NotFuncExpr(expr) => {
+            fold_expr(expr, max_bits);
+            is_number(expr)
+        }
+        Expr::NumberSepFuncExpr(expr1, expr2) => {
+            fold_expr(expr1, max_bits);
+            fold_expr(expr2, max_bits);
+            // division by zero is left to fail at runtime
+            is_number(expr1) && is_number(expr2) && !is_zero(expr2)
+        }
+        Expr::PlusFuncExpr(expr1, expr2)
+        | Expr::MinusFuncExpr(expr1, expr2)
+        | Expr::MulFuncExpr(expr1, expr2)
+        | Expr::AndFuncExpr(expr1, expr2)
+        | Expr::OrFuncExpr(expr1, expr2)
+        | Expr::EqFuncExpr(expr1, expr2)
+        | Expr::GtFuncExpr(expr1, expr2)
+        | Expr::LtFuncExpr(expr1, expr2) => {
+            fold_expr(expr1, max_bits);
+            fold_expr(expr2, max_bits);
+            is_number(expr1) && is_number(expr2)
+        }
+    };
+    if is_foldable {
+        // constant subtrees never touch the env, so any env evaluates them
+        let mut env = Env::new(Vec::new, |_| {}).with_max_bits(max_bits);
+        let value = eval_expr(&mut env, expr);
+        if !env.is_stopped() {
+            *expr = Expr::ValueExpr(value);
+        }
+    }
+}
+
+// Reads fractions of number literals, such as `1/3` or `-0.5/3`, as single
+// number literals, for the extended literals extension.
+pub fn fold_fractions(bowl: &mut Bowl) {
+    for noodle in bowl.noodles.iter_mut() {
+        fold_fraction(&mut noodle.nn_expr);
+        fold_fraction(&mut noodle.expr);
+    }
+}
+
+fn fold_fraction(expr: &mut Expr) {
+    match expr {
+        Expr::ValueExpr(Value::Bowl(bowl)) => fold_fractions(&mut bowl.borrow_mut()),
+        Expr::ValueExpr(_) => {}
+        Expr::NumberSepFuncExpr(expr1, expr2) => {
+            fold_fraction(expr1);
+            fold_fraction(expr2);
+            if let (Expr::ValueExpr(Value::Number(a)), Expr::ValueExpr(Value::Number(b))) =
+                (&**expr1, &**expr2)
+            {
+                if b.bool() {
+                    *expr = Expr::ValueExpr(Value::from_number(&(&**a / &**b)));
+                }
+            }
+        }
+        Expr::MemReadExpr(expr) | Expr::DenoFuncExpr(expr) | Expr::NotFuncExpr(expr) => {
+            fold_fraction(expr)
+        }
+        Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+            fold_fraction(expr1);
+            fold_fraction(expr2);
+            fold_fraction(expr3);
+        }
+        Expr::BowlReadExpr(expr1, expr2)
+        | Expr::MemWriteExpr(expr1, expr2)
+        | Expr::PlusFuncExpr(expr1, expr2)
+        | Expr::MinusFuncExpr(expr1, expr2)
+        | Expr::MulFuncExpr(expr1, expr2)
+        | Expr::AndFuncExpr(expr1, expr2)
+        | Expr::OrFuncExpr(expr1, expr2)
+        | Expr::EqFuncExpr(expr1, expr2)
+        | Expr::GtFuncExpr(expr1, expr2)
+        | Expr::LtFuncExpr(expr1, expr2) => {
+            fold_fraction(expr1);
+            fold_fraction(expr2);
+        }
+    }
+}
+
+fn is_number(expr: &Expr) -> bool {
+    matches!(expr, Expr::ValueExpr(Value::Number(_)))
+}
+
+fn is_zero(expr: &Expr) -> bool {
+    match expr {
+        Expr::ValueExpr(Value::Number(number)) => !number.bool(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::{BigInt, BigUint};
+
+    use super::*;
+    use crate::datatype::Number;
+
+    fn num(n: i32) -> Box<Expr> {
+        Box::new(Expr::ValueExpr(Value::from_big_int(
+            &BigInt::from(n),
+            &BigUint::from(1u32),
+        )))
+    }
+
+    fn folded(mut expr: Expr) -> Expr {
+        fold_expr(&mut expr, None);
+        expr
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        // 100 + 1
+        let expr = folded(Expr::PlusFuncExpr(num(100), num(1)));
+        assert_eq!(format!("{}", expr), "101");
+
+        // (0 - 3)/7
+        let expr = folded(Expr::NumberSepFuncExpr(
+            Box::new(Expr::MinusFuncExpr(num(0), num(3))),
+            num(7),
+        ));
+        match expr {
+            Expr::ValueExpr(Value::Number(number)) => {
+                assert_eq!(
+                    *number,
+                    Number::new(BigInt::from(-3), BigUint::from(7u32)).unwrap()
+                )
+            }
+            _ => panic!("expression is not folded"),
+        }
+
+        // 100 * 100, over a limit of 8 bits
+        let mut expr = Expr::MulFuncExpr(num(100), num(100));
+        fold_expr(&mut expr, Some(8));
+        assert_eq!(format!("{}", expr), "(100)*(100)");
+    }
+
+    #[test]
+    fn fold_comparison() {
+        // ^(4/2) ?= 1
+        let expr = folded(Expr::EqFuncExpr(
+            Box::new(Expr::DenoFuncExpr(Box::new(Expr::NumberSepFuncExpr(
+                num(4),
+                num(2),
+            )))),
+            num(1),
+        ));
+        assert_eq!(format!("{}", expr), "1");
+
+        // !(3 < 1)
+        let expr = folded(Expr::NotFuncExpr(Box::new(Expr::LtFuncExpr(
+            num(3),
+            num(1),
+        ))));
+        assert_eq!(format!("{}", expr), "1");
+    }
+
+    #[test]
+    fn keep_memory_access() {
+        // @:(1/2) = @:(1/2) + (2 * 3)
+        let expr = folded(Expr::MemWriteExpr(
+            Box::new(Expr::NumberSepFuncExpr(num(1), num(2))),
+            Box::new(Expr::PlusFuncExpr(
+                Box::new(Expr::MemReadExpr(Box::new(Expr::NumberSepFuncExpr(
+                    num(1),
+                    num(2),
+                )))),
+                Box::new(Expr::MulFuncExpr(num(2), num(3))),
+            )),
+        ));
+        assert_eq!(format!("{}", expr), "(@:(1/2) = ((@:(1/2))+(6)))");
+    }
+
+    #[test]
+    fn keep_division_by_zero() {
+        let expr = folded(Expr::NumberSepFuncExpr(
+            num(1),
+            Box::new(Expr::MinusFuncExpr(num(2), num(2))),
+        ));
+        assert_eq!(format!("{}", expr), "(1)/(0)");
+    }
+
+    #[test]
+    fn fold_bowl_literal() {
+        let mut bowl = Bowl {
+            noodles: vec![Noodle {
+                nn_expr: Expr::PlusFuncExpr(num(1), num(2)),
+                expr: Expr::ValueExpr(Value::from_bowl(Bowl {
+                    noodles: vec![Noodle {
+                        nn_expr: Expr::MulFuncExpr(num(2), num(5)),
+                        expr: Expr::PlusFuncExpr(num(72), num(1)),
+                        line: None,
+                    }],
+                })),
+                line: None,
+            }],
+        };
+        optimize(&mut bowl, None);
+        assert_eq!(format!("{}", bowl), "{[3; {[10; 73]}]}");
+    }
+}
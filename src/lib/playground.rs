@@ -0,0 +1,366 @@
+// A local web playground: `GET /` serves an HTML page, and `POST /run` runs
+// `{"code": ..., "input": ..., "format": ...}` within the step and time budget,
+// replying with the output, parse errors, the number of noodles evaluated and
+// the memory, its numbers in the format given. Each program runs on a thread
+// of its own, with a stack deep enough for the evaluation depth of the budget
+// and for the most nested code a request holds.
+
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, BufReader, Write},
+    net::TcpListener,
+    panic::{self, AssertUnwindSafe},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    datatype::{Formatted, NumberFormat},
+    env::Env,
+    error::{self, TimeLimitError},
+    eval::eval_steps,
+    parse, syntax,
+};
+
+const PAGE: &str = include_str!("playground.html");
+// the largest request body read
+const MAX_REQUEST: usize = 1 << 16;
+const STACK_SIZE: usize = 256 << 20;
+
+pub struct Budget {
+    pub steps: usize,
+    pub time: Duration,
+    // the largest number of bits of a numerator or denominator
+    pub max_bits: u64,
+    // the deepest evaluations may nest, such as bowls reading themselves
+    pub max_depth: usize,
+}
+
+impl Default for Budget {
+    fn default() -> Budget {
+        Budget {
+            steps: 1_000_000,
+            time: Duration::from_secs(5),
+            max_bits: 1 << 16,
+            max_depth: 10_000,
+        }
+    }
+}
+
+pub fn serve(listener: &TcpListener, budget: &Budget) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        if let Err(e) = handle(BufReader::new(&stream), &stream, budget) {
+            eprintln!("Error: {}", e);
+        }
+    }
+    Ok(())
+}
+
+// Answers one HTTP request.
+pub fn handle<R: BufRead, W: Write>(
+    mut input: R,
+    mut output: W,
+    budget: &Budget,
+) -> io::Result<()> {
+    let mut request_line = String::new();
+    input.read_line(&mut request_line)?;
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![];
+    if length <= MAX_REQUEST {
+        body.resize(length, 0);
+        input.read_exact(&mut body)?;
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, content) = match (parts.next(), parts.next()) {
+        _ if length > MAX_REQUEST => (
+            "413 Payload Too Large",
+            "text/plain",
+            "request too large".to_string(),
+        ),
+        (Some("GET"), Some("/")) => ("200 OK", "text/html", PAGE.to_string()),
+        (Some("POST"), Some("/run")) => match run_request(&body, budget) {
+            Ok(result) => ("200 OK", "application/json", result.to_string()),
+            Err(e) => (
+                "400 Bad Request",
+                "application/json",
+                json!({"error": e}).to_string(),
+            ),
+        },
+        _ => ("404 Not Found", "text/plain", "not found".to_string()),
+    };
+    write!(
+        output,
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        content.len(),
+        content
+    )?;
+    output.flush()
+}
+
+fn run_request(body: &[u8], budget: &Budget) -> Result<Json, String> {
+    let request = serde_json::from_slice::<Json>(body).map_err(|e| e.to_string())?;
+    let code = request["code"].as_str().unwrap_or("");
+    let input = request["input"].as_str().unwrap_or("");
+    let format = match request["format"].as_str() {
+        Some(format) => format
+            .parse()
+            .map_err(|e: error::NumberFormatParseError| e.to_string())?,
+        None => NumberFormat::Fraction,
+    };
+    Ok(run(code, input.as_bytes(), format, budget))
+}
+
+pub fn run(code: &str, input: &[u8], format: NumberFormat, budget: &Budget) -> Json {
+    thread::scope(|scope| {
+        let thread = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || run_code(code, input, format, budget));
+        match thread {
+            // panics are caught on the thread
+            Ok(thread) => thread.join().unwrap(),
+            Err(e) => json!({
+                "status": "error",
+                "output": "",
+                "errors": [{"message": e.to_string()}],
+                "steps": 0,
+                "cursor": null,
+                "memory": [],
+            }),
+        }
+    })
+}
+
+fn run_code(code: &str, input: &[u8], format: NumberFormat, budget: &Budget) -> Json {
+    let bowl = match parse(code) {
+        Ok(bowl) => bowl,
+        Err(errs) => {
+            let mut errors: Vec<Json> = errs
+                .iter()
+                .map(|e| {
+                    json!({
+                        "start": e.start,
+                        "end": e.end,
+                        "line": syntax::line_of(code, e.start),
+                        "message": e.message,
+                    })
+                })
+                .collect();
+            if errors.is_empty() {
+                errors.push(
+                    json!({"start": 0, "end": 0, "line": 1, "message": "invalid Bibim code"}),
+                );
+            }
+            return json!({
+                "status": "parse error",
+                "output": "",
+                "errors": errors,
+                "steps": 0,
+                "cursor": null,
+                "memory": [],
+            });
+        }
+    };
+    let output = RefCell::new(vec![]);
+    let started = Instant::now();
+    let mut env = Env::new(|| input.to_vec(), |data| output.borrow_mut().extend(data))
+        .with_max_bits(Some(budget.max_bits))
+        .with_max_depth(Some(budget.max_depth))
+        .with_deadline(Some(started + budget.time));
+    let mut steps = 0;
+    let mut errors = vec![];
+    // one noodle at a time, to check the budget in between
+    let result = panic::catch_unwind(AssertUnwindSafe(|| loop {
+        match eval_steps(&mut env, &bowl, Some(1)) {
+            Ok(true) => return "finished",
+            Ok(false) => {}
+            // a noodle running out of time is stopped before it finishes
+            Err(e) if e.is::<TimeLimitError>() => return "time limit",
+            Err(e) => {
+                errors.push(json!({"message": e.to_string()}));
+                return "error";
+            }
+        }
+        steps += 1;
+        if steps >= budget.steps {
+            return "step limit";
+        }
+        if started.elapsed() > budget.time {
+            return "time limit";
+        }
+    }));
+    let status = result.unwrap_or("error");
+    let memory: Vec<Json> = env
+        .mem
+        .iter()
+        .map(|(nn, value)| {
+            json!({
+                "nn": nn.format(format),
+                "value": Formatted(value, format).to_string(),
+            })
+        })
+        .collect();
+    let cursor = env.cursor.as_ref().map(|cursor| cursor.format(format));
+    env.collect();
+    drop(env);
+    json!({
+        "status": status,
+        "output": String::from_utf8_lossy(&output.into_inner()),
+        "errors": errors,
+        "steps": steps,
+        "cursor": cursor,
+        "memory": memory,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, net::TcpStream, thread};
+
+    use super::*;
+
+    fn post(body: &str) -> String {
+        let request = format!(
+            "POST /run HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut response = vec![];
+        handle(request.as_bytes(), &mut response, &Budget::default()).unwrap();
+        String::from_utf8(response).unwrap()
+    }
+
+    fn json_body(response: &str) -> Json {
+        serde_json::from_str(response.split_once("\r\n\r\n").unwrap().1).unwrap()
+    }
+
+    #[test]
+    fn run_code() {
+        let response = post(r#"{"code": "{[0; @:1 = @:1][1; @:2 = 7/2]}", "input": "Hi"}"#);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        let result = json_body(&response);
+        assert_eq!(result["status"], "finished");
+        assert_eq!(result["output"], "Hi");
+        assert_eq!(result["steps"], 2);
+        assert_eq!(result["cursor"], "1");
+        assert_eq!(result["memory"][0], json!({"nn": "2", "value": "7/2"}));
+
+        let result = json_body(&post(
+            r#"{"code": "{[0; @:2/3 = 1/3]}", "format": "decimal"}"#,
+        ));
+        assert_eq!(
+            result["memory"][0],
+            json!({"nn": "0.(6)", "value": "0.(3)"})
+        );
+        assert!(post(r#"{"code": "", "format": "roman"}"#).starts_with("HTTP/1.1 400"));
+
+        let request = format!("POST /run HTTP/1.1\r\nContent-Length: {}\r\n\r\n", 1 << 30);
+        let mut response = vec![];
+        handle(request.as_bytes(), &mut response, &Budget::default()).unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 413"));
+    }
+
+    #[test]
+    fn parse_errors_and_budgets() {
+        let result = json_body(&post(r#"{"code": "{\n[0; @:1 = ]}"}"#));
+        assert_eq!(result["status"], "parse error");
+        assert_eq!(result["errors"][0]["line"], 2);
+
+        // loops forever, moving its last noodle ahead of the cursor
+        let budget = Budget {
+            steps: 100,
+            time: Duration::from_secs(5),
+            ..Budget::default()
+        };
+        let result = run("{[0; 0][@:0 + 1; 0]}", b"", NumberFormat::Fraction, &budget);
+        assert_eq!(result["status"], "step limit");
+        assert_eq!(result["steps"], 100);
+
+        let budget = Budget {
+            steps: usize::MAX,
+            time: Duration::from_millis(10),
+            ..Budget::default()
+        };
+        assert_eq!(
+            run("{[0; 0][@:0 + 1; 0]}", b"", NumberFormat::Fraction, &budget)["status"],
+            "time limit"
+        );
+
+        // a single noodle reading 2^40 noodles of a bowl
+        let mut code = "{[0; @:2 = {".to_string();
+        for nn in 0..40 {
+            code += &format!("[{}; (@:2):{} + (@:2):{}]", nn, nn + 1, nn + 1);
+        }
+        code += "[40; 1]}][1; @:3 = (@:2):0]}";
+        let result = run(&code, b"", NumberFormat::Fraction, &budget);
+        assert_eq!(result["status"], "time limit");
+        assert_eq!(result["steps"], 1);
+
+        // a bowl reading itself forever
+        let result = run(
+            "{[0; @:2 = {[0; (@:2):0]}][1; @:3 = (@:2):0]}",
+            b"",
+            NumberFormat::Fraction,
+            &Budget::default(),
+        );
+        assert_eq!(result["status"], "error");
+        assert!(result["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("nested over the limit of 10000 levels"));
+
+        // squares 3 until it does not fit in the budget
+        let result = run(
+            "{[0; @:2 = 3][@:0 + 1; @:2 = @:2 * @:2]}",
+            b"",
+            NumberFormat::Fraction,
+            &Budget::default(),
+        );
+        assert_eq!(result["status"], "error");
+        assert_eq!(result["steps"], 16);
+        assert!(result["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("is over the limit of 65536 bits"));
+    }
+
+    #[test]
+    fn serve_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle(BufReader::new(&stream), &stream, &Budget::default()).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Bibim Playground"));
+    }
+}
@@ -0,0 +1,188 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use crate::datatype::{Noodle, Number};
+
+#[derive(Debug, Clone)]
+pub struct NoodleProfile {
+    pub line: Option<usize>,
+    pub noodle: String,
+    pub count: u64,
+    pub eval_time: Duration,
+    pub scan_time: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct CellProfile {
+    pub cell: Number,
+    pub reads: u64,
+    pub writes: u64,
+    // accesses per index of the noodle running at the time
+    pub noodles: HashMap<usize, u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    pub noodles: HashMap<usize, NoodleProfile>,
+    pub cells: HashMap<Number, CellProfile>,
+    pub scan_time: Duration,
+    pub current: Option<usize>,
+}
+
+impl Profiler {
+    pub fn select(&mut self, index: usize, noodle: &Noodle, scan_time: Duration) {
+        let profile = self.noodles.entry(index).or_insert_with(|| NoodleProfile {
+            line: noodle.line,
+            noodle: format!("{}", noodle.nn_expr),
+            count: 0,
+            eval_time: Duration::ZERO,
+            scan_time: Duration::ZERO,
+        });
+        profile.count += 1;
+        profile.scan_time += scan_time;
+        self.scan_time += scan_time;
+        self.current = Some(index);
+    }
+
+    pub fn finish(&mut self, eval_time: Duration) {
+        if let Some(profile) = self.current.and_then(|index| self.noodles.get_mut(&index)) {
+            profile.eval_time += eval_time;
+        }
+    }
+
+    pub fn read_cell(&mut self, cell: &Number) {
+        self.cell(cell).reads += 1;
+    }
+
+    pub fn write_cell(&mut self, cell: &Number) {
+        self.cell(cell).writes += 1;
+    }
+
+    fn cell(&mut self, cell: &Number) -> &mut CellProfile {
+        let current = self.current;
+        let profile = self
+            .cells
+            .entry(cell.clone())
+            .or_insert_with(|| CellProfile {
+                cell: cell.clone(),
+                reads: 0,
+                writes: 0,
+                noodles: HashMap::new(),
+            });
+        if let Some(index) = current {
+            *profile.noodles.entry(index).or_insert(0) += 1;
+        }
+        profile
+    }
+
+    pub fn sorted_noodles(&self) -> Vec<&NoodleProfile> {
+        let mut noodles: Vec<&NoodleProfile> = self.noodles.values().collect();
+        noodles.sort_by(|a, b| {
+            (b.eval_time + b.scan_time)
+                .cmp(&(a.eval_time + a.scan_time))
+                .then(a.line.cmp(&b.line))
+        });
+        noodles
+    }
+
+    pub fn sorted_cells(&self) -> Vec<&CellProfile> {
+        let mut cells: Vec<&CellProfile> = self.cells.values().collect();
+        cells.sort_by(|a, b| {
+            (b.reads + b.writes)
+                .cmp(&(a.reads + a.writes))
+                .then_with(|| a.cell.cmp(&b.cell))
+        });
+        cells
+    }
+
+    fn lines_of(&self, cell: &CellProfile) -> String {
+        let mut noodles: Vec<(&usize, &u64)> = cell.noodles.iter().collect();
+        noodles.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        noodles
+            .iter()
+            .take(3)
+            .map(
+                |(index, count)| match self.noodles.get(index).and_then(|profile| profile.line) {
+                    Some(line) => format!("{}({})", line, count),
+                    None => format!("?({})", count),
+                },
+            )
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let eval_time: Duration = self.noodles.values().map(|profile| profile.eval_time).sum();
+        writeln!(
+            f,
+            "[profile] eval: {:.3}ms, scan: {:.3}ms",
+            millis(eval_time),
+            millis(self.scan_time)
+        )?;
+        writeln!(
+            f,
+            "{:>6} {:>10} {:>12} {:>12}  noodle",
+            "line", "count", "eval(ms)", "scan(ms)"
+        )?;
+        for profile in self.sorted_noodles() {
+            let line = match profile.line {
+                Some(line) => line.to_string(),
+                None => "?".to_string(),
+            };
+            writeln!(
+                f,
+                "{:>6} {:>10} {:>12.3} {:>12.3}  {}",
+                line,
+                profile.count,
+                millis(profile.eval_time),
+                millis(profile.scan_time),
+                profile.noodle
+            )?;
+        }
+        writeln!(f, "{:>12} {:>10} {:>10}  lines", "cell", "reads", "writes")?;
+        for cell in self.sorted_cells().iter().take(10) {
+            writeln!(
+                f,
+                "{:>12} {:>10} {:>10}  {}",
+                format!("@:{}", cell.cell),
+                cell.reads,
+                cell.writes,
+                self.lines_of(cell)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{env::Env, run};
+
+    use super::*;
+
+    #[test]
+    fn profile_loop() {
+        let code = "{\n[3; @:2 = 0 - 10]\n[0; @:2 = 0]\n[@:2 + 1; @:2 = @:2 + 1]\n}";
+        let mut env = Env::new(Vec::new, |_| {}).with_profiler(Some(Profiler::default()));
+        run(code.to_string(), &mut env).unwrap();
+        let profiler = env.profiler.unwrap();
+
+        let mut noodles: Vec<(Option<usize>, u64)> = profiler
+            .noodles
+            .values()
+            .map(|profile| (profile.line, profile.count))
+            .collect();
+        noodles.sort();
+        assert_eq!(noodles, vec![(Some(2), 1), (Some(3), 1), (Some(4), 2)]);
+
+        let cell = profiler.sorted_cells()[0];
+        assert_eq!(format!("{}", cell.cell), "2");
+        assert_eq!(cell.writes, 4);
+        assert!(format!("{}", profiler).contains("@:2"));
+    }
+}
@@ -0,0 +1,720 @@
+/* Runtime of Bibim programs compiled to C. Numbers are reduced rationals of
+ * arbitrary size, bowls are reference counted and shared like `Rc<RefCell<Bowl>>`,
+ * and memory is a list of cells searched in insertion order like `Env::mem`. */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static void bibim_panic(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "%s\n", message);
+    exit(101);
+}
+
+static void *bibim_alloc(size_t size) {
+    void *p = malloc(size ? size : 1);
+    if (!p) bibim_panic("out of memory");
+    return p;
+}
+
+/* natural numbers, little endian 32 bit limbs without leading zeros */
+
+typedef struct {
+    size_t len;
+    uint32_t *d;
+} Nat;
+
+static Nat nat_new(size_t len) {
+    Nat n;
+    n.len = len;
+    n.d = bibim_alloc(len * sizeof(uint32_t));
+    memset(n.d, 0, len * sizeof(uint32_t));
+    return n;
+}
+
+static void nat_trim(Nat *n) {
+    while (n->len > 0 && n->d[n->len - 1] == 0) n->len--;
+}
+
+static Nat nat_from_u32(uint32_t value) {
+    Nat n = nat_new(1);
+    n.d[0] = value;
+    nat_trim(&n);
+    return n;
+}
+
+static Nat nat_copy(const Nat *a) {
+    Nat n = nat_new(a->len);
+    memcpy(n.d, a->d, a->len * sizeof(uint32_t));
+    return n;
+}
+
+static int nat_cmp(const Nat *a, const Nat *b) {
+    if (a->len != b->len) return a->len < b->len ? -1 : 1;
+    for (size_t i = a->len; i-- > 0;) {
+        if (a->d[i] != b->d[i]) return a->d[i] < b->d[i] ? -1 : 1;
+    }
+    return 0;
+}
+
+static int nat_is_u32(const Nat *a, uint32_t value) {
+    if (value == 0) return a->len == 0;
+    return a->len == 1 && a->d[0] == value;
+}
+
+static Nat nat_add(const Nat *a, const Nat *b) {
+    const Nat *x = a->len >= b->len ? a : b;
+    const Nat *y = a->len >= b->len ? b : a;
+    Nat n = nat_new(x->len + 1);
+    uint64_t carry = 0;
+    for (size_t i = 0; i < x->len; i++) {
+        carry += (uint64_t)x->d[i] + (i < y->len ? y->d[i] : 0);
+        n.d[i] = (uint32_t)carry;
+        carry >>= 32;
+    }
+    n.d[x->len] = (uint32_t)carry;
+    nat_trim(&n);
+    return n;
+}
+
+/* a - b, where a >= b */
+static Nat nat_sub(const Nat *a, const Nat *b) {
+    Nat n = nat_new(a->len);
+    int64_t borrow = 0;
+    for (size_t i = 0; i < a->len; i++) {
+        int64_t diff = (int64_t)a->d[i] - (i < b->len ? b->d[i] : 0) - borrow;
+        borrow = diff < 0;
+        n.d[i] = (uint32_t)(diff + (borrow ? ((int64_t)1 << 32) : 0));
+    }
+    nat_trim(&n);
+    return n;
+}
+
+static Nat nat_mul(const Nat *a, const Nat *b) {
+    Nat n = nat_new(a->len + b->len);
+    for (size_t i = 0; i < a->len; i++) {
+        uint64_t carry = 0;
+        for (size_t j = 0; j < b->len; j++) {
+            carry += (uint64_t)a->d[i] * b->d[j] + n.d[i + j];
+            n.d[i + j] = (uint32_t)carry;
+            carry >>= 32;
+        }
+        n.d[i + b->len] = (uint32_t)carry;
+    }
+    nat_trim(&n);
+    return n;
+}
+
+static Nat nat_mul_small(const Nat *a, uint32_t m, uint32_t add) {
+    Nat n = nat_new(a->len + 1);
+    uint64_t carry = add;
+    for (size_t i = 0; i < a->len; i++) {
+        carry += (uint64_t)a->d[i] * m;
+        n.d[i] = (uint32_t)carry;
+        carry >>= 32;
+    }
+    n.d[a->len] = (uint32_t)carry;
+    nat_trim(&n);
+    return n;
+}
+
+static int nlz(uint32_t x) {
+    int n = 0;
+    if (x == 0) return 32;
+    while (!(x & 0x80000000u)) {
+        x <<= 1;
+        n++;
+    }
+    return n;
+}
+
+/* Knuth's algorithm D. b must not be zero. */
+static void nat_divmod(const Nat *a, const Nat *b, Nat *q, Nat *r) {
+    if (nat_cmp(a, b) < 0) {
+        *q = nat_new(0);
+        *r = nat_copy(a);
+        return;
+    }
+    size_t n = b->len, m = a->len;
+    if (n == 1) {
+        uint64_t rem = 0;
+        *q = nat_new(m);
+        for (size_t i = m; i-- > 0;) {
+            uint64_t cur = (rem << 32) | a->d[i];
+            q->d[i] = (uint32_t)(cur / b->d[0]);
+            rem = cur % b->d[0];
+        }
+        nat_trim(q);
+        *r = nat_from_u32((uint32_t)rem);
+        return;
+    }
+    int s = nlz(b->d[n - 1]);
+    uint32_t *vn = bibim_alloc(n * sizeof(uint32_t));
+    uint32_t *un = bibim_alloc((m + 1) * sizeof(uint32_t));
+    for (size_t i = n - 1; i > 0; i--)
+        vn[i] = (b->d[i] << s) | (s ? (uint32_t)((uint64_t)b->d[i - 1] >> (32 - s)) : 0);
+    vn[0] = b->d[0] << s;
+    un[m] = s ? (uint32_t)((uint64_t)a->d[m - 1] >> (32 - s)) : 0;
+    for (size_t i = m - 1; i > 0; i--)
+        un[i] = (a->d[i] << s) | (s ? (uint32_t)((uint64_t)a->d[i - 1] >> (32 - s)) : 0);
+    un[0] = a->d[0] << s;
+    *q = nat_new(m - n + 1);
+    for (size_t j = m - n + 1; j-- > 0;) {
+        uint64_t num = ((uint64_t)un[j + n] << 32) | un[j + n - 1];
+        uint64_t qhat = num / vn[n - 1];
+        uint64_t rhat = num % vn[n - 1];
+        while (qhat >= ((uint64_t)1 << 32) ||
+               qhat * vn[n - 2] > ((rhat << 32) | un[j + n - 2])) {
+            qhat--;
+            rhat += vn[n - 1];
+            if (rhat >= ((uint64_t)1 << 32)) break;
+        }
+        int64_t borrow = 0;
+        uint64_t carry = 0;
+        for (size_t i = 0; i < n; i++) {
+            uint64_t p = qhat * vn[i] + carry;
+            carry = p >> 32;
+            int64_t t = (int64_t)un[i + j] - borrow - (int64_t)(uint32_t)p;
+            un[i + j] = (uint32_t)t;
+            borrow = t < 0;
+        }
+        int64_t t = (int64_t)un[j + n] - borrow - (int64_t)carry;
+        un[j + n] = (uint32_t)t;
+        if (t < 0) {
+            qhat--;
+            uint64_t c = 0;
+            for (size_t i = 0; i < n; i++) {
+                c += (uint64_t)un[i + j] + vn[i];
+                un[i + j] = (uint32_t)c;
+                c >>= 32;
+            }
+            un[j + n] += (uint32_t)c;
+        }
+        q->d[j] = (uint32_t)qhat;
+    }
+    nat_trim(q);
+    *r = nat_new(n);
+    for (size_t i = 0; i < n; i++)
+        r->d[i] = (un[i] >> s) | (s ? (uint32_t)((uint64_t)un[i + 1] << (32 - s)) : 0);
+    nat_trim(r);
+    free(vn);
+    free(un);
+}
+
+static Nat nat_gcd(const Nat *a, const Nat *b) {
+    Nat x = nat_copy(a), y = nat_copy(b);
+    while (y.len > 0) {
+        Nat q, r;
+        nat_divmod(&x, &y, &q, &r);
+        free(q.d);
+        free(x.d);
+        x = y;
+        y = r;
+    }
+    free(y.d);
+    return x;
+}
+
+static Nat nat_parse(const char *digits) {
+    Nat n = nat_new(0);
+    for (; *digits; digits++) {
+        Nat next = nat_mul_small(&n, 10, (uint32_t)(*digits - '0'));
+        free(n.d);
+        n = next;
+    }
+    return n;
+}
+
+/* numbers, always reduced with a positive denominator */
+
+typedef struct {
+    int refs;
+    int neg;
+    Nat num;
+    Nat den;
+} Num;
+
+static Num *num_make(int neg, Nat num, Nat den) {
+    if (den.len == 0) bibim_panic("denominator cannot be zero");
+    Num *n = bibim_alloc(sizeof(Num));
+    n->refs = 1;
+    if (!nat_is_u32(&den, 1) && num.len > 0) {
+        Nat g = nat_gcd(&num, &den);
+        if (!nat_is_u32(&g, 1)) {
+            Nat q, r;
+            nat_divmod(&num, &g, &q, &r);
+            free(r.d);
+            free(num.d);
+            num = q;
+            nat_divmod(&den, &g, &q, &r);
+            free(r.d);
+            free(den.d);
+            den = q;
+        }
+        free(g.d);
+    }
+    if (num.len == 0) {
+        neg = 0;
+        free(den.d);
+        den = nat_from_u32(1);
+    }
+    n->neg = neg;
+    n->num = num;
+    n->den = den;
+    return n;
+}
+
+static Num *num_parse(const char *numerator, const char *denominator) {
+    int neg = numerator[0] == '-';
+    return num_make(neg, nat_parse(numerator + neg), nat_parse(denominator));
+}
+
+static Num *num_small(uint32_t value) {
+    return num_make(0, nat_from_u32(value), nat_from_u32(1));
+}
+
+static void num_release(Num *n) {
+    if (--n->refs == 0) {
+        free(n->num.d);
+        free(n->den.d);
+        free(n);
+    }
+}
+
+/* signed addition of a * bd and b * ad */
+static Num *num_add_signed(const Num *a, const Num *b, int b_neg) {
+    Nat x = nat_mul(&a->num, &b->den);
+    Nat y = nat_mul(&b->num, &a->den);
+    Nat den = nat_mul(&a->den, &b->den);
+    Nat num;
+    int neg;
+    if (a->neg == b_neg) {
+        num = nat_add(&x, &y);
+        neg = a->neg;
+    } else if (nat_cmp(&x, &y) >= 0) {
+        num = nat_sub(&x, &y);
+        neg = a->neg;
+    } else {
+        num = nat_sub(&y, &x);
+        neg = b_neg;
+    }
+    free(x.d);
+    free(y.d);
+    return num_make(neg, num, den);
+}
+
+static Num *num_mul(const Num *a, const Num *b) {
+    return num_make(a->neg != b->neg, nat_mul(&a->num, &b->num), nat_mul(&a->den, &b->den));
+}
+
+static Num *num_div(const Num *a, const Num *b) {
+    return num_make(a->neg != b->neg, nat_mul(&a->num, &b->den), nat_mul(&a->den, &b->num));
+}
+
+static int num_cmp(const Num *a, const Num *b) {
+    if (a->neg != b->neg) {
+        if (a->num.len == 0 && b->num.len == 0) return 0;
+        return a->neg ? -1 : 1;
+    }
+    Nat x = nat_mul(&a->num, &b->den);
+    Nat y = nat_mul(&b->num, &a->den);
+    int c = nat_cmp(&x, &y);
+    free(x.d);
+    free(y.d);
+    return a->neg ? -c : c;
+}
+
+static int num_is_u32(const Num *a, uint32_t value) {
+    return !a->neg && nat_is_u32(&a->num, value) && nat_is_u32(&a->den, 1);
+}
+
+/* values */
+
+typedef enum { T_NULL, T_NUM, T_BOWL } Tag;
+
+typedef struct {
+    Tag tag;
+    void *p;
+} Value;
+
+typedef Value (*Thunk)(void);
+
+/* a noodle either runs compiled expressions or holds written values */
+typedef struct {
+    Thunk nn_fn;
+    Thunk fn;
+    Value nn;
+    Value value;
+} Noodle;
+
+typedef struct {
+    int refs;
+    size_t len, cap;
+    Noodle *noodles;
+} Bowl;
+
+static const Value NULL_VALUE = {T_NULL, NULL};
+
+static Value retain(Value v) {
+    if (v.tag == T_NUM) ((Num *)v.p)->refs++;
+    if (v.tag == T_BOWL) ((Bowl *)v.p)->refs++;
+    return v;
+}
+
+static void release(Value v) {
+    if (v.tag == T_NUM) num_release(v.p);
+    if (v.tag == T_BOWL) {
+        Bowl *b = v.p;
+        if (--b->refs == 0) {
+            for (size_t i = 0; i < b->len; i++) {
+                release(b->noodles[i].nn);
+                release(b->noodles[i].value);
+            }
+            free(b->noodles);
+            free(b);
+        }
+    }
+}
+
+static Value num_value(Num *n) {
+    Value v = {T_NUM, n};
+    return v;
+}
+
+static Value small_value(uint32_t value) {
+    return num_value(num_small(value));
+}
+
+static Bowl *bowl_new(void) {
+    Bowl *b = bibim_alloc(sizeof(Bowl));
+    b->refs = 1;
+    b->len = 0;
+    b->cap = 0;
+    b->noodles = NULL;
+    return b;
+}
+
+static void bowl_push(Bowl *b, Noodle noodle) {
+    if (b->len == b->cap) {
+        b->cap = b->cap ? b->cap * 2 : 4;
+        Noodle *noodles = bibim_alloc(b->cap * sizeof(Noodle));
+        if (b->len) memcpy(noodles, b->noodles, b->len * sizeof(Noodle));
+        free(b->noodles);
+        b->noodles = noodles;
+    }
+    b->noodles[b->len++] = noodle;
+}
+
+static Value bowl_value(Bowl *b) {
+    Value v = {T_BOWL, b};
+    return v;
+}
+
+/* a new bowl from the bowl literal `literal`, as `new_bowl` makes one: the
+ * literals its noodles hold are copied along, and the thunks are shared */
+static Value bowl_copy(Bowl *literal) {
+    Bowl *b = bowl_new();
+    for (size_t i = 0; i < literal->len; i++) {
+        Noodle noodle = literal->noodles[i];
+        noodle.nn = noodle.nn.tag == T_BOWL ? bowl_copy(noodle.nn.p) : retain(noodle.nn);
+        noodle.value = noodle.value.tag == T_BOWL ? bowl_copy(noodle.value.p) : retain(noodle.value);
+        bowl_push(b, noodle);
+    }
+    return bowl_value(b);
+}
+
+static Value noodle_nn(Noodle *noodle) {
+    return noodle->nn_fn ? noodle->nn_fn() : retain(noodle->nn);
+}
+
+static Value noodle_value(Noodle *noodle) {
+    return noodle->fn ? noodle->fn() : retain(noodle->value);
+}
+
+static int is_equal_num(Value a, const Num *b) {
+    return a.tag == T_NUM && num_cmp(a.p, b) == 0;
+}
+
+static Value bowl_read(Bowl *b, const Num *nn) {
+    for (size_t i = 0; i < b->len; i++) {
+        Value inner = noodle_nn(&b->noodles[i]);
+        int found = is_equal_num(inner, nn);
+        release(inner);
+        if (found) return noodle_value(&b->noodles[i]);
+    }
+    return NULL_VALUE;
+}
+
+/* takes ownership of nn and value */
+static void bowl_write(Bowl *b, Value nn, Value value) {
+    for (size_t i = 0; i < b->len; i++) {
+        Value inner = noodle_nn(&b->noodles[i]);
+        int found = is_equal_num(inner, nn.p);
+        release(inner);
+        if (found) {
+            release(b->noodles[i].value);
+            b->noodles[i].fn = NULL;
+            b->noodles[i].value = value;
+            release(nn);
+            return;
+        }
+    }
+    Noodle noodle = {NULL, NULL, nn, value};
+    bowl_push(b, noodle);
+}
+
+/* memory */
+
+typedef struct {
+    Num *nn;
+    Value value;
+} Cell;
+
+static Cell *mem = NULL;
+static size_t mem_len = 0, mem_cap = 0;
+static Num *cursor = NULL;
+
+static Value read_io(void) {
+    Bowl *b = bowl_new();
+    int c;
+    uint32_t index = 0;
+    while ((c = getchar()) != EOF) {
+        Noodle noodle = {NULL, NULL, small_value(index++), small_value((uint32_t)c)};
+        bowl_push(b, noodle);
+    }
+    return bowl_value(b);
+}
+
+static void write_io(Bowl *b) {
+    uint32_t index = 0;
+    for (;;) {
+        Num *nn = num_small(index);
+        Value value = bowl_read(b, nn);
+        num_release(nn);
+        if (value.tag != T_NUM) {
+            release(value);
+            break;
+        }
+        Num *n = value.p;
+        int is_byte = !n->neg && nat_is_u32(&n->den, 1) && n->num.len <= 1 &&
+                      (n->num.len == 0 || n->num.d[0] < 256);
+        uint32_t byte = n->num.len ? n->num.d[0] : 0;
+        release(value);
+        if (!is_byte) break;
+        putchar((int)byte);
+        index++;
+    }
+    fflush(stdout);
+}
+
+static Value mem_read(Num *nn) {
+    if (num_is_u32(nn, 0)) {
+        if (!cursor) return NULL_VALUE;
+        cursor->refs++;
+        return num_value(cursor);
+    }
+    if (num_is_u32(nn, 1)) return read_io();
+    for (size_t i = 0; i < mem_len; i++) {
+        if (num_cmp(mem[i].nn, nn) == 0) return retain(mem[i].value);
+    }
+    return NULL_VALUE;
+}
+
+/* takes ownership of value */
+static void mem_write(Num *nn, Value value) {
+    if (num_is_u32(nn, 1)) {
+        if (value.tag == T_BOWL) write_io(value.p);
+        release(value);
+        return;
+    }
+    for (size_t i = 0; i < mem_len; i++) {
+        if (num_cmp(mem[i].nn, nn) == 0) {
+            release(mem[i].value);
+            mem[i].value = value;
+            return;
+        }
+    }
+    if (mem_len == mem_cap) {
+        mem_cap = mem_cap ? mem_cap * 2 : 16;
+        Cell *cells = bibim_alloc(mem_cap * sizeof(Cell));
+        if (mem_len) memcpy(cells, mem, mem_len * sizeof(Cell));
+        free(mem);
+        mem = cells;
+    }
+    nn->refs++;
+    mem[mem_len].nn = nn;
+    mem[mem_len].value = value;
+    mem_len++;
+}
+
+/* operators, each taking ownership of its operands like `eval_expr` */
+
+static Value b_bowl_read(Value b, Value nn) {
+    Value result = NULL_VALUE;
+    if (b.tag == T_BOWL && nn.tag == T_NUM) result = bowl_read(b.p, nn.p);
+    release(b);
+    release(nn);
+    return result;
+}
+
+static Value b_mem_read(Value nn) {
+    Value result = NULL_VALUE;
+    if (nn.tag == T_NUM) result = mem_read(nn.p);
+    release(nn);
+    return result;
+}
+
+static Value b_bowl_write(Value b, Value nn, Value value) {
+    if (b.tag == T_BOWL && nn.tag == T_NUM) {
+        bowl_write(b.p, nn, value);
+    } else {
+        release(nn);
+        release(value);
+    }
+    release(b);
+    return NULL_VALUE;
+}
+
+static Value b_mem_write(Value nn, Value value) {
+    if (nn.tag == T_NUM) {
+        mem_write(nn.p, value);
+    } else {
+        release(value);
+    }
+    release(nn);
+    return NULL_VALUE;
+}
+
+static Value b_deno(Value a) {
+    Value result = NULL_VALUE;
+    if (a.tag == T_NUM) result = num_value(num_make(0, nat_copy(&((Num *)a.p)->den), nat_from_u32(1)));
+    release(a);
+    return result;
+}
+
+enum { OP_ADD, OP_SUB, OP_MUL, OP_DIV, OP_AND, OP_OR, OP_EQ, OP_GT, OP_LT };
+
+static Value b_binary(int op, Value a, Value b) {
+    Value result = NULL_VALUE;
+    if (a.tag == T_NUM && b.tag == T_NUM) {
+        Num *x = a.p, *y = b.p;
+        switch (op) {
+        case OP_ADD: result = num_value(num_add_signed(x, y, y->neg)); break;
+        case OP_SUB: result = num_value(num_add_signed(x, y, y->num.len ? !y->neg : 0)); break;
+        case OP_MUL: result = num_value(num_mul(x, y)); break;
+        case OP_DIV: result = num_value(num_div(x, y)); break;
+        case OP_AND: result = small_value(x->num.len && y->num.len); break;
+        case OP_OR: result = small_value(x->num.len || y->num.len); break;
+        case OP_EQ: result = small_value(num_cmp(x, y) == 0); break;
+        case OP_GT: result = small_value(num_cmp(x, y) > 0); break;
+        case OP_LT: result = small_value(num_cmp(x, y) < 0); break;
+        }
+    } else if (op >= OP_AND) {
+        result = small_value(0);
+    }
+    release(a);
+    release(b);
+    return result;
+}
+
+/* the values reading a bowl finds, by noodle number */
+typedef struct {
+    size_t len;
+    Value *nns, *values;
+} Entries;
+
+static Entries bowl_entries(Bowl *b) {
+    size_t len = b->len;
+    Entries e = {0, bibim_alloc(len * sizeof(Value)), bibim_alloc(len * sizeof(Value))};
+    for (size_t i = 0; i < len; i++) {
+        Value nn = noodle_nn(&b->noodles[i]);
+        /* the first noodle with a number shadows the others */
+        int is_new = nn.tag == T_NUM;
+        for (size_t j = 0; is_new && j < e.len; j++) is_new = num_cmp(e.nns[j].p, nn.p) != 0;
+        if (is_new) {
+            e.nns[e.len] = nn;
+            e.values[e.len++] = noodle_value(&b->noodles[i]);
+        } else {
+            release(nn);
+        }
+    }
+    return e;
+}
+
+static void entries_release(Entries *e) {
+    for (size_t i = 0; i < e->len; i++) {
+        release(e->nns[i]);
+        release(e->values[i]);
+    }
+    free(e->nns);
+    free(e->values);
+}
+
+/* the bowls being compared, taken as equal when met again */
+typedef struct Pair {
+    const void *a, *b;
+    const struct Pair *next;
+} Pair;
+
+/* `Value::structural_eq` */
+static int is_structural_eq(Value a, Value b, const Pair *comparing) {
+    if (a.tag != b.tag) return 0;
+    if (a.tag == T_NULL) return 1;
+    if (a.tag == T_NUM) return num_cmp(a.p, b.p) == 0;
+    if (a.p == b.p) return 1;
+    for (const Pair *pair = comparing; pair; pair = pair->next) {
+        if (pair->a == a.p && pair->b == b.p) return 1;
+    }
+    Entries e1 = bowl_entries(a.p), e2 = bowl_entries(b.p);
+    Pair pair = {a.p, b.p, comparing};
+    int is_equal = e1.len == e2.len;
+    for (size_t i = 0; is_equal && i < e1.len; i++) {
+        size_t j = 0;
+        while (j < e2.len && num_cmp(e1.nns[i].p, e2.nns[j].p) != 0) j++;
+        is_equal = j < e2.len && is_structural_eq(e1.values[i], e2.values[j], &pair);
+    }
+    entries_release(&e1);
+    entries_release(&e2);
+    return is_equal;
+}
+
+static Value b_structural_eq(Value a, Value b) {
+    Value result = small_value(is_structural_eq(a, b, NULL));
+    release(a);
+    release(b);
+    return result;
+}
+
+static Value b_not(Value a) {
+    Value result = small_value(!(a.tag == T_NUM && num_is_u32(a.p, 1)));
+    release(a);
+    return result;
+}
+
+/* the loop of `eval`, over the noodles of the program bowl */
+static void run(Bowl *program) {
+    for (;;) {
+        size_t next = 0;
+        Value min = NULL_VALUE;
+        for (size_t i = 0; i < program->len; i++) {
+            Value nn = noodle_nn(&program->noodles[i]);
+            int is_nextable = nn.tag == T_NUM && (!cursor || num_cmp(nn.p, cursor) > 0);
+            if (is_nextable && (min.tag == T_NULL || num_cmp(nn.p, min.p) < 0)) {
+                release(min);
+                min = nn;
+                next = i;
+            } else {
+                release(nn);
+            }
+        }
+        if (min.tag == T_NULL) break;
+        release(min);
+        Value nn = noodle_nn(&program->noodles[next]);
+        if (nn.tag != T_NUM) bibim_panic("Cannot set cursor to non-number value");
+        if (cursor) num_release(cursor);
+        cursor = nn.p;
+        release(noodle_value(&program->noodles[next]));
+    }
+}
@@ -0,0 +1,456 @@
+// Parser of Bibim script, a small structured language compiled to Bibim by
+// `compile`:
+//
+//     fn sum(n) {
+//         var total = 0;
+//         while n > 0 {
+//             total = total + n;
+//             n = n - 1;
+//         }
+//         return total;
+//     }
+//
+//     fn main() {
+//         var s = "sum: _";
+//         s[5] = sum(3) + 48;
+//         print s;
+//     }
+//
+// Statements are `var`, assignments to variables or `bowl[index]`, `if`/`else`,
+// `while`, `return`, `print` and expressions. Strings are bowls of bytes.
+// `input()` reads the standard input and `deno(x)` returns the denominator.
+
+use num_bigint::BigInt;
+
+use crate::error::CompileError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone)]
+pub enum ScriptExpr {
+    Number(BigInt),
+    Str(Vec<u8>),
+    Var(String),
+    Call(String, Vec<ScriptExpr>),
+    Index(Box<ScriptExpr>, Box<ScriptExpr>),
+    Unary(UnaryOp, Box<ScriptExpr>),
+    Binary(BinaryOp, Box<ScriptExpr>, Box<ScriptExpr>),
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
+    Var(String, ScriptExpr),
+    Assign(String, ScriptExpr),
+    IndexAssign(ScriptExpr, ScriptExpr, ScriptExpr),
+    If(ScriptExpr, Vec<Statement>, Vec<Statement>),
+    While(ScriptExpr, Vec<Statement>),
+    Return(Option<ScriptExpr>),
+    Print(ScriptExpr),
+    Expr(ScriptExpr),
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Statement>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(BigInt),
+    Str(Vec<u8>),
+    Ident(String),
+    Punct(&'static str),
+}
+
+const PUNCTS: [&str; 22] = [
+    "==", "!=", ">=", "<=", "(", ")", "{", "}", "[", "]", ",", ";", "=", ">", "<", "+", "-", "*",
+    "/", "&", "|", "!",
+];
+
+const KEYWORDS: [&str; 7] = ["fn", "var", "if", "else", "while", "return", "print"];
+
+fn error(line: usize, message: &str) -> CompileError {
+    CompileError {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, CompileError> {
+    let bytes = source.as_bytes();
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut i = 0;
+    'outer: while i < bytes.len() {
+        let c = bytes[i];
+        if c == b'\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_ascii_whitespace() {
+            i += 1;
+        } else if source[i..].starts_with("//") {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            let number = source[start..i].parse().unwrap();
+            tokens.push((Token::Number(number), line));
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(source[start..i].to_string()), line));
+        } else if c == b'"' {
+            let start_line = line;
+            let mut string = vec![];
+            i += 1;
+            loop {
+                match bytes.get(i) {
+                    None => return Err(error(start_line, "unterminated string")),
+                    Some(b'"') => break,
+                    Some(b'\\') => {
+                        let escaped = match bytes.get(i + 1) {
+                            Some(b'n') => b'\n',
+                            Some(b't') => b'\t',
+                            Some(b'0') => 0,
+                            Some(b'\\') => b'\\',
+                            Some(b'"') => b'"',
+                            _ => return Err(error(line, "invalid escape in string")),
+                        };
+                        string.push(escaped);
+                        i += 2;
+                    }
+                    Some(byte) => {
+                        if *byte == b'\n' {
+                            line += 1;
+                        }
+                        string.push(*byte);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push((Token::Str(string), start_line));
+        } else {
+            for punct in PUNCTS.iter() {
+                if source[i..].starts_with(punct) {
+                    tokens.push((Token::Punct(punct), line));
+                    i += punct.len();
+                    continue 'outer;
+                }
+            }
+            let c = source[i..].chars().next().unwrap();
+            return Err(error(line, &format!("unexpected character `{}`", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+pub fn parse_script(source: &str) -> Result<Vec<Function>, CompileError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+    };
+    let mut functions = vec![];
+    while parser.peek().is_some() {
+        functions.push(parser.function()?);
+    }
+    Ok(functions)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some((_, line)) => *line,
+            None => self.tokens.last().map(|(_, line)| *line).unwrap_or(1),
+        }
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(name)) if name == keyword)
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        if self.is_punct(punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), CompileError> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", punct)))
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<String, CompileError> {
+        match self.peek() {
+            Some(Token::Ident(name)) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> CompileError {
+        let found = match self.peek() {
+            Some(Token::Number(number)) => format!("`{}`", number),
+            Some(Token::Str(_)) => "a string".to_string(),
+            Some(Token::Ident(name)) => format!("`{}`", name),
+            Some(Token::Punct(punct)) => format!("`{}`", punct),
+            None => "end of file".to_string(),
+        };
+        error(
+            self.line(),
+            &format!("expected {}, found {}", expected, found),
+        )
+    }
+
+    fn function(&mut self) -> Result<Function, CompileError> {
+        let line = self.line();
+        if !self.eat_keyword("fn") {
+            return Err(self.unexpected("`fn`"));
+        }
+        let name = self.expect_name()?;
+        self.expect_punct("(")?;
+        let mut params = vec![];
+        if !self.eat_punct(")") {
+            loop {
+                params.push(self.expect_name()?);
+                if self.eat_punct(")") {
+                    break;
+                }
+                self.expect_punct(",")?;
+            }
+        }
+        let body = self.block()?;
+        Ok(Function {
+            name,
+            params,
+            body,
+            line,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, CompileError> {
+        self.expect_punct("{")?;
+        let mut statements = vec![];
+        while !self.eat_punct("}") {
+            if self.peek().is_none() {
+                return Err(self.unexpected("`}`"));
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, CompileError> {
+        let line = self.line();
+        let kind = if self.eat_keyword("var") {
+            let name = self.expect_name()?;
+            self.expect_punct("=")?;
+            let expr = self.expr()?;
+            self.expect_punct(";")?;
+            StatementKind::Var(name, expr)
+        } else if self.eat_keyword("if") {
+            let cond = self.expr()?;
+            let then_body = self.block()?;
+            let else_body = if self.eat_keyword("else") {
+                if self.is_keyword("if") {
+                    vec![self.statement()?]
+                } else {
+                    self.block()?
+                }
+            } else {
+                vec![]
+            };
+            StatementKind::If(cond, then_body, else_body)
+        } else if self.eat_keyword("while") {
+            let cond = self.expr()?;
+            StatementKind::While(cond, self.block()?)
+        } else if self.eat_keyword("return") {
+            let expr = if self.is_punct(";") {
+                None
+            } else {
+                Some(self.expr()?)
+            };
+            self.expect_punct(";")?;
+            StatementKind::Return(expr)
+        } else if self.eat_keyword("print") {
+            let expr = self.expr()?;
+            self.expect_punct(";")?;
+            StatementKind::Print(expr)
+        } else {
+            let expr = self.expr()?;
+            let kind = if self.eat_punct("=") {
+                let value = self.expr()?;
+                match expr {
+                    ScriptExpr::Var(name) => StatementKind::Assign(name, value),
+                    ScriptExpr::Index(bowl, index) => {
+                        StatementKind::IndexAssign(*bowl, *index, value)
+                    }
+                    _ => return Err(error(line, "invalid assignment target")),
+                }
+            } else {
+                StatementKind::Expr(expr)
+            };
+            self.expect_punct(";")?;
+            kind
+        };
+        Ok(Statement { kind, line })
+    }
+
+    fn expr(&mut self) -> Result<ScriptExpr, CompileError> {
+        self.binary(0)
+    }
+
+    // Precedence climbing, from `|` and `&` up to `*` and `/`.
+    fn binary(&mut self, level: usize) -> Result<ScriptExpr, CompileError> {
+        const LEVELS: [&[(&str, BinaryOp)]; 4] = [
+            &[("&", BinaryOp::And), ("|", BinaryOp::Or)],
+            &[
+                ("==", BinaryOp::Eq),
+                ("!=", BinaryOp::Ne),
+                (">=", BinaryOp::Ge),
+                ("<=", BinaryOp::Le),
+                (">", BinaryOp::Gt),
+                ("<", BinaryOp::Lt),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[("*", BinaryOp::Mul), ("/", BinaryOp::Div)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut expr = self.binary(level + 1)?;
+        'outer: loop {
+            for (punct, op) in LEVELS[level] {
+                if self.eat_punct(punct) {
+                    let rhs = self.binary(level + 1)?;
+                    expr = ScriptExpr::Binary(*op, Box::new(expr), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(expr);
+        }
+    }
+
+    fn unary(&mut self) -> Result<ScriptExpr, CompileError> {
+        if self.eat_punct("-") {
+            Ok(ScriptExpr::Unary(UnaryOp::Neg, Box::new(self.unary()?)))
+        } else if self.eat_punct("!") {
+            Ok(ScriptExpr::Unary(UnaryOp::Not, Box::new(self.unary()?)))
+        } else {
+            self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> Result<ScriptExpr, CompileError> {
+        let mut expr = self.primary()?;
+        while self.eat_punct("[") {
+            let index = self.expr()?;
+            self.expect_punct("]")?;
+            expr = ScriptExpr::Index(Box::new(expr), Box::new(index));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<ScriptExpr, CompileError> {
+        match self.peek().cloned() {
+            Some(Token::Number(number)) => {
+                self.pos += 1;
+                Ok(ScriptExpr::Number(number))
+            }
+            Some(Token::Str(string)) => {
+                self.pos += 1;
+                Ok(ScriptExpr::Str(string))
+            }
+            Some(Token::Punct("(")) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect_punct(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) if !KEYWORDS.contains(&name.as_str()) => {
+                self.pos += 1;
+                if !self.eat_punct("(") {
+                    return Ok(ScriptExpr::Var(name));
+                }
+                let mut args = vec![];
+                if !self.eat_punct(")") {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat_punct(")") {
+                            break;
+                        }
+                        self.expect_punct(",")?;
+                    }
+                }
+                Ok(ScriptExpr::Call(name, args))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Snapshots of a running program, to resume it later or elsewhere. A snapshot
+// holds the program itself, so it resumes without the source, along with the
+// memory and the cursor. Every bowl reachable from them is written
+// once, so sharing and cycles between them are kept.
+//
+//     snapshot := "BIBS" version:u8 fingerprint:u64 bowl mem cursor table
+//     mem      := count:varint (number value)*
+//     cursor   := 0 | 1 number
+//
+// The fingerprint identifies the program the snapshot was taken from, before
+// it started running.
+
+use std::error;
+
+use crate::{
+    binary::{self, Decoder, Encoder},
+    datatype::{Bowl, Number, Value},
+    env::Env,
+    error::DecodeError,
+    eval::eval_steps,
+};
+
+const MAGIC: &[u8] = b"BIBS";
+const VERSION: u8 = 1;
+
+pub struct Snapshot {
+    pub fingerprint: u64,
+    pub program: Bowl,
+    pub mem: Vec<(Number, Value)>,
+    pub cursor: Option<Number>,
+}
+
+// FNV-1a of the binary encoding of `bowl`.
+pub fn fingerprint(bowl: &Bowl) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in binary::encode(bowl) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Runs at most `steps` noodles of `program` with the tree walking engine, and
+// takes a snapshot if it has not finished by then.
+pub fn run(
+    env: &mut Env,
+    program: Bowl,
+    fingerprint: u64,
+    steps: Option<usize>,
+) -> Result<Option<Snapshot>, Box<dyn error::Error>> {
+    if eval_steps(env, &program, steps)? {
+        return Ok(None);
+    }
+    Ok(Some(Snapshot::take(env, program, fingerprint)))
+}
+
+impl Snapshot {
+    pub fn take(env: &Env, program: Bowl, fingerprint: u64) -> Snapshot {
+        Snapshot {
+            fingerprint,
+            program,
+            mem: env.mem.clone(),
+            cursor: env.cursor.clone(),
+        }
+    }
+
+    // Puts the memory and the cursor back into `env`, and returns the program
+    // to continue with.
+    pub fn restore(self, env: &mut Env) -> Bowl {
+        env.mem = self.mem;
+        env.cursor = self.cursor;
+        self.program
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new(true);
+        encoder.data.extend(MAGIC);
+        encoder.data.push(VERSION);
+        encoder.data.extend(self.fingerprint.to_le_bytes());
+        encoder.bowl(&self.program);
+        encoder.varint(self.mem.len());
+        for (nn, value) in &self.mem {
+            encoder.number(nn);
+            encoder.value(value);
+        }
+        match &self.cursor {
+            Some(cursor) => {
+                encoder.data.push(1);
+                encoder.number(cursor);
+            }
+            None => encoder.data.push(0),
+        }
+        encoder.table();
+        encoder.data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Snapshot, DecodeError> {
+        let mut decoder = Decoder::new(data, true);
+        decoder.header(MAGIC, VERSION, "not a Bibim snapshot")?;
+        let fingerprint = u64::from_le_bytes(decoder.bytes(8)?.try_into().unwrap());
+        let program = decoder.bowl()?;
+        let mut mem = vec![];
+        for _ in 0..decoder.varint()? {
+            mem.push((decoder.number()?, decoder.value()?));
+        }
+        let cursor = match decoder.byte()? {
+            0 => None,
+            1 => Some(decoder.number()?),
+            _ => return Err(decoder.error("invalid cursor")),
+        };
+        decoder.table()?;
+        decoder.finish()?;
+        Ok(Snapshot {
+            fingerprint,
+            program,
+            mem,
+            cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{datatype::Expr, eval::eval, parse};
+
+    fn env<'a>(output: &'a RefCell<Vec<u8>>) -> Env<'a> {
+        Env::new(Vec::new, move |data| output.borrow_mut().extend(data))
+    }
+
+    #[test]
+    fn resume() {
+        // counts to 5 in a bowl shared by two cells, printing each digit
+        let code = r"{
+            [0; @:2 = {[0; 0]}]
+            [1; @:3 = @:2]
+            [{[0; (@:2):0 * 2 + 2]}:((@:2):0 ?= 5); (@:2):0 = (@:2):0 + 1]
+            [(@:3):0 * 2 + 1; @:1 = {[0; 48 + (@:3):0]}]
+        }";
+        let fingerprint = fingerprint(&parse(code).unwrap());
+
+        let expected = RefCell::new(vec![]);
+        eval(&mut env(&expected), parse(code).unwrap()).unwrap();
+
+        let output = RefCell::new(vec![]);
+        let mut data = {
+            let mut env = env(&output);
+            let snapshot = run(&mut env, parse(code).unwrap(), fingerprint, Some(4)).unwrap();
+            snapshot.unwrap().encode()
+        };
+        loop {
+            let snapshot = Snapshot::decode(&data).unwrap();
+            assert_eq!(snapshot.fingerprint, fingerprint);
+            let mut env = env(&output);
+            let program = snapshot.restore(&mut env);
+            match run(&mut env, program, fingerprint, Some(3)).unwrap() {
+                Some(snapshot) => data = snapshot.encode(),
+                None => break,
+            }
+        }
+
+        assert_eq!(*expected.borrow(), b"12345");
+        assert_eq!(*output.borrow(), *expected.borrow());
+    }
+
+    #[test]
+    fn sharing_and_cycles() {
+        let bowl = Value::from_bowl(Bowl { noodles: vec![] });
+        if let Value::Bowl(inner) = &bowl {
+            // the bowl holds itself
+            inner.borrow_mut().noodles = parse("{[0; 1]}").unwrap().noodles;
+            inner.borrow_mut().noodles[0].expr = Expr::ValueExpr(bowl.clone());
+        }
+        let snapshot = Snapshot {
+            fingerprint: 7,
+            program: Bowl { noodles: vec![] },
+            mem: vec![(Number::one(), bowl.clone()), (Number::zero(), bowl)],
+            cursor: Some(Number::one()),
+        };
+
+        let decoded = Snapshot::decode(&snapshot.encode()).unwrap();
+
+        match (&decoded.mem[0].1, &decoded.mem[1].1) {
+            (Value::Bowl(a), Value::Bowl(b)) => {
+                assert!(Rc::ptr_eq(a, b));
+                match &a.borrow().noodles[0].expr {
+                    Expr::ValueExpr(Value::Bowl(c)) => assert!(Rc::ptr_eq(a, c)),
+                    _ => panic!("expected a bowl"),
+                }
+            }
+            _ => panic!("expected bowls"),
+        }
+        assert_eq!(decoded.cursor.unwrap().to_string(), "1");
+        assert_eq!(decoded.fingerprint, 7);
+    }
+}
@@ -0,0 +1,373 @@
+// Lexical view of Bibim source code. Unlike the parser, the scanner never
+// fails, so it also works on code that is still being edited.
+
+#[derive(Debug, Clone)]
+pub struct BowlNode {
+    pub start: usize,
+    pub end: usize,
+    pub noodles: Vec<NoodleNode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NoodleNode {
+    pub start: usize,
+    pub end: usize,
+    pub sep: Option<usize>,
+    pub bowls: Vec<BowlNode>,
+}
+
+impl NoodleNode {
+    pub fn nn_range(&self) -> (usize, usize) {
+        match self.sep {
+            Some(sep) => (self.start + 1, sep),
+            None => (self.start + 1, self.end),
+        }
+    }
+
+    pub fn expr_range(&self) -> Option<(usize, usize)> {
+        self.sep
+            .map(|sep| (sep + 1, self.end.saturating_sub(1).max(sep + 1)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Outline {
+    pub bowls: Vec<BowlNode>,
+    pub comments: Vec<Comment>,
+}
+
+enum Open {
+    Bowl(BowlNode),
+    Noodle(NoodleNode),
+}
+
+// Returns the end of the comment starting at `start`, if there is one.
+pub fn comment_end(code: &str, start: usize) -> Option<usize> {
+    let bytes = code.as_bytes();
+    if bytes.get(start) != Some(&b'~') {
+        return None;
+    }
+    let mut i = start + 1;
+    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    if bytes.get(i) != Some(&b'#') {
+        return None;
+    }
+    i += 1;
+    while i < bytes.len() {
+        if bytes[i] == b'#' {
+            let mut j = i + 1;
+            while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            if bytes.get(j) == Some(&b'~') {
+                return Some(j + 1);
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+pub fn scan(code: &str) -> Outline {
+    let bytes = code.as_bytes();
+    let mut outline = Outline::default();
+    let mut stack: Vec<Open> = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'~' => {
+                if let Some(end) = comment_end(code, i) {
+                    outline.comments.push(Comment { start: i, end });
+                    i = end;
+                    continue;
+                }
+            }
+            b'{' => stack.push(Open::Bowl(BowlNode {
+                start: i,
+                end: i + 1,
+                noodles: vec![],
+            })),
+            b'[' => stack.push(Open::Noodle(NoodleNode {
+                start: i,
+                end: i + 1,
+                sep: None,
+                bowls: vec![],
+            })),
+            b';' => {
+                if let Some(Open::Noodle(noodle)) = stack.last_mut() {
+                    if noodle.sep.is_none() {
+                        noodle.sep = Some(i);
+                    }
+                }
+            }
+            b'}' => {
+                if let Some(Open::Noodle(_)) = stack.last() {
+                    close_noodle(&mut stack, &mut outline, i);
+                }
+                if let Some(Open::Bowl(_)) = stack.last() {
+                    close_bowl(&mut stack, &mut outline, i + 1);
+                }
+            }
+            b']' => {
+                if let Some(Open::Noodle(_)) = stack.last() {
+                    close_noodle(&mut stack, &mut outline, i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    while let Some(open) = stack.last() {
+        match open {
+            Open::Bowl(_) => close_bowl(&mut stack, &mut outline, code.len()),
+            Open::Noodle(_) => close_noodle(&mut stack, &mut outline, code.len()),
+        }
+    }
+    outline
+}
+
+fn close_noodle(stack: &mut Vec<Open>, outline: &mut Outline, end: usize) {
+    if let Some(Open::Noodle(mut noodle)) = stack.pop() {
+        noodle.end = end;
+        match stack.last_mut() {
+            Some(Open::Bowl(bowl)) => bowl.noodles.push(noodle),
+            // a noodle outside of any bowl is kept as a bowl of its own
+            _ => add_bowl(
+                stack,
+                outline,
+                BowlNode {
+                    start: noodle.start,
+                    end,
+                    noodles: vec![noodle],
+                },
+            ),
+        }
+    }
+}
+
+fn close_bowl(stack: &mut Vec<Open>, outline: &mut Outline, end: usize) {
+    if let Some(Open::Bowl(mut bowl)) = stack.pop() {
+        bowl.end = end;
+        add_bowl(stack, outline, bowl);
+    }
+}
+
+fn add_bowl(stack: &mut [Open], outline: &mut Outline, bowl: BowlNode) {
+    match stack.last_mut() {
+        Some(Open::Noodle(noodle)) => noodle.bowls.push(bowl),
+        Some(Open::Bowl(parent)) => parent.noodles.push(NoodleNode {
+            start: bowl.start,
+            end: bowl.end,
+            sep: None,
+            bowls: vec![bowl],
+        }),
+        None => outline.bowls.push(bowl),
+    }
+}
+
+// Finds the innermost bowls and noodles containing `offset`, outermost first.
+pub fn path_at(outline: &Outline, offset: usize) -> (Vec<&BowlNode>, Vec<&NoodleNode>) {
+    let mut bowls = vec![];
+    let mut noodles = vec![];
+    let mut candidates: Vec<&BowlNode> = outline.bowls.iter().collect();
+    while let Some(bowl) = candidates
+        .iter()
+        .find(|bowl| bowl.start <= offset && offset < bowl.end)
+    {
+        bowls.push(*bowl);
+        match bowl
+            .noodles
+            .iter()
+            .find(|noodle| noodle.start <= offset && offset < noodle.end)
+        {
+            Some(noodle) => {
+                noodles.push(noodle);
+                candidates = noodle.bowls.iter().collect();
+            }
+            None => break,
+        }
+    }
+    (bowls, noodles)
+}
+
+// Finds the literals only the extended literals extension accepts: hexadecimal,
+// binary and decimal numbers, and the signs of negative numbers.
+pub fn extended_literals(code: &str) -> Vec<(usize, usize)> {
+    let bytes = code.as_bytes();
+    let mut literals = vec![];
+    // a minus right after an operand subtracts, anywhere else it is a sign
+    let mut is_after_operand = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c == b'~' {
+            if let Some(end) = comment_end(code, i) {
+                i = end;
+                continue;
+            }
+        }
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            let digits_from = |mut end: usize, is_digit: fn(&u8) -> bool| {
+                while end < bytes.len() && is_digit(&bytes[end]) {
+                    end += 1;
+                }
+                end
+            };
+            let prefix = bytes.get(i + 1).copied();
+            let first = bytes.get(i + 2);
+            if c == b'0' && prefix == Some(b'x') && first.is_some_and(u8::is_ascii_hexdigit) {
+                i = digits_from(i + 2, u8::is_ascii_hexdigit);
+                literals.push((start, i));
+            } else if c == b'0'
+                && prefix == Some(b'b')
+                && first.is_some_and(|b| *b == b'0' || *b == b'1')
+            {
+                i = digits_from(i + 2, |b| *b == b'0' || *b == b'1');
+                literals.push((start, i));
+            } else {
+                i = digits_from(i, u8::is_ascii_digit);
+                if bytes.get(i) == Some(&b'.') && bytes.get(i + 1).is_some_and(u8::is_ascii_digit) {
+                    i = digits_from(i + 1, u8::is_ascii_digit);
+                    literals.push((start, i));
+                } else {
+                    // plain numbers may contain whitespace, as in `1 000`
+                    i = digits_from(i, |b| b.is_ascii_digit() || b.is_ascii_whitespace());
+                }
+            }
+            is_after_operand = true;
+            continue;
+        }
+        if c == b'-' && !is_after_operand {
+            literals.push((i, i + 1));
+        }
+        is_after_operand = c == b')' || c == b'}';
+        i += 1;
+    }
+    literals
+}
+
+pub fn line_of(code: &str, offset: usize) -> usize {
+    code[..offset.min(code.len())].matches('\n').count() + 1
+}
+
+// Re-indents code by bracket nesting. Comment contents are left untouched.
+pub fn format_code(code: &str) -> String {
+    let bytes = code.as_bytes();
+    let mut result = String::new();
+    let mut indents: Vec<usize> = vec![];
+    let mut stack: Vec<usize> = vec![];
+    let mut line_start = 0;
+    let mut comment_until = 0;
+    while line_start <= code.len() {
+        let line_end = code[line_start..]
+            .find('\n')
+            .map(|i| line_start + i)
+            .unwrap_or(code.len());
+        let line = &code[line_start..line_end];
+        let text = line.trim();
+        let line_no = indents.len();
+        let indent = match text.as_bytes().first() {
+            Some(b'}') | Some(b']') => stack.last().map(|&open| indents[open]).unwrap_or(0),
+            _ => stack.last().map(|&open| indents[open] + 1).unwrap_or(0),
+        };
+        indents.push(indent);
+        if line_start < comment_until {
+            result.push_str(line.trim_end());
+        } else if !text.is_empty() {
+            result.push_str(&"    ".repeat(indent));
+            result.push_str(text);
+        }
+        let mut i = line_start.max(comment_until);
+        while i < line_end {
+            match bytes[i] {
+                b'~' => {
+                    if let Some(end) = comment_end(code, i) {
+                        comment_until = end;
+                        i = end;
+                        continue;
+                    }
+                }
+                b'{' | b'[' => stack.push(line_no),
+                b'}' | b']' => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        if line_end == code.len() {
+            break;
+        }
+        result.push('\n');
+        line_start = line_end + 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_nested_bowls() {
+        let code = "~# a #~ {[0; {[0; 72][1; 73]}] [1; 2]}";
+        let outline = scan(code);
+        assert_eq!(outline.comments.len(), 1);
+        assert_eq!(outline.bowls.len(), 1);
+        let bowl = &outline.bowls[0];
+        assert_eq!(
+            &code[bowl.start..bowl.end],
+            "{[0; {[0; 72][1; 73]}] [1; 2]}"
+        );
+        assert_eq!(bowl.noodles.len(), 2);
+        let noodle = &bowl.noodles[0];
+        let (start, end) = noodle.nn_range();
+        assert_eq!(&code[start..end], "0");
+        assert_eq!(noodle.bowls.len(), 1);
+        assert_eq!(noodle.bowls[0].noodles.len(), 2);
+
+        let offset = code.find("73").unwrap();
+        let (bowls, noodles) = path_at(&outline, offset);
+        assert_eq!(bowls.len(), 2);
+        assert_eq!(&code[noodles[1].start..noodles[1].end], "[1; 73]");
+    }
+
+    #[test]
+    fn scan_unclosed_code() {
+        let outline = scan("{[0; {[0; 1]");
+        assert_eq!(outline.bowls.len(), 1);
+        assert_eq!(outline.bowls[0].end, 12);
+    }
+
+    #[test]
+    fn find_extended_literals() {
+        let code = "{[0x48; -1 - 2][0b10; 0.25 - -3] ~# -0x1 #~ [1 000; (1)-2]}";
+        let literals: Vec<&str> = extended_literals(code)
+            .into_iter()
+            .map(|(start, end)| &code[start..end])
+            .collect();
+        assert_eq!(literals, ["0x48", "-", "0b10", "0.25", "-"]);
+    }
+
+    #[test]
+    fn format_nested_code() {
+        let code = "{\n[0; @:1 = {\n  [0; 72]   \n}]\n[1; { ~# a\n  b #~\n[1; 2]\n}:(1)]\n}";
+        assert_eq!(
+            format_code(code),
+            "{\n    [0; @:1 = {\n        [0; 72]\n    }]\n    [1; { ~# a\n  b #~\n        [1; 2]\n    }:(1)]\n}"
+        );
+    }
+}
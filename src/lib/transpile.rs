@@ -0,0 +1,200 @@
+// Compiles a bowl to a standalone C program. Every expression becomes a C
+// function evaluating its operands in the same order as `eval_expr`, on top of
+// the runtime in `runtime.c`.
+
+use std::{cell::RefCell, collections::HashMap, fmt::Write, rc::Rc};
+
+use crate::{
+    datatype::{Bowl, Expr, Value},
+    env::Extensions,
+};
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+struct Transpiler {
+    extensions: Extensions,
+    functions: String,
+    inits: String,
+    declarations: String,
+    count: usize,
+    // bowl literals are copied from one global bowl each
+    bowls: HashMap<*const (), String>,
+}
+
+pub fn to_c(bowl: &Bowl, extensions: &Extensions) -> String {
+    let mut transpiler = Transpiler {
+        extensions: *extensions,
+        functions: String::new(),
+        inits: String::new(),
+        declarations: String::new(),
+        count: 0,
+        bowls: HashMap::new(),
+    };
+    let program = transpiler.bowl(bowl, false);
+
+    let mut code = String::new();
+    code.push_str(RUNTIME);
+    code.push('\n');
+    code.push_str(&transpiler.declarations);
+    code.push('\n');
+    code.push_str(&transpiler.functions);
+    writeln!(code, "int main(void) {{").unwrap();
+    code.push_str(&transpiler.inits);
+    writeln!(code, "    run({});", program).unwrap();
+    writeln!(code, "    return 0;").unwrap();
+    writeln!(code, "}}").unwrap();
+    code
+}
+
+impl Transpiler {
+    fn name(&mut self, prefix: &str) -> String {
+        self.count += 1;
+        format!("{}_{}", prefix, self.count)
+    }
+
+    // Declares a global bowl holding the noodles of `bowl`, and returns its
+    // name. Bowl literals are copied on every evaluation, so the literals their
+    // noodles hold directly are held as values, to be copied along.
+    fn bowl(&mut self, bowl: &Bowl, is_literal: bool) -> String {
+        let name = self.name("bowl");
+        writeln!(self.declarations, "static Bowl *{};", name).unwrap();
+        let mut pushes = String::new();
+        for noodle in &bowl.noodles {
+            let (nn_fn, nn) = self.held(&noodle.nn_expr, is_literal);
+            let (fn_, value) = self.held(&noodle.expr, is_literal);
+            writeln!(
+                pushes,
+                "    bowl_push({}, (Noodle){{{}, {}, {}, {}}});",
+                name, nn_fn, fn_, nn, value
+            )
+            .unwrap();
+        }
+        writeln!(self.inits, "    {} = bowl_new();", name).unwrap();
+        self.inits.push_str(&pushes);
+        name
+    }
+
+    // Returns the global bowl for the bowl literal `bowl`.
+    fn literal(&mut self, bowl: &Rc<RefCell<Bowl>>) -> String {
+        let key = Rc::as_ptr(bowl) as *const ();
+        match self.bowls.get(&key) {
+            Some(name) => name.clone(),
+            None => {
+                let name = self.bowl(&bowl.borrow(), true);
+                self.bowls.insert(key, name.clone());
+                name
+            }
+        }
+    }
+
+    // Returns the thunk and the value a noodle keeps for `expr`.
+    fn held(&mut self, expr: &Expr, is_literal: bool) -> (String, String) {
+        match expr {
+            Expr::ValueExpr(Value::Bowl(bowl)) if is_literal => {
+                let name = self.literal(bowl);
+                ("NULL".to_string(), format!("retain(bowl_value({}))", name))
+            }
+            _ => (self.function(expr), "NULL_VALUE".to_string()),
+        }
+    }
+
+    // Compiles `expr` to a C function without arguments, and returns its name.
+    fn function(&mut self, expr: &Expr) -> String {
+        let name = self.name("expr");
+        let mut body = String::new();
+        let mut temps = 0;
+        let result = self.expr(expr, &mut body, &mut temps);
+        writeln!(self.declarations, "static Value {}(void);", name).unwrap();
+        writeln!(self.functions, "static Value {}(void) {{", name).unwrap();
+        self.functions.push_str(&body);
+        writeln!(self.functions, "    return {};", result).unwrap();
+        writeln!(self.functions, "}}\n").unwrap();
+        name
+    }
+
+    // Writes the statements computing `expr` into `body`, and returns the
+    // temporary holding the result.
+    fn expr(&mut self, expr: &Expr, body: &mut String, temps: &mut usize) -> String {
+        let value = match expr {
+            Expr::ValueExpr(Value::Number(number)) => {
+                let name = self.name("number");
+                writeln!(self.declarations, "static Num *{};", name).unwrap();
+                writeln!(
+                    self.inits,
+                    "    {} = num_parse(\"{}\", \"{}\");",
+                    name,
+                    number.numerator(),
+                    number.denominator()
+                )
+                .unwrap();
+                format!("retain(num_value({}))", name)
+            }
+            Expr::ValueExpr(Value::Bowl(bowl)) => {
+                let name = self.literal(bowl);
+                format!("bowl_copy({})", name)
+            }
+            Expr::ValueExpr(Value::Null) => "NULL_VALUE".to_string(),
+            Expr::BowlReadExpr(expr1, expr2) => {
+                let a = self.expr(expr1, body, temps);
+                let b = self.expr(expr2, body, temps);
+                format!("b_bowl_read({}, {})", a, b)
+            }
+            Expr::MemReadExpr(expr) => {
+                let a = self.expr(expr, body, temps);
+                format!("b_mem_read({})", a)
+            }
+            Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+                let a = self.expr(expr1, body, temps);
+                let b = self.expr(expr2, body, temps);
+                let c = self.expr(expr3, body, temps);
+                format!("b_bowl_write({}, {}, {})", a, b, c)
+            }
+            Expr::MemWriteExpr(expr1, expr2) => {
+                let a = self.expr(expr1, body, temps);
+                let b = self.expr(expr2, body, temps);
+                format!("b_mem_write({}, {})", a, b)
+            }
+            Expr::DenoFuncExpr(expr) => {
+                let a = self.expr(expr, body, temps);
+                format!("b_deno({})", a)
+            }
+            Expr::NotFuncExpr(expr) => {
+                let a = self.expr(expr, body, temps);
+                format!("b_not({})", a)
+            }
+            Expr::PlusFuncExpr(expr1, expr2) => self.binary("OP_ADD", expr1, expr2, body, temps),
+            Expr::MinusFuncExpr(expr1, expr2) => self.binary("OP_SUB", expr1, expr2, body, temps),
+            Expr::MulFuncExpr(expr1, expr2) => self.binary("OP_MUL", expr1, expr2, body, temps),
+            Expr::NumberSepFuncExpr(expr1, expr2) => {
+                self.binary("OP_DIV", expr1, expr2, body, temps)
+            }
+            Expr::AndFuncExpr(expr1, expr2) => self.binary("OP_AND", expr1, expr2, body, temps),
+            Expr::OrFuncExpr(expr1, expr2) => self.binary("OP_OR", expr1, expr2, body, temps),
+            Expr::EqFuncExpr(expr1, expr2) if self.extensions.structural_eq => {
+                let a = self.expr(expr1, body, temps);
+                let b = self.expr(expr2, body, temps);
+                format!("b_structural_eq({}, {})", a, b)
+            }
+            Expr::EqFuncExpr(expr1, expr2) => self.binary("OP_EQ", expr1, expr2, body, temps),
+            Expr::GtFuncExpr(expr1, expr2) => self.binary("OP_GT", expr1, expr2, body, temps),
+            Expr::LtFuncExpr(expr1, expr2) => self.binary("OP_LT", expr1, expr2, body, temps),
+        };
+        *temps += 1;
+        let temp = format!("t{}", temps);
+        writeln!(body, "    Value {} = {};", temp, value).unwrap();
+        temp
+    }
+
+    fn binary(
+        &mut self,
+        op: &str,
+        expr1: &Expr,
+        expr2: &Expr,
+        body: &mut String,
+        temps: &mut usize,
+    ) -> String {
+        let a = self.expr(expr1, body, temps);
+        let b = self.expr(expr2, body, temps);
+        format!("b_binary({}, {}, {})", op, a, b)
+    }
+}
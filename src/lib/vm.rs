@@ -0,0 +1,608 @@
+// A stack based virtual machine running the same programs as `eval`. Every
+// expression compiles to a postfix sequence of `Op`s, and the noodles of every
+// bowl literal are compiled ahead of time, so bowl reads evaluate stored
+// expressions without walking the tree. Bowls made from a literal run its code.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error, mem,
+    rc::{Rc, Weak},
+};
+
+use num_bigint::{BigUint, ToBigInt};
+
+use crate::{
+    datatype::{Bowl, Expr, Formatted, Noodle, Number, Value},
+    env::{Assignment, Env},
+    eval::{eval_expr, new_bowl, unshare, Place, Root, StoredEval},
+};
+
+#[derive(Debug, Clone)]
+pub enum Op {
+    Push(Value),
+    // a new bowl from the bowl literal
+    NewBowl(Rc<RefCell<Bowl>>),
+    BowlRead,
+    // a read of the bowl literal in place
+    LiteralRead(Rc<RefCell<Bowl>>),
+    MemRead,
+    // memory access with a constant noodle number
+    MemReadAt(Number),
+    BowlWrite,
+    // a write with value semantics to the bowl at a memory cell, when true, or
+    // a value, and the given number of noodle numbers read from there
+    PlaceWrite(bool, usize),
+    MemWrite,
+    MemWriteAt(Number),
+    Deno,
+    Not,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Eq,
+    Gt,
+    Lt,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Code {
+    pub nn: Vec<Op>,
+    pub expr: Vec<Op>,
+}
+
+#[derive(Debug, Default)]
+pub struct Program {
+    pub noodles: Vec<Code>,
+    // code of the noodles of each bowl literal, by bowl
+    pub bowls: HashMap<*const RefCell<Bowl>, Vec<Code>>,
+    pub assignment: Assignment,
+}
+
+pub fn compile(bowl: &Bowl, assignment: Assignment) -> Program {
+    let mut program = Program {
+        assignment,
+        ..Program::default()
+    };
+    program.noodles = program.compile_noodles(bowl);
+    program
+}
+
+impl Program {
+    fn compile_noodles(&mut self, bowl: &Bowl) -> Vec<Code> {
+        bowl.noodles
+            .iter()
+            .map(|noodle| {
+                let mut code = Code::default();
+                self.compile_expr(&noodle.nn_expr, &mut code.nn);
+                self.compile_expr(&noodle.expr, &mut code.expr);
+                code
+            })
+            .collect()
+    }
+
+    fn compile_expr(&mut self, expr: &Expr, ops: &mut Vec<Op>) {
+        match expr {
+            Expr::ValueExpr(Value::Bowl(bowl)) => {
+                self.compile_literal(bowl);
+                ops.push(Op::NewBowl(bowl.clone()));
+            }
+            Expr::ValueExpr(value) => ops.push(Op::Push(value.clone())),
+            Expr::BowlReadExpr(expr1, expr2) => {
+                if let Expr::ValueExpr(Value::Bowl(bowl)) = &**expr1 {
+                    self.compile_literal(bowl);
+                    self.compile_expr(expr2, ops);
+                    ops.push(Op::LiteralRead(bowl.clone()));
+                    return;
+                }
+                self.compile_expr(expr1, ops);
+                self.compile_expr(expr2, ops);
+                ops.push(Op::BowlRead);
+            }
+            Expr::MemReadExpr(expr) => match expr.as_ref() {
+                Expr::ValueExpr(Value::Number(number)) => {
+                    ops.push(Op::MemReadAt(*number.clone()));
+                }
+                _ => {
+                    self.compile_expr(expr, ops);
+                    ops.push(Op::MemRead);
+                }
+            },
+            Expr::BowlWriteExpr(expr1, expr2, expr3) if self.assignment == Assignment::Value => {
+                let (is_mem, len) = self.compile_place(expr1, ops);
+                self.compile_expr(expr2, ops);
+                self.compile_expr(expr3, ops);
+                ops.push(Op::PlaceWrite(is_mem, len));
+            }
+            Expr::BowlWriteExpr(expr1, expr2, expr3) => {
+                self.compile_expr(expr1, ops);
+                self.compile_expr(expr2, ops);
+                self.compile_expr(expr3, ops);
+                ops.push(Op::BowlWrite);
+            }
+            Expr::MemWriteExpr(expr1, expr2) => match expr1.as_ref() {
+                Expr::ValueExpr(Value::Number(number)) => {
+                    self.compile_expr(expr2, ops);
+                    ops.push(Op::MemWriteAt(*number.clone()));
+                }
+                _ => {
+                    self.compile_expr(expr1, ops);
+                    self.compile_expr(expr2, ops);
+                    ops.push(Op::MemWrite);
+                }
+            },
+            Expr::DenoFuncExpr(expr) => {
+                self.compile_expr(expr, ops);
+                ops.push(Op::Deno);
+            }
+            Expr::NotFuncExpr(expr) => {
+                self.compile_expr(expr, ops);
+                ops.push(Op::Not);
+            }
+            Expr::PlusFuncExpr(expr1, expr2) => self.compile_binary(expr1, expr2, Op::Add, ops),
+            Expr::MinusFuncExpr(expr1, expr2) => self.compile_binary(expr1, expr2, Op::Sub, ops),
+            Expr::MulFuncExpr(expr1, expr2) => self.compile_binary(expr1, expr2, Op::Mul, ops),
+            Expr::NumberSepFuncExpr(expr1, expr2) => {
+                self.compile_binary(expr1, expr2, Op::Div, ops)
+            }
+            Expr::AndFuncExpr(expr1, expr2) => self.compile_binary(expr1, expr2, Op::And, ops),
+            Expr::OrFuncExpr(expr1, expr2) => self.compile_binary(expr1, expr2, Op::Or, ops),
+            Expr::EqFuncExpr(expr1, expr2) => self.compile_binary(expr1, expr2, Op::Eq, ops),
+            Expr::GtFuncExpr(expr1, expr2) => self.compile_binary(expr1, expr2, Op::Gt, ops),
+            Expr::LtFuncExpr(expr1, expr2) => self.compile_binary(expr1, expr2, Op::Lt, ops),
+        }
+    }
+
+    // Compiles the parts of a place like `eval::place` evaluates them.
+    fn compile_place(&mut self, expr: &Expr, ops: &mut Vec<Op>) -> (bool, usize) {
+        match expr {
+            Expr::MemReadExpr(expr) => {
+                self.compile_expr(expr, ops);
+                (true, 0)
+            }
+            Expr::BowlReadExpr(expr1, expr2) => {
+                let (is_mem, len) = self.compile_place(expr1, ops);
+                self.compile_expr(expr2, ops);
+                (is_mem, len + 1)
+            }
+            _ => {
+                self.compile_expr(expr, ops);
+                (false, 0)
+            }
+        }
+    }
+
+    fn compile_literal(&mut self, bowl: &Rc<RefCell<Bowl>>) {
+        let key = Rc::as_ptr(bowl);
+        if !self.bowls.contains_key(&key) {
+            let codes = self.compile_noodles(&bowl.borrow());
+            self.bowls.insert(key, codes);
+        }
+    }
+
+    fn compile_binary(&mut self, expr1: &Expr, expr2: &Expr, op: Op, ops: &mut Vec<Op>) {
+        self.compile_expr(expr1, ops);
+        self.compile_expr(expr2, ops);
+        ops.push(op);
+    }
+}
+
+// Runs `bowl` like `eval::eval`.
+pub fn run(env: &mut Env, bowl: &Bowl) -> Result<bool, Box<dyn error::Error>> {
+    let program = compile(bowl, env.assignment);
+    let mut vm = Vm {
+        program: &program,
+        stack: vec![],
+        instances: HashMap::new(),
+        max_instances: MIN_INSTANCES,
+        zero: Number::zero(),
+        one: Number::one(),
+    };
+    loop {
+        env.sweep();
+        let mut next: Option<(usize, Box<Number>)> = None;
+        for (index, code) in program.noodles.iter().enumerate() {
+            let nn = vm.exec(env, &code.nn);
+            env.check_limit(&bowl.noodles[index])?;
+            if let Value::Number(number) = nn {
+                let is_nextable = match &env.cursor {
+                    Some(cursor) => *number > *cursor,
+                    None => true,
+                };
+                let is_min = match &next {
+                    Some((_, min)) => number < *min,
+                    None => true,
+                };
+                if is_nextable && is_min {
+                    next = Some((index, number));
+                }
+            }
+        }
+        let index = match next {
+            Some((index, _)) => index,
+            None => break,
+        };
+        let code = &program.noodles[index];
+        if env.is_debug {
+            println!(
+                "[=] noodle: {}",
+                Formatted(&bowl.noodles[index], env.number_format)
+            );
+        }
+        let new_cursor = vm.exec(env, &code.nn);
+        env.check_limit(&bowl.noodles[index])?;
+        if env.is_debug {
+            println!(
+                "[.] noodle number: {}",
+                Formatted(&new_cursor, env.number_format)
+            );
+        }
+        if let Value::Number(number) = new_cursor {
+            env.cursor = Some(*number);
+        } else {
+            panic!("Cannot set cursor to non-number value");
+        }
+        vm.exec(env, &code.expr);
+        env.check_limit(&bowl.noodles[index])?;
+        if env.is_debug {
+            println!(
+                "[.] cursor: {}",
+                env.cursor.as_ref().unwrap().format(env.number_format)
+            );
+            println!(
+                "[.] mem state: {}",
+                Formatted(&env.mem_to_bowl(), env.number_format)
+            );
+        }
+    }
+    Ok(true)
+}
+
+// the number of bowls made from literals to track before dropping the freed
+const MIN_INSTANCES: usize = 1024;
+
+struct Instance {
+    // keeps the address from being reused while the entry is there
+    bowl: Weak<RefCell<Bowl>>,
+    literal: *const RefCell<Bowl>,
+}
+
+struct Vm<'a> {
+    program: &'a Program,
+    stack: Vec<Value>,
+    // bowls made from literals, by address
+    instances: HashMap<*const RefCell<Bowl>, Instance>,
+    max_instances: usize,
+    zero: Number,
+    one: Number,
+}
+
+impl Vm<'_> {
+    fn exec(&mut self, env: &mut Env, ops: &[Op]) -> Value {
+        let base = self.stack.len();
+        for op in ops {
+            let value = match op {
+                Op::Push(value) => value.clone(),
+                Op::NewBowl(literal) => self.new_bowl(literal),
+                Op::BowlRead => {
+                    let nn = self.pop();
+                    match (self.pop(), nn) {
+                        (Value::Bowl(bowl), Value::Number(nn)) => self.read_bowl(env, &bowl, &nn),
+                        _ => Value::Null,
+                    }
+                }
+                Op::LiteralRead(literal) => match self.pop() {
+                    Value::Number(nn) => self.read_literal(env, literal, &nn),
+                    _ => Value::Null,
+                },
+                Op::MemRead => match self.pop() {
+                    Value::Number(nn) => self.read_mem(env, &nn),
+                    _ => Value::Null,
+                },
+                Op::MemReadAt(nn) => self.read_mem(env, nn),
+                Op::BowlWrite => {
+                    let value = self.pop();
+                    let nn = self.pop();
+                    if let (Value::Bowl(bowl), Value::Number(nn)) = (self.pop(), nn) {
+                        self.write_bowl(env, &bowl, nn, value);
+                    }
+                    Value::Null
+                }
+                Op::PlaceWrite(is_mem, len) => {
+                    let value = self.pop();
+                    let nn = self.pop();
+                    let path = self.stack.split_off(self.stack.len() - len);
+                    let root = self.pop();
+                    let place = Place {
+                        root: if *is_mem {
+                            Root::Mem(root)
+                        } else {
+                            Root::Temp(root)
+                        },
+                        path,
+                    };
+                    if let Value::Number(nn) = nn {
+                        if let Some(bowl) = unshare(env, place, self) {
+                            self.write_bowl(env, &bowl, nn, value);
+                        }
+                    }
+                    Value::Null
+                }
+                Op::MemWrite => {
+                    let value = self.pop();
+                    if let Value::Number(nn) = self.pop() {
+                        write_mem(env, &nn, value);
+                    }
+                    Value::Null
+                }
+                Op::MemWriteAt(nn) => {
+                    let value = self.pop();
+                    write_mem(env, nn, value);
+                    Value::Null
+                }
+                Op::Deno => match self.pop() {
+                    Value::Number(number) => Value::from_big_int(
+                        &number.denominator().to_bigint().unwrap(),
+                        &BigUint::from(1u32),
+                    ),
+                    _ => Value::Null,
+                },
+                Op::Not => match self.pop() {
+                    Value::Number(number) if *number == self.one => Value::new_zero(),
+                    _ => Value::new_one(),
+                },
+                Op::Add => self.arithmetic(env, |a, b| a + b),
+                Op::Sub => self.arithmetic(env, |a, b| a - b),
+                Op::Mul => self.arithmetic(env, |a, b| a * b),
+                Op::Div => self.arithmetic(env, |a, b| a / b),
+                Op::And => self.compare(|a, b| a.and(b)),
+                Op::Or => self.compare(|a, b| a.or(b)),
+                Op::Eq if env.extensions.structural_eq => {
+                    let value2 = self.pop();
+                    let value1 = self.pop();
+                    if value1.structural_eq(&value2, env) {
+                        Value::new_one()
+                    } else {
+                        Value::new_zero()
+                    }
+                }
+                Op::Eq => self.compare(|a, b| a == b),
+                Op::Gt => self.compare(|a, b| a > b),
+                Op::Lt => self.compare(|a, b| a < b),
+            };
+            if env.is_stopped() {
+                // a limit was reached, so the rest is not run
+                self.stack.truncate(base);
+                return Value::Null;
+            }
+            self.stack.push(value);
+        }
+        self.pop()
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn arithmetic(&mut self, env: &mut Env, op: fn(&Number, &Number) -> Number) -> Value {
+        let value2 = self.pop();
+        match (self.pop(), value2) {
+            (Value::Number(a), Value::Number(b)) => env.limit(op(&a, &b)),
+            _ => Value::Null,
+        }
+    }
+
+    fn compare(&mut self, op: fn(&Number, &Number) -> bool) -> Value {
+        let value2 = self.pop();
+        match (self.pop(), value2) {
+            (Value::Number(a), Value::Number(b)) if op(&a, &b) => Value::new_one(),
+            _ => Value::new_zero(),
+        }
+    }
+
+    fn new_bowl(&mut self, literal: &Rc<RefCell<Bowl>>) -> Value {
+        let instances = &mut self.instances;
+        let bowl = new_bowl(literal, &mut |literal, bowl| {
+            let instance = Instance {
+                bowl: Rc::downgrade(bowl),
+                literal: Rc::as_ptr(literal),
+            };
+            instances.insert(Rc::as_ptr(bowl), instance);
+        });
+        if self.instances.len() > self.max_instances {
+            self.instances
+                .retain(|_, instance| instance.bowl.strong_count() > 0);
+            self.max_instances = MIN_INSTANCES.max(2 * self.instances.len());
+        }
+        Value::Bowl(bowl)
+    }
+
+    // Evaluates a stored expression of `bowl`, with the compiled code when the
+    // noodle still holds its expression from the bowl literal. No borrow of the
+    // bowl is held meanwhile, so the expression may read and write it.
+    fn eval_noodle(
+        &mut self,
+        env: &mut Env,
+        bowl: &Rc<RefCell<Bowl>>,
+        index: usize,
+        is_nn: bool,
+    ) -> Value {
+        let program = self.program;
+        let key = match self.instances.get(&Rc::as_ptr(bowl)) {
+            Some(instance) => instance.literal,
+            None => Rc::as_ptr(bowl),
+        };
+        let code = program.bowls.get(&key).and_then(|codes| codes.get(index));
+        let expr = {
+            let noodle = &bowl.borrow().noodles[index];
+            match if is_nn { &noodle.nn_expr } else { &noodle.expr } {
+                Expr::ValueExpr(value) => return value.clone(),
+                _ if code.is_some() => None,
+                expr => Some(expr.clone()),
+            }
+        };
+        let value = match (code, expr) {
+            _ if !env.enter() => Value::Null,
+            (Some(code), _) if is_nn => self.exec(env, &code.nn),
+            (Some(code), _) => self.exec(env, &code.expr),
+            (None, Some(expr)) => eval_expr(env, &expr),
+            (None, None) => Value::Null,
+        };
+        env.leave();
+        value
+    }
+
+    fn read_bowl(&mut self, env: &mut Env, bowl: &Rc<RefCell<Bowl>>, nn: &Number) -> Value {
+        match self.find(env, bowl, nn) {
+            Some(index) => self.eval_noodle(env, bowl, index, false),
+            None => Value::Null,
+        }
+    }
+
+    // Reads the bowl literal as a new bowl from it would be read, running the
+    // code of the noodle found.
+    fn read_literal(&mut self, env: &mut Env, literal: &Rc<RefCell<Bowl>>, nn: &Number) -> Value {
+        let program = self.program;
+        let index = match self.find(env, literal, nn) {
+            Some(index) => index,
+            None => return Value::Null,
+        };
+        let value = if env.enter() {
+            self.exec(env, &program.bowls[&Rc::as_ptr(literal)][index].expr)
+        } else {
+            Value::Null
+        };
+        env.leave();
+        value
+    }
+
+    fn write_bowl(
+        &mut self,
+        env: &mut Env,
+        bowl: &Rc<RefCell<Bowl>>,
+        nn: Box<Number>,
+        value: Value,
+    ) {
+        let index = self.find(env, bowl, &nn);
+        // the noodle may not have been found, only given up on
+        if env.is_stopped() {
+            return;
+        }
+        let mut inner = bowl.borrow_mut();
+        match index {
+            Some(index) => {
+                let overwritten =
+                    mem::replace(&mut inner.noodles[index].expr, Expr::ValueExpr(value));
+                drop(inner);
+                if let Expr::ValueExpr(overwritten) = overwritten {
+                    env.discard(overwritten);
+                }
+            }
+            None => inner.noodles.push(Noodle {
+                nn_expr: Expr::ValueExpr(Value::Number(nn)),
+                expr: Expr::ValueExpr(value),
+                line: None,
+            }),
+        }
+    }
+
+    // The index of the first noodle of `bowl` with the number `nn`, as
+    // `Bowl::find` finds it.
+    fn find(&mut self, env: &mut Env, bowl: &Rc<RefCell<Bowl>>, nn: &Number) -> Option<usize> {
+        let mut index = 0;
+        while index < bowl.borrow().noodles.len() {
+            // noodle numbers already evaluated are compared without a copy
+            let is_value = match &bowl.borrow().noodles[index].nn_expr {
+                Expr::ValueExpr(value) => {
+                    Some(matches!(value, Value::Number(number) if **number == *nn))
+                }
+                _ => None,
+            };
+            let is_match = match is_value {
+                Some(is_match) => is_match,
+                None => matches!(self.eval_noodle(env, bowl, index, true),
+                    Value::Number(number) if *number == *nn),
+            };
+            if is_match {
+                return Some(index);
+            }
+            index += 1;
+        }
+        None
+    }
+
+    fn read_mem(&mut self, env: &mut Env, nn: &Number) -> Value {
+        if *nn == self.zero {
+            return match &env.cursor {
+                Some(cursor) => Value::from_number(cursor),
+                None => Value::Null,
+            };
+        }
+        if *nn == self.one {
+            return Value::from_bowl(env.read_io());
+        }
+        for (cell, value) in &env.mem {
+            if cell == nn {
+                return value.clone();
+            }
+        }
+        Value::Null
+    }
+}
+
+impl StoredEval for Vm<'_> {
+    fn eval_stored(
+        &mut self,
+        env: &mut Env,
+        bowl: &Rc<RefCell<Bowl>>,
+        index: usize,
+        is_nn: bool,
+    ) -> Value {
+        self.eval_noodle(env, bowl, index, is_nn)
+    }
+}
+
+fn write_mem(env: &mut Env, nn: &Number, value: Value) {
+    if *nn == Number::one() {
+        if let Value::Bowl(bowl) = value {
+            env.write_io(&bowl);
+        }
+        return;
+    }
+    match env.mem.iter_mut().find(|cell| cell.0 == *nn) {
+        Some(cell) => {
+            let overwritten = mem::replace(&mut cell.1, value);
+            env.discard(overwritten);
+        }
+        None => env.mem.push((nn.clone(), value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn compile_constant_memory_access() {
+        let bowl = parse("{[0; @:2 = @:3 + 1]}").unwrap();
+        let program = compile(&bowl, Assignment::Reference);
+
+        let ops = &program.noodles[0].expr;
+        assert!(matches!(
+            ops.as_slice(),
+            [Op::MemReadAt(_), Op::Push(_), Op::Add, Op::MemWriteAt(_)]
+        ));
+    }
+
+    #[test]
+    fn compile_bowl_literals() {
+        let bowl = parse("{[0; {[0; @:2][1; {[2; 3]}:2]}:1]}").unwrap();
+        let program = compile(&bowl, Assignment::Reference);
+
+        assert_eq!(program.bowls.len(), 2);
+    }
+}
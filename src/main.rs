@@ -1,34 +1,397 @@
 use std::{
     env, fs,
-    io::{self, BufRead, Read, Write},
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    net::TcpListener,
+    path::Path,
+    process,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use bibim::{env::Env, run};
+use bibim::{
+    asm, binary, bundle,
+    compile::compile,
+    coverage::Coverage,
+    datatype::{Bowl, NumberFormat},
+    doc,
+    env::{Assignment, Engine, Env, Extensions},
+    error::ParseError,
+    lsp,
+    optimize::optimize,
+    parse_with,
+    playground::{self, Budget},
+    profile::Profiler,
+    run, run_bowl,
+    snapshot::{self, Snapshot},
+    syntax, transpile,
+};
 
 fn main() {
-    if let Some(file_path) = env::args().nth(1) {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("lsp") {
+        let extensions = match take_extensions(&mut args) {
+            Some(extensions) => extensions,
+            None => return,
+        };
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        if let Err(e) = lsp::serve(stdin.lock(), stdout.lock(), &extensions) {
+            eprintln!("Error: {}", e);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("doc") {
+        let is_html = take_flag(&mut args, "--html");
+        let extensions = match take_extensions(&mut args) {
+            Some(extensions) => extensions,
+            None => return,
+        };
+        match args.get(1) {
+            Some(file_path) => {
+                let code = fs::read_to_string(file_path).unwrap();
+                let (routines, lints) = doc::document(&code, &extensions);
+                for lint in lints {
+                    eprintln!(
+                        "warning: {}:{}: {}",
+                        file_path,
+                        syntax::line_of(&code, lint.start),
+                        lint.message
+                    );
+                }
+                if is_html {
+                    print!("{}", doc::to_html(&routines));
+                } else {
+                    print!("{}", doc::to_markdown(&routines));
+                }
+            }
+            None => eprintln!("Usage: rustbibim doc [--html] [--extension <names>] <file>"),
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("asm") {
+        let output_path = take_option(&mut args, "-o");
+        let extensions = match take_extensions(&mut args) {
+            Some(extensions) => extensions,
+            None => return,
+        };
+        match args.get(1) {
+            Some(file_path) => {
+                let source = fs::read_to_string(file_path).unwrap();
+                match asm::assemble(&source, &extensions) {
+                    Ok(assembly) => match output_path {
+                        Some(output_path) => {
+                            fs::write(&output_path, &assembly.code).unwrap();
+                            fs::write(
+                                format!("{}.map", output_path),
+                                assembly.source_map_json(file_path),
+                            )
+                            .unwrap();
+                        }
+                        None => print!("{}", assembly.code),
+                    },
+                    Err(e) => eprintln!("Error: {}:{}", file_path, e),
+                }
+            }
+            None => eprintln!("Usage: rustbibim asm [--extension <names>] <file> [-o <output>]"),
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("compile") {
+        let emit = take_option(&mut args, "--emit").unwrap_or_else(|| "bibim".to_string());
+        let extensions = match take_extensions(&mut args) {
+            Some(extensions) => extensions,
+            None => return,
+        };
+        match args.get(1) {
+            Some(file_path) => {
+                let bowl = match load_bowl(file_path, &extensions) {
+                    Some(bowl) => bowl,
+                    None => return,
+                };
+                match emit.as_str() {
+                    "bibim" => println!("{}", bowl),
+                    "c" => print!("{}", transpile::to_c(&bowl, &extensions)),
+                    _ => eprintln!("Error: unknown output format `{}`", emit),
+                }
+            }
+            None => {
+                eprintln!("Usage: rustbibim compile [--emit bibim|c] [--extension <names>] <file>")
+            }
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("ast") {
+        let format = take_option(&mut args, "--format").unwrap_or_else(|| "json".to_string());
+        let output_path = take_option(&mut args, "-o");
+        let extensions = match take_extensions(&mut args) {
+            Some(extensions) => extensions,
+            None => return,
+        };
+        match args.get(1) {
+            Some(file_path) => {
+                let bowl = match load_bowl(file_path, &extensions) {
+                    Some(bowl) => bowl,
+                    None => return,
+                };
+                let data = match format.as_str() {
+                    #[cfg(feature = "serde")]
+                    "json" => serde_json::to_vec_pretty(&bowl).unwrap(),
+                    #[cfg(not(feature = "serde"))]
+                    "json" => {
+                        eprintln!("Error: JSON output needs the `serde` feature");
+                        return;
+                    }
+                    "binary" => binary::encode(&bowl),
+                    _ => {
+                        eprintln!("Error: unknown AST format `{}`", format);
+                        return;
+                    }
+                };
+                match output_path {
+                    Some(output_path) => fs::write(output_path, data).unwrap(),
+                    None => io::stdout().write_all(&data).unwrap(),
+                }
+            }
+            None => eprintln!("Usage: rustbibim ast [--format json|binary] [--extension <names>] <file> [-o <output>]"),
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("serve") {
+        let port: u16 = match take_option(&mut args, "--port").map(|port| port.parse()) {
+            None => 8080,
+            Some(Ok(port)) => port,
+            Some(Err(_)) => {
+                eprintln!("Error: `--port` expects a port number");
+                return;
+            }
+        };
+        let mut budget = Budget::default();
+        match take_option(&mut args, "--steps").map(|steps| steps.parse()) {
+            None => {}
+            Some(Ok(steps)) => budget.steps = steps,
+            Some(Err(_)) => {
+                eprintln!("Error: `--steps` expects a number of noodles");
+                return;
+            }
+        }
+        match take_option(&mut args, "--timeout").map(|timeout| timeout.parse()) {
+            None => {}
+            Some(Ok(timeout)) => budget.time = Duration::from_millis(timeout),
+            Some(Err(_)) => {
+                eprintln!("Error: `--timeout` expects a number of milliseconds");
+                return;
+            }
+        }
+        match take_option(&mut args, "--max-bits").map(|bits| bits.parse()) {
+            None => {}
+            Some(Ok(bits)) => budget.max_bits = bits,
+            Some(Err(_)) => {
+                eprintln!("Error: `--max-bits` expects a number of bits");
+                return;
+            }
+        }
+        let listener = match TcpListener::bind(("127.0.0.1", port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return;
+            }
+        };
+        eprintln!("Serving the playground on http://127.0.0.1:{}", port);
+        if let Err(e) = playground::serve(&listener, &budget) {
+            eprintln!("Error: {}", e);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("bundle") {
+        let output_path = take_option(&mut args, "-o");
+        let extensions = match take_extensions(&mut args) {
+            Some(extensions) => extensions,
+            None => return,
+        };
+        match args.get(1) {
+            Some(file_path) => {
+                let bowl = match load_program(file_path, &extensions) {
+                    Some(bowl) => bowl,
+                    None => return,
+                };
+                let output_path = output_path.unwrap_or_else(|| {
+                    let stem = Path::new(file_path).file_stem().unwrap();
+                    stem.to_string_lossy().to_string()
+                });
+                let executable = fs::read(env::current_exe().unwrap()).unwrap();
+                fs::write(
+                    &output_path,
+                    bundle::bundle(&executable, &bowl, &extensions),
+                )
+                .unwrap();
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&output_path, fs::Permissions::from_mode(0o755)).unwrap();
+                }
+            }
+            None => eprintln!("Usage: rustbibim bundle [--extension <names>] <file> [-o <output>]"),
+        }
+        return;
+    }
+    let is_optimize = take_flag(&mut args, "--optimize");
+    let engine = match take_option(&mut args, "--engine").as_deref() {
+        None | Some("tree") => Engine::Tree,
+        Some("vm") => Engine::Vm,
+        #[cfg(feature = "jit")]
+        Some("jit") => Engine::Jit,
+        Some(engine) => {
+            eprintln!("Error: unknown engine `{}`", engine);
+            return;
+        }
+    };
+    let extensions = match take_extensions(&mut args) {
+        Some(extensions) => extensions,
+        None => return,
+    };
+    let assignment = match take_option(&mut args, "--assignment").as_deref() {
+        None | Some("reference") => Assignment::Reference,
+        Some("value") => Assignment::Value,
+        Some(assignment) => {
+            eprintln!("Error: unknown assignment `{}`", assignment);
+            return;
+        }
+    };
+    let is_debug = take_flag(&mut args, "--debug");
+    let mut number_format = match take_option(&mut args, "--format").map(|format| format.parse()) {
+        None => NumberFormat::Fraction,
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let is_profile = take_flag(&mut args, "--profile");
+    let coverage_path = take_option(&mut args, "--coverage");
+    let steps = match take_option(&mut args, "--steps").map(|steps| steps.parse()) {
+        None => None,
+        Some(Ok(steps)) => Some(steps),
+        Some(Err(_)) => {
+            eprintln!("Error: `--steps` expects a number of noodles");
+            return;
+        }
+    };
+    let max_bits = match take_option(&mut args, "--max-bits").map(|bits| bits.parse()) {
+        None => None,
+        Some(Ok(bits)) => Some(bits),
+        Some(Err(_)) => {
+            eprintln!("Error: `--max-bits` expects a number of bits");
+            return;
+        }
+    };
+    let snapshot_path = take_option(&mut args, "--snapshot");
+    let is_resume = args.first().map(String::as_str) == Some("resume");
+    if is_resume {
+        args.remove(0);
+        if args.is_empty() {
+            eprintln!(
+                "Usage: rustbibim resume [--steps <n>] [--snapshot <output>] <snapshot> [<file>]"
+            );
+            return;
+        }
+    }
+    if (is_resume || steps.is_some()) && engine != Engine::Tree {
+        // snapshots are taken from the tree walking engine
+        eprintln!("Error: `--steps` and `resume` only run with `--engine tree`");
+        return;
+    }
+    if let Some(file_path) = args.first() {
         let input = Arc::new(Mutex::new(io::stdin()));
         let output = Arc::new(Mutex::new(io::stdout()));
-        let mut env = Env {
-            cursor: None,
-            mem: vec![],
-            is_debug: false,
-            on_read_io: Box::new(|| {
+        let mut env = Env::new(
+            || {
                 let mut buffer = Vec::new();
                 input.lock().unwrap().read_to_end(&mut buffer).unwrap();
                 buffer
-            }),
-            on_write_io: Box::new(|data| {
-                output.lock().unwrap().write(data.as_slice()).unwrap();
+            },
+            |data| {
+                output.lock().unwrap().write_all(data.as_slice()).unwrap();
                 output.lock().unwrap().flush().ok();
-            }),
+            },
+        )
+        .with_debug(is_debug)
+        .with_number_format(number_format)
+        .with_optimize(is_optimize)
+        .with_engine(engine)
+        .with_extensions(extensions)
+        .with_assignment(assignment)
+        .with_max_bits(max_bits)
+        .with_profiler(if is_profile {
+            Some(Profiler::default())
+        } else {
+            None
+        })
+        .with_coverage(coverage_path.as_ref().map(|_| Coverage::default()));
+        let (mut bowl, fingerprint) = if is_resume {
+            let snapshot = match Snapshot::decode(&fs::read(file_path).unwrap()) {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    println!("Error: {}:{}", file_path, e);
+                    return;
+                }
+            };
+            if let Some(program_path) = args.get(1) {
+                match load_program(program_path, &extensions) {
+                    Some(program) if snapshot::fingerprint(&program) == snapshot.fingerprint => {}
+                    Some(_) => {
+                        println!("Error: {} was not taken from {}", file_path, program_path);
+                        return;
+                    }
+                    None => return,
+                }
+            }
+            let fingerprint = snapshot.fingerprint;
+            (snapshot.restore(&mut env), fingerprint)
+        } else {
+            match load_program(file_path, &extensions) {
+                Some(bowl) => {
+                    let fingerprint = snapshot::fingerprint(&bowl);
+                    (bowl, fingerprint)
+                }
+                None => return,
+            }
+        };
+        let result = if is_resume || steps.is_some() {
+            if env.is_optimize && !is_resume {
+                optimize(&mut bowl, env.max_bits);
+            }
+            if let Some(coverage) = env.coverage.as_mut() {
+                coverage.register(&bowl);
+            }
+            match snapshot::run(&mut env, bowl, fingerprint, steps) {
+                Ok(Some(snapshot)) => {
+                    match &snapshot_path {
+                        Some(snapshot_path) => fs::write(snapshot_path, snapshot.encode()).unwrap(),
+                        None => eprintln!("Stopped after {} steps", steps.unwrap()),
+                    }
+                    Ok(())
+                }
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            }
+        } else {
+            run_bowl(bowl, &mut env)
         };
-        let code = fs::read_to_string(file_path).unwrap();
-        match run(code, &mut env) {
+        match result {
             Ok(_) => {}
             Err(e) => println!("Error: {}", e),
         }
+        if let Some(profiler) = &env.profiler {
+            eprint!("{}", profiler);
+        }
+        if let (Some(coverage), Some(coverage_path)) = (&env.coverage, &coverage_path) {
+            fs::write(coverage_path, coverage.to_lcov(file_path)).unwrap();
+        }
+    } else if let Some((bowl, extensions)) = bundled_program() {
+        // only looked for without a program to run, so other runs never read
+        // the executable
+        run_bundled(bowl, extensions);
     } else {
         loop {
             print!(">>> ");
@@ -36,27 +399,180 @@ fn main() {
             let input = Arc::new(Mutex::new(io::stdin()));
             let output = Arc::new(Mutex::new(io::stdout()));
             let code = input.lock().unwrap().lock().lines().next();
-            let mut env = Env {
-                cursor: None,
-                mem: vec![],
-                is_debug: false,
-                on_read_io: Box::new(|| {
+            let mut env = Env::new(
+                || {
                     let mut buffer = Vec::new();
                     input.lock().unwrap().read_to_end(&mut buffer).unwrap();
                     buffer
-                }),
-                on_write_io: Box::new(|data| {
-                    output.lock().unwrap().write(data.as_slice()).unwrap();
+                },
+                |data| {
+                    output.lock().unwrap().write_all(data.as_slice()).unwrap();
                     output.lock().unwrap().flush().ok();
-                }),
-            };
-            match code {
-                Some(Ok(ref l)) => match run(l.to_string(), &mut env) {
-                    Ok(_) => {}
-                    Err(e) => println!("Error: {}", e),
                 },
+            )
+            .with_debug(is_debug)
+            .with_number_format(number_format)
+            .with_optimize(is_optimize)
+            .with_engine(engine)
+            .with_extensions(extensions)
+            .with_assignment(assignment)
+            .with_max_bits(max_bits);
+            match code {
+                // `:format <format>` shows the numbers of debug traces in that format
+                Some(Ok(ref l)) if l.starts_with(":format") => {
+                    match l[":format".len()..].trim().parse() {
+                        Ok(format) => number_format = format,
+                        Err(e) => println!("Error: {}", e),
+                    }
+                }
+                Some(Ok(ref l)) => {
+                    if let Err(e) = run(l.to_string(), &mut env) {
+                        println!("Error: {}", e);
+                    }
+                    // every line runs on a new environment
+                    env.collect();
+                }
                 _ => break,
             }
         }
     }
 }
+
+// The program bundled into this executable and its extensions, if any.
+fn bundled_program() -> Option<(Bowl, Extensions)> {
+    let mut file = fs::File::open(env::current_exe().ok()?).ok()?;
+    let mut trailer = [0; bundle::TRAILER_LEN];
+    file.seek(SeekFrom::End(-(bundle::TRAILER_LEN as i64)))
+        .ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    let (len, extensions) = bundle::read_trailer(&trailer)?;
+    let mut data = vec![0; len];
+    file.seek(SeekFrom::End(-((bundle::TRAILER_LEN + len) as i64)))
+        .ok()?;
+    file.read_exact(&mut data).ok()?;
+    match binary::decode(&data) {
+        Ok(bowl) => Some((bowl, extensions)),
+        Err(e) => {
+            eprintln!("Error: bundled program: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Runs a bundled program with stdio wired to `@:1`.
+fn run_bundled(bowl: Bowl, extensions: Extensions) {
+    let mut env = Env::new(
+        || {
+            let mut buffer = Vec::new();
+            io::stdin().read_to_end(&mut buffer).unwrap();
+            buffer
+        },
+        |data| {
+            let mut stdout = io::stdout();
+            stdout.write_all(data.as_slice()).unwrap();
+            stdout.flush().ok();
+        },
+    )
+    .with_extensions(extensions);
+    if let Err(e) = run_bowl(bowl, &mut env) {
+        println!("Error: {}", e);
+    }
+}
+
+// Loads a program to run, from an encoded program, Bibim assembly, a Bibim
+// script or Bibim code, reporting errors.
+fn load_program(file_path: &str, extensions: &Extensions) -> Option<Bowl> {
+    let data = fs::read(file_path).unwrap();
+    if binary::is_encoded(&data) {
+        return match binary::decode(&data) {
+            Ok(bowl) => Some(bowl),
+            Err(e) => {
+                println!("Error: {}:{}", file_path, e);
+                None
+            }
+        };
+    }
+    let mut code = String::from_utf8(data).unwrap();
+    if file_path.ends_with(".bibima") {
+        code = match asm::assemble(&code, extensions) {
+            Ok(assembly) => assembly.code,
+            Err(e) => {
+                println!("Error: {}:{}", file_path, e);
+                return None;
+            }
+        };
+    }
+    if file_path.ends_with(".bibims") {
+        return match compile(&code) {
+            Ok(bowl) => Some(bowl),
+            Err(e) => {
+                println!("Error: {}:{}", file_path, e);
+                None
+            }
+        };
+    }
+    match parse_with(&code, extensions) {
+        Ok(bowl) => Some(bowl),
+        Err(errs) => {
+            for e in errs {
+                println!("{}", e.message);
+            }
+            println!("Error: {}", ParseError);
+            None
+        }
+    }
+}
+
+// Parses a Bibim file, or compiles a Bibim script, reporting errors.
+fn load_bowl(file_path: &str, extensions: &Extensions) -> Option<Bowl> {
+    let source = fs::read_to_string(file_path).unwrap();
+    if file_path.ends_with(".bibims") {
+        match compile(&source) {
+            Ok(bowl) => Some(bowl),
+            Err(e) => {
+                eprintln!("Error: {}:{}", file_path, e);
+                None
+            }
+        }
+    } else {
+        match parse_with(&source, extensions) {
+            Ok(bowl) => Some(bowl),
+            Err(errs) => {
+                for e in errs {
+                    eprintln!("{}", e.message);
+                }
+                None
+            }
+        }
+    }
+}
+
+// Takes `--extension <name>,<name>...`, reporting unknown extensions.
+fn take_extensions(args: &mut Vec<String>) -> Option<Extensions> {
+    let mut extensions = Extensions::default();
+    if let Some(names) = take_option(args, "--extension") {
+        for name in names.split(',') {
+            if !extensions.enable(name) {
+                eprintln!("Error: unknown extension `{}`", name);
+                return None;
+            }
+        }
+    }
+    Some(extensions)
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
+}
+
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    args.remove(index);
+    if index < args.len() {
+        Some(args.remove(index))
+    } else {
+        None
+    }
+}
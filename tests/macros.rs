@@ -0,0 +1,42 @@
+use std::cell::RefCell;
+
+use bibim::{
+    datatype::{Bowl, Expr, Number, Value},
+    env::Env,
+    run_bowl,
+};
+use bibim_macros::bibim;
+
+// Runs `bowl`, returning what it writes.
+fn output(bowl: Bowl) -> Vec<u8> {
+    let output = RefCell::new(vec![]);
+    let mut env = Env::new(Vec::new, |data| output.borrow_mut().extend(data));
+    run_bowl(bowl, &mut env).unwrap();
+    drop(env);
+    output.into_inner()
+}
+
+#[test]
+fn expand_string_literal() {
+    let bowl = bibim!("{[0; @:1 = {[0; 72][1; 105]}] ~# says hi #~}");
+
+    assert_eq!(output(bowl), b"Hi");
+}
+
+#[test]
+fn expand_tokens() {
+    let bowl = bibim! { {[0; @:2 = 1/3][1; @:1 = {[0; 48 + ((@:2 * 3) ?= 1)]}]} };
+
+    assert_eq!(output(bowl), b"1");
+}
+
+#[test]
+fn expand_large_numbers() {
+    let bowl = bibim!("{[0; 340282366920938463463374607431768211456]}");
+
+    let expected: Number = "340282366920938463463374607431768211456".parse().unwrap();
+    match &bowl.noodles[0].expr {
+        Expr::ValueExpr(Value::Number(number)) => assert_eq!(**number, expected),
+        expr => panic!("{} is not a number", expr),
+    }
+}